@@ -22,7 +22,15 @@ fn main() {
         Ok(client) => client,
         Err(err) => panic!("can't open window system client: {}", err),
     };
-    let window = match client.window().build(()) {
+    match client.monitors() {
+        Ok(monitors) => {
+            for monitor in monitors {
+                info!("{:?}", monitor);
+            }
+        },
+        Err(err) => error!("can't query monitors: {}", err),
+    }
+    let window = match client.window().with_title("axis-window event debugger").build(()) {
         Ok(window) => window,
         Err(err) => panic!("can't create main window: {}", err),
     };
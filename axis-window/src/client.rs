@@ -6,47 +6,157 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
+use std::time::Duration;
+
+use crate::device::Devices;
 use crate::error::{Error, Result};
-use crate::event::{Event, MainLoop};
-use crate::pixel_format::{IPixelFormat, PixelFormat};
-use crate::window::{IWindow, IWindowBuilder, Window, WindowBuilder};
+use crate::event::{ControlFlow, Event, MainLoop};
+use crate::monitor::Monitors;
+use crate::pixel_format::{IPixelFormat, PixelFormat, PixelFormatRequirements};
+use crate::tray::{Icon, ITrayIcon, TrayIcon};
+use crate::window::{IWindow, IWindowBuilder, Theme, Window, WindowBuilder};
 
 /// Interface for window system clients.
 pub trait IClient {
     type PixelFormat: IPixelFormat;
+    type TrayIcon: ITrayIcon<Client = Self>;
     type Window: IWindow<Client = Self>;
     type WindowBuilder: IWindowBuilder<Client = Self>;
     type WindowId: 'static + Clone;
 
+    /// Chooses the pixel format which best satisfies `requirements`, for use with
+    /// [with_pixel_format](IWindowBuilder::with_pixel_format). Fails if no format satisfies
+    /// them.
+    fn choose_pixel_format(&self, requirements: &PixelFormatRequirements)
+        -> Result<Self::PixelFormat>;
+
+    /// Adds an icon to the system tray. Tray events for the icon carry `id`.
+    fn create_tray_icon(&self, id: Self::WindowId, icon: &Icon) -> Result<Self::TrayIcon>;
+
     /// Returns the default pixel format.
     fn default_pixel_format(&self) -> Self::PixelFormat;
 
-    /// Runs the main loop.
-    fn run<F: Fn(Event<Self::WindowId>)>(&self, main_loop: &MainLoop, f: &F) -> Result<()>;
+    /// Returns an iterator over the input devices. Devices which are added or removed later are
+    /// reported by [DeviceAdded](Event::DeviceAdded) and [DeviceRemoved](Event::DeviceRemoved)
+    /// events.
+    fn devices(&self) -> Result<Devices>;
+
+    /// Returns a handle to the live window with the specified ID, if there is one. See
+    /// [windows](IClient::windows).
+    fn find_window(&self, id: &Self::WindowId) -> Option<Self::Window>
+        where Self::WindowId: PartialEq
+    {
+        self.windows().into_iter().find(|window| window.id() == id)
+    }
+
+    /// Prevents the screensaver from starting and the display from going to sleep while
+    /// `inhibit` is true, such as while media is playing.
+    fn inhibit_screensaver(&self, inhibit: bool) -> Result<()>;
+
+    /// Returns an iterator over the connected monitors.
+    fn monitors(&self) -> Result<Monitors>;
+
+    /// Handles pending window system events without blocking and returns the resulting events.
+    /// This is an alternative to [run](IClient::run) for applications which own their main loop.
+    fn poll_events(&self) -> Result<Vec<Event<Self::WindowId>>>;
+
+    /// Runs the main loop. The callback may return `()` or a [ControlFlow].
+    fn run<F, R>(&self, main_loop: &MainLoop, f: &F) -> Result<()>
+        where F: Fn(Event<Self::WindowId>) -> R, R: Into<ControlFlow>;
+
+    /// Sets the application ID, which the desktop uses to group windows in the taskbar and to
+    /// match them with the application's desktop entry. On X11, this applies to the client's
+    /// windows, but window managers may ignore changes to windows which are already visible. On
+    /// Windows, this applies to the whole process and should be called before any windows are
+    /// created.
+    fn set_app_id(&self, app_id: &str) -> Result<()>;
+
+    /// Returns the system's preferred theme, or `None` if it can't be determined.
+    fn theme(&self) -> Option<Theme>;
+
+    /// Waits until window system events are available or `timeout` elapses, then behaves like
+    /// [poll_events](IClient::poll_events). The result may be empty.
+    fn wait_events(&self, timeout: Option<Duration>) -> Result<Vec<Event<Self::WindowId>>>;
 
     /// Returns a new window builder.
     fn window(&self) -> Self::WindowBuilder;
+
+    /// Returns handles to the client's live windows, in no particular order. Dropping a handle
+    /// doesn't destroy its window.
+    fn windows(&self) -> Vec<Self::Window>;
 }
 
 /// Internal interface for [Client].
 pub trait IClientObject<W: 'static + Clone>: 'static {
+    fn choose_pixel_format(&self, requirements: &PixelFormatRequirements) -> Result<PixelFormat>;
+    fn create_tray_icon(&self, id: W, icon: &Icon) -> Result<TrayIcon<W>>;
     fn default_pixel_format(&self) -> PixelFormat;
-    fn run(&self, main_loop: &MainLoop, f: &dyn Fn(Event<W>)) -> Result<()>;
+    fn devices(&self) -> Result<Devices>;
+    fn inhibit_screensaver(&self, inhibit: bool) -> Result<()>;
+    fn monitors(&self) -> Result<Monitors>;
+    fn poll_events(&self) -> Result<Vec<Event<W>>>;
+    fn run(&self, main_loop: &MainLoop, f: &dyn Fn(Event<W>) -> ControlFlow) -> Result<()>;
+    fn set_app_id(&self, app_id: &str) -> Result<()>;
+    fn theme(&self) -> Option<Theme>;
+    fn wait_events(&self, timeout: Option<Duration>) -> Result<Vec<Event<W>>>;
     fn window(&self) -> WindowBuilder<W>;
+    fn windows(&self) -> Vec<Window<W>>;
 }
 
 impl<T: 'static + IClient> IClientObject<T::WindowId> for T {
+    fn choose_pixel_format(&self, requirements: &PixelFormatRequirements) -> Result<PixelFormat> {
+        Ok(PixelFormat::new(<T as IClient>::choose_pixel_format(self, requirements)?))
+    }
+
+    fn create_tray_icon(&self, id: T::WindowId, icon: &Icon) -> Result<TrayIcon<T::WindowId>> {
+        Ok(TrayIcon::new(<T as IClient>::create_tray_icon(self, id, icon)?))
+    }
+
     fn default_pixel_format(&self) -> PixelFormat {
         PixelFormat::new(<T as IClient>::default_pixel_format(&self))
     }
 
-    fn run(&self, main_loop: &MainLoop, f: &dyn Fn(Event<T::WindowId>)) -> Result<()> {
+    fn devices(&self) -> Result<Devices> {
+        <T as IClient>::devices(self)
+    }
+
+    fn inhibit_screensaver(&self, inhibit: bool) -> Result<()> {
+        <T as IClient>::inhibit_screensaver(self, inhibit)
+    }
+
+    fn monitors(&self) -> Result<Monitors> {
+        <T as IClient>::monitors(self)
+    }
+
+    fn poll_events(&self) -> Result<Vec<Event<T::WindowId>>> {
+        <T as IClient>::poll_events(self)
+    }
+
+    fn run(&self, main_loop: &MainLoop, f: &dyn Fn(Event<T::WindowId>) -> ControlFlow)
+        -> Result<()>
+    {
         <T as IClient>::run(self, main_loop, &f)
     }
 
+    fn set_app_id(&self, app_id: &str) -> Result<()> {
+        <T as IClient>::set_app_id(self, app_id)
+    }
+
+    fn theme(&self) -> Option<Theme> {
+        <T as IClient>::theme(self)
+    }
+
+    fn wait_events(&self, timeout: Option<Duration>) -> Result<Vec<Event<T::WindowId>>> {
+        <T as IClient>::wait_events(self, timeout)
+    }
+
     fn window(&self) -> WindowBuilder<T::WindowId> {
         WindowBuilder::new(<T as IClient>::window(self))
     }
+
+    fn windows(&self) -> Vec<Window<T::WindowId>> {
+        <T as IClient>::windows(self).into_iter().map(Window::new).collect()
+    }
 }
 
 /// Boxed window system client type.
@@ -96,19 +206,62 @@ impl<W: 'static + Clone> Client<W> {
 
 impl<W: 'static + Clone> IClient for Client<W> {
     type PixelFormat = PixelFormat;
+    type TrayIcon = TrayIcon<W>;
     type Window = Window<W>;
     type WindowBuilder = WindowBuilder<W>;
     type WindowId = W;
 
+    fn choose_pixel_format(&self, requirements: &PixelFormatRequirements) -> Result<PixelFormat> {
+        self.inner.choose_pixel_format(requirements)
+    }
+
+    fn create_tray_icon(&self, id: W, icon: &Icon) -> Result<TrayIcon<W>> {
+        self.inner.create_tray_icon(id, icon)
+    }
+
     fn default_pixel_format(&self) -> PixelFormat {
         self.inner.default_pixel_format()
     }
 
-    fn run<F: Fn(Event<W>)>(&self, main_loop: &MainLoop, f: &F) -> Result<()> {
-        self.inner.run(main_loop, f)
+    fn devices(&self) -> Result<Devices> {
+        self.inner.devices()
+    }
+
+    fn inhibit_screensaver(&self, inhibit: bool) -> Result<()> {
+        self.inner.inhibit_screensaver(inhibit)
+    }
+
+    fn monitors(&self) -> Result<Monitors> {
+        self.inner.monitors()
+    }
+
+    fn poll_events(&self) -> Result<Vec<Event<W>>> {
+        self.inner.poll_events()
+    }
+
+    fn run<F, R>(&self, main_loop: &MainLoop, f: &F) -> Result<()>
+        where F: Fn(Event<W>) -> R, R: Into<ControlFlow>
+    {
+        self.inner.run(main_loop, &|event| f(event).into())
+    }
+
+    fn set_app_id(&self, app_id: &str) -> Result<()> {
+        self.inner.set_app_id(app_id)
+    }
+
+    fn theme(&self) -> Option<Theme> {
+        self.inner.theme()
+    }
+
+    fn wait_events(&self, timeout: Option<Duration>) -> Result<Vec<Event<W>>> {
+        self.inner.wait_events(timeout)
     }
 
     fn window(&self) -> WindowBuilder<W> {
         self.inner.window()
     }
+
+    fn windows(&self) -> Vec<Window<W>> {
+        self.inner.windows()
+    }
 }
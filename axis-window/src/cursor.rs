@@ -0,0 +1,96 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use vectorial::Vec2;
+
+use crate::error::Result;
+use crate::Coord;
+
+/// Describes the appearance of the mouse cursor over a window.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Cursor {
+    Image(CursorImage),
+    Shape(CursorShape),
+}
+
+impl Default for Cursor {
+    fn default() -> Cursor {
+        Cursor::Shape(CursorShape::Arrow)
+    }
+}
+
+impl From<CursorImage> for Cursor {
+    fn from(image: CursorImage) -> Cursor {
+        Cursor::Image(image)
+    }
+}
+
+impl From<CursorShape> for Cursor {
+    fn from(shape: CursorShape) -> Cursor {
+        Cursor::Shape(shape)
+    }
+}
+
+/// Custom cursor image with a hotspot.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CursorImage {
+    hotspot: Vec2<Coord>,
+    pixels: Vec<[u8; 4]>,
+    size: Vec2<Coord>,
+}
+
+impl CursorImage {
+    /// Constructs a cursor image from non-premultiplied RGBA pixels in row-major order.
+    pub fn new(size: Vec2<Coord>, hotspot: Vec2<Coord>, pixels: Vec<[u8; 4]>)
+        -> Result<CursorImage>
+    {
+        if size.x <= 0 || size.y <= 0 || size.x > u16::MAX as Coord || size.y > u16::MAX as Coord {
+            return Err(err!(InvalidArgument("cursor size out of range")));
+        }
+        if pixels.len() != size.x as usize * size.y as usize {
+            return Err(err!(InvalidArgument("cursor pixel count doesn't match size")));
+        }
+        if hotspot.x < 0 || hotspot.y < 0 || hotspot.x >= size.x || hotspot.y >= size.y {
+            return Err(err!(InvalidArgument("cursor hotspot out of bounds")));
+        }
+
+        Ok(CursorImage { hotspot, pixels, size })
+    }
+
+    /// Returns the position of the cursor's active point within the image.
+    pub fn hotspot(&self) -> Vec2<Coord> {
+        self.hotspot
+    }
+
+    /// Returns the RGBA pixels of the image in row-major order.
+    pub fn pixels(&self) -> &[[u8; 4]] {
+        self.pixels.as_slice()
+    }
+
+    /// Returns the size of the image.
+    pub fn size(&self) -> Vec2<Coord> {
+        self.size
+    }
+}
+
+/// Enumeration of standard system cursor shapes.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CursorShape {
+    Arrow,
+    Crosshair,
+    Hand,
+    Hidden,
+    Move,
+    NotAllowed,
+    ResizeEw,
+    ResizeNesw,
+    ResizeNs,
+    ResizeNwse,
+    Text,
+    Wait,
+}
@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+/// Describes an input device.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Device {
+    attachment: Option<u64>,
+    id: u64,
+    kind: DeviceKind,
+    master: bool,
+    name: String,
+}
+
+impl Device {
+    /// Returns the ID of the master device which this device is attached to. Returns `None` for
+    /// master devices, floating devices, and on window systems without a device hierarchy.
+    pub fn attachment(&self) -> Option<u64> {
+        self.attachment
+    }
+
+    /// Returns the window system's ID for the device. This is the ID reported by
+    /// [DeviceAdded](crate::Event::DeviceAdded) and [DeviceRemoved](crate::Event::DeviceRemoved)
+    /// events.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns true if this is a master device, which is a virtual device combining the input of
+    /// the physical devices attached to it. Only X11 has master devices.
+    pub fn is_master(&self) -> bool {
+        self.master
+    }
+
+    /// Returns the kind of the device.
+    pub fn kind(&self) -> DeviceKind {
+        self.kind
+    }
+
+    /// Returns the name of the device as reported by the window system.
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+}
+
+impl Device {
+    pub(crate) fn new(id: u64, kind: DeviceKind, name: String) -> Device {
+        Device {
+            attachment: None,
+            id,
+            kind,
+            master: false,
+            name,
+        }
+    }
+
+    pub(crate) fn with_attachment(self, attachment: Option<u64>) -> Device {
+        Device { attachment, ..self }
+    }
+
+    pub(crate) fn with_master(self, master: bool) -> Device {
+        Device { master, ..self }
+    }
+}
+
+/// Enumeration of input device kinds.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DeviceKind {
+    Keyboard,
+    /// A device which is neither a keyboard nor a pointer, such as a game controller.
+    Other,
+    /// A mouse, touchpad, tablet or other device which moves the pointer.
+    Pointer,
+}
+
+/// Iterator over the input devices reported by a window system client.
+pub struct Devices {
+    inner: std::vec::IntoIter<Device>,
+}
+
+impl Devices {
+    pub(crate) fn new(devices: Vec<Device>) -> Devices {
+        Devices { inner: devices.into_iter() }
+    }
+}
+
+impl Iterator for Devices {
+    type Item = Device;
+
+    fn next(&mut self) -> Option<Device> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
@@ -11,58 +11,101 @@ use std::collections::VecDeque;
 use std::mem::MaybeUninit;
 use std::os::raw::c_void;
 use std::rc::Rc;
+use std::time::Duration;
+
+use winapi::um::winuser::MSG;
 
 use crate::client::IClient;
+use crate::device::{Device, Devices};
+use crate::driver::win32::device::query_devices;
+use crate::driver::win32::monitor::query_monitors;
 use crate::driver::win32::pixel_format::PixelFormat;
-use crate::driver::win32::window::{Window, WindowBuilder, WindowClassManager};
+use crate::driver::win32::tray::TrayIcon;
+use crate::driver::win32::window::{
+    query_theme,
+    Window,
+    WindowBuilder,
+    WindowClassManager,
+    WindowManager,
+};
 use crate::error::Result;
-use crate::event::{Event, MainLoop, UpdateMode};
+use crate::event::{ControlFlow, Event, FrameClock, MainLoop, UpdateMode};
+use crate::ffi;
+use crate::monitor::Monitors;
+use crate::pixel_format::PixelFormatRequirements;
+use crate::tray::Icon;
+use crate::window::Theme;
 
 /// Win32 window system client type.
 pub struct Client<W: 'static + Clone> {
     event_manager: Rc<EventManager<W>>,
+    native_event_hook: RefCell<Option<Rc<dyn Fn(&MSG) -> bool>>>,
     window_class_name: Rc<Vec<u16>>,
+    window_manager: Rc<WindowManager<W>>,
 }
 
 impl<W: 'static + Clone> Client<W> {
-    /// Opens a window system client for the current thread.
+    /// Removes the hook set by [set_native_event_hook](Client::set_native_event_hook).
+    pub fn clear_native_event_hook(&self) {
+        *self.native_event_hook.borrow_mut() = None;
+    }
+
+    /// Opens a window system client for the current thread. The process is opted into
+    /// per-monitor DPI awareness, so coordinates are in physical pixels and windows report
+    /// [ScaleFactorChanged](Event::ScaleFactorChanged) events as they move between monitors.
     pub fn open() -> Result<Client<W>> {
+        ffi::win32::enable_dpi_awareness();
+
         Ok(Client {
             event_manager: Rc::new(EventManager::new()),
+            native_event_hook: RefCell::new(None),
             window_class_name: Rc::new(WindowClassManager::get().lock()?.register::<W>()?),
+            window_manager: Rc::new(WindowManager::new()),
         })
     }
+
+    /// Sets a function which receives each message retrieved from the thread's message queue
+    /// before it's translated and dispatched. If the hook returns true, the message is consumed
+    /// and the client doesn't process it. Messages sent directly to a window procedure bypass the
+    /// queue, so they aren't seen by the hook.
+    pub fn set_native_event_hook<F: 'static + Fn(&MSG) -> bool>(&self, hook: F) {
+        *self.native_event_hook.borrow_mut() = Some(Rc::new(hook));
+    }
 }
 
 impl<W: 'static + Clone> Client<W> {
     pub(crate) fn event_manager(&self) -> &Rc<EventManager<W>> { &self.event_manager }
 
     pub(crate) fn window_class_name(&self) -> &Rc<Vec<u16>> { &self.window_class_name }
+
+    pub(crate) fn window_manager(&self) -> &Rc<WindowManager<W>> { &self.window_manager }
 }
 
-impl<W: 'static + Clone> IClient for Client<W> {
-    type PixelFormat = PixelFormat;
-    type Window = Window<W>;
-    type WindowBuilder = WindowBuilder<W>;
-    type WindowId = W;
+impl<W: 'static + Clone> Client<W> {
+    /// Passes a message to the native event hook, then translates and dispatches it unless the
+    /// hook consumed it.
+    unsafe fn dispatch_message(&self, msg: &MSG) {
+        // The hook is cloned so that it can replace itself.
+        let hook = self.native_event_hook.borrow().clone();
+        if let Some(hook) = hook {
+            if hook(msg) {
+                return;
+            }
+        }
 
-    fn default_pixel_format(&self) -> PixelFormat {
-        PixelFormat::default()
+        winapi::um::winuser::TranslateMessage(msg);
+        winapi::um::winuser::DispatchMessageW(msg);
     }
 
-    fn run<F: Fn(Event<W>)>(&self, main_loop: &MainLoop, f: &F) -> Result<()> {
-        let need_update = Cell::new(true);
-        let f = |event| {
-            match event {
-                Event::Update { .. } => (),
-                _ => need_update.set(true),
-            }
-            f(event);
-        };
+    /// Runs the main loop until it quits.
+    fn run_loop<F: Fn(Event<W>)>(&self, main_loop: &MainLoop, f: &F, need_update: &Cell<bool>)
+        -> Result<()>
+    {
+        let mut frame_clock = None;
 
         unsafe {
             let mut msg = MaybeUninit::zeroed().assume_init();
-            let event_handler = EventHandler::push(self.event_manager.as_ref(), &f);
+            let event_handler = EventHandler::push(self.event_manager.as_ref(), f);
 
             // Handle events that were processed and queued.
             'queue_loop: while !main_loop.is_quit_requested() {
@@ -81,14 +124,47 @@ impl<W: 'static + Clone> IClient for Client<W> {
                         break 'main_loop;
                     }
 
-                    winapi::um::winuser::TranslateMessage(&msg);
-                    winapi::um::winuser::DispatchMessageW(&msg);
+                    self.dispatch_message(&msg);
 
                     if main_loop.is_quit_requested() {
                         break 'main_loop;
                     }
                 }
 
+                // Handle events posted by proxies.
+                for data in main_loop.take_user_events()? {
+                    event_handler.dispatch(Event::User { data });
+                    if main_loop.is_quit_requested() {
+                        break 'main_loop;
+                    }
+                }
+
+                // Apply commands sent through window handles. They're sent from other threads,
+                // so failures can only be logged.
+                for (target, command) in main_loop.take_window_commands()? {
+                    let window = self.window_manager.windows()
+                        .into_iter()
+                        .find(|window| window.handle_target() == target);
+                    if let Some(window) = window {
+                        if let Err(_err) = command.apply(&window) {
+                            warn!("window command failed: {}", _err);
+                        }
+                    }
+                }
+
+                // Handle expired timers.
+                for timer_id in main_loop.take_expired_timers() {
+                    event_handler.dispatch(Event::Timer { timer_id });
+                    if main_loop.is_quit_requested() {
+                        break 'main_loop;
+                    }
+                }
+
+                // A requested wake-up time counts as a reason to update.
+                if main_loop.take_wait_expired() {
+                    need_update.set(true);
+                }
+
                 // Handle update event and wait for more messages.
                 match main_loop.update_mode() {
                     UpdateMode::Passive => {
@@ -102,39 +178,213 @@ impl<W: 'static + Clone> IClient for Client<W> {
                             break 'main_loop;
                         }
 
+                        // With timers pending, wait for input or the next deadline, and let the
+                        // next iteration collect any messages.
+                        if let Some(timeout) = main_loop.next_timeout() {
+                            wait_for_messages(Some(timeout))?;
+                            continue 'main_loop;
+                        }
+
                         match winapi::um::winuser::GetMessageW(&mut msg, std::ptr::null_mut(),
                                                                0, 0)
                         {
                             -1 => return Err(err!(RuntimeError("GetMessageW"): ??w)),
                             0 => break 'main_loop,
-                            _ => {
-                                winapi::um::winuser::TranslateMessage(&msg);
-                                winapi::um::winuser::DispatchMessageW(&msg);
-                            },
+                            _ => self.dispatch_message(&msg),
                         }
                     },
 
-                    UpdateMode::Active | UpdateMode::Sync => {
+                    UpdateMode::Active => {
                         event_handler.dispatch(Event::Update {
                             update_mode: UpdateMode::Active,
                         });
                     },
+
+                    UpdateMode::Sync => match frame_clock {
+                        None => {
+                            event_handler.dispatch(Event::Update {
+                                update_mode: UpdateMode::Sync,
+                            });
+
+                            // `DwmFlush` blocks until the compositor presents its next frame. It
+                            // fails if desktop composition is disabled, in which case updates are
+                            // paced to the monitor's refresh rate instead.
+                            if winapi::um::dwmapi::DwmFlush() < 0 {
+                                warn!("DwmFlush failed; pacing updates to the refresh rate");
+                                frame_clock = Some(FrameClock::for_monitors(
+                                    self.monitors().into_iter().flatten()));
+                            }
+                        },
+
+                        Some(ref mut frame_clock) => match frame_clock.time_until_frame() {
+                            None => {
+                                frame_clock.advance();
+                                event_handler.dispatch(Event::Update {
+                                    update_mode: UpdateMode::Sync,
+                                });
+                            },
+                            Some(frame_timeout) => {
+                                let timeout = match main_loop.next_timeout() {
+                                    None => frame_timeout,
+                                    Some(timeout) => std::cmp::min(timeout, frame_timeout),
+                                };
+                                wait_for_messages(Some(timeout))?;
+                            },
+                        },
+                    },
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: 'static + Clone> IClient for Client<W> {
+    type PixelFormat = PixelFormat;
+    type TrayIcon = TrayIcon<W>;
+    type Window = Window<W>;
+    type WindowBuilder = WindowBuilder<W>;
+    type WindowId = W;
+
+    fn choose_pixel_format(&self, requirements: &PixelFormatRequirements) -> Result<PixelFormat> {
+        PixelFormat::choose(requirements)
+    }
+
+    fn create_tray_icon(&self, id: W, icon: &Icon) -> Result<TrayIcon<W>> {
+        TrayIcon::new(self, id, icon)
+    }
+
+    fn default_pixel_format(&self) -> PixelFormat {
+        PixelFormat::default()
+    }
+
+    /// Returns the raw input devices. Windows doesn't have master devices, so every device is
+    /// reported as a separate physical device.
+    fn devices(&self) -> Result<Devices> {
+        Ok(Devices::new(query_devices()?))
+    }
+
+    fn inhibit_screensaver(&self, inhibit: bool) -> Result<()> {
+        // The execution state belongs to the calling thread and lasts until it's changed again or
+        // the thread exits.
+        let flags = if inhibit {
+            winapi::um::winnt::ES_CONTINUOUS | winapi::um::winnt::ES_DISPLAY_REQUIRED
+            | winapi::um::winnt::ES_SYSTEM_REQUIRED
+        } else {
+            winapi::um::winnt::ES_CONTINUOUS
+        };
+
+        unsafe {
+            if winapi::um::winbase::SetThreadExecutionState(flags) == 0 {
+                return Err(err!(RuntimeError("SetThreadExecutionState")));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn monitors(&self) -> Result<Monitors> {
+        Ok(Monitors::new(query_monitors()?))
+    }
+
+    fn poll_events(&self) -> Result<Vec<Event<W>>> {
+        let events = RefCell::new(Vec::new());
+        let f = |event| events.borrow_mut().push(event);
+
+        unsafe {
+            let mut msg = MaybeUninit::zeroed().assume_init();
+            let event_handler = EventHandler::push(self.event_manager.as_ref(), &f);
+
+            while let Some(event) = self.event_manager.pop() {
+                event_handler.dispatch(event);
+            }
+
+            while winapi::um::winuser::PeekMessageW(
+                &mut msg, std::ptr::null_mut(), 0, 0, winapi::um::winuser::PM_REMOVE) != 0
+            {
+                // Re-posting the quit message would wake `wait_events` immediately forever, so
+                // it's reported to the caller instead.
+                if msg.message == winapi::um::winuser::WM_QUIT {
+                    event_handler.dispatch(Event::Quit { exit_code: msg.wParam as i32 });
+                    break;
                 }
+
+                self.dispatch_message(&msg);
             }
         }
 
+        Ok(events.into_inner())
+    }
+
+    fn run<F, R>(&self, main_loop: &MainLoop, f: &F) -> Result<()>
+        where F: Fn(Event<W>) -> R, R: Into<ControlFlow>
+    {
+        let need_update = Cell::new(true);
+        let f = |event| {
+            match event {
+                Event::Update { .. } => (),
+                _ => need_update.set(true),
+            }
+            main_loop.apply_control_flow(f(event).into());
+        };
+
+        // Proxies wake `GetMessageW` by posting an empty thread message.
+        let thread_id = unsafe { winapi::um::processthreadsapi::GetCurrentThreadId() };
+        main_loop.set_waker(Some(Box::new(move || unsafe {
+            winapi::um::winuser::PostThreadMessageW(thread_id, winapi::um::winuser::WM_NULL, 0, 0);
+        })))?;
+        let result = self.run_loop(main_loop, &f, &need_update);
+        main_loop.set_waker(None)?;
+        result
+    }
+
+    /// Sets the explicit AppUserModelID of the process.
+    fn set_app_id(&self, app_id: &str) -> Result<()> {
+        if app_id.contains('\0') {
+            return Err(err!(InvalidArgument("app ID contains a null character")));
+        }
+        let app_id: Vec<u16> = app_id.encode_utf16().chain(std::iter::repeat(0).take(1)).collect();
+
+        let hr = unsafe { ffi::win32::SetCurrentProcessExplicitAppUserModelID(app_id.as_ptr()) };
+        if hr < 0 {
+            return Err(err!(RequestFailed{"SetCurrentProcessExplicitAppUserModelID failed: {:#x}",
+                                          hr}));
+        }
         Ok(())
     }
 
+    fn theme(&self) -> Option<Theme> {
+        query_theme()
+    }
+
+    fn wait_events(&self, timeout: Option<Duration>) -> Result<Vec<Event<W>>> {
+        let events = self.poll_events()?;
+        if !events.is_empty() {
+            return Ok(events);
+        }
+
+        unsafe {
+            wait_for_messages(timeout)?;
+        }
+        self.poll_events()
+    }
+
     fn window(&self) -> WindowBuilder<W> {
         WindowBuilder::new(self)
     }
+
+    fn windows(&self) -> Vec<Window<W>> {
+        self.window_manager.windows()
+    }
 }
 
 /// Handles window system events.
 pub struct EventManager<W: 'static + Clone> {
+    devices: RefCell<Vec<u64>>,
     dispatch_stack: RefCell<Vec<EventDispatch<W>>>,
     event_queue: RefCell<VecDeque<Event<W>>>,
+    theme: Cell<Option<Theme>>,
 }
 
 impl<W: 'static + Clone> EventManager<W> {
@@ -150,13 +400,49 @@ impl<W: 'static + Clone> EventManager<W> {
             },
         }
     }
+
+    /// Compares the attached devices with those last seen, and pushes
+    /// [DeviceAdded](Event::DeviceAdded) and [DeviceRemoved](Event::DeviceRemoved) events for the
+    /// differences. Every top-level window is notified of the same change.
+    pub fn push_device_changes(&self) {
+        let devices = match query_devices() {
+            Ok(devices) => devices.iter().map(Device::id).collect::<Vec<_>>(),
+            Err(_err) => {
+                warn!("can't query devices: {}", _err);
+                return;
+            },
+        };
+        let old_devices = self.devices.replace(devices.clone());
+
+        for &device_id in devices.iter().filter(|id| !old_devices.contains(id)) {
+            self.push(Event::DeviceAdded { device_id });
+        }
+        for &device_id in old_devices.iter().filter(|id| !devices.contains(id)) {
+            self.push(Event::DeviceRemoved { device_id });
+        }
+    }
+
+    /// Pushes a [ThemeChanged](Event::ThemeChanged) event unless `theme` was already reported.
+    /// Every top-level window is notified of the same change.
+    pub fn push_theme_change(&self, theme: Theme) {
+        if self.theme.replace(Some(theme)) != Some(theme) {
+            self.push(Event::ThemeChanged { theme });
+        }
+    }
 }
 
 impl<W: 'static + Clone> EventManager<W> {
     fn new() -> EventManager<W> {
+        let devices = match query_devices() {
+            Ok(devices) => devices.iter().map(Device::id).collect(),
+            Err(_) => Vec::new(),
+        };
+
         EventManager {
+            devices: RefCell::new(devices),
             dispatch_stack: RefCell::new(Vec::new()),
             event_queue: RefCell::new(VecDeque::new()),
+            theme: Cell::new(query_theme()),
         }
     }
 
@@ -218,3 +504,24 @@ impl<'a, W: 'static + Clone> Drop for EventHandler<'a, W> {
         }
     }
 }
+
+/// Waits until input is available for the current thread or `timeout` elapses. Messages are left
+/// in the queue.
+unsafe fn wait_for_messages(timeout: Option<Duration>) -> Result<()> {
+    // Round up so that timers aren't woken early. `INFINITE` is reserved.
+    let millis = match timeout {
+        None => winapi::um::winbase::INFINITE,
+        Some(timeout) => {
+            let millis = (timeout.as_nanos() + 999_999) / 1_000_000;
+            u32::try_from(millis).unwrap_or(winapi::um::winbase::INFINITE - 1)
+        },
+    };
+    if winapi::um::winuser::MsgWaitForMultipleObjectsEx(0, std::ptr::null(), millis,
+                                                        winapi::um::winuser::QS_ALLINPUT,
+                                                        winapi::um::winuser::MWMO_INPUTAVAILABLE)
+        == winapi::um::winbase::WAIT_FAILED
+    {
+        return Err(err!(RuntimeError("MsgWaitForMultipleObjectsEx"): ??w));
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::mem::MaybeUninit;
+
+use vectorial::Vec2;
+use winapi::shared::windef::{HBITMAP, HCURSOR, HICON};
+use winapi::um::wingdi::BITMAPV5HEADER;
+use winapi::um::winuser::ICONINFO;
+
+use crate::cursor::{Cursor, CursorImage, CursorShape};
+use crate::error::Result;
+use crate::Coord;
+
+/// Cursor handle which is destroyed when dropped if it isn't a shared system cursor.
+pub struct CursorHandle {
+    hcursor: HCURSOR,
+    owned: bool,
+}
+
+impl CursorHandle {
+    /// Creates a cursor handle.
+    pub fn new(cursor: &Cursor) -> Result<CursorHandle> {
+        match *cursor {
+            Cursor::Image(ref image) => create_image_cursor(image),
+            Cursor::Shape(shape) => load_shape_cursor(shape),
+        }
+    }
+
+    /// Returns the underlying cursor handle, which is null for hidden cursors.
+    pub fn hcursor(&self) -> HCURSOR {
+        self.hcursor
+    }
+}
+
+impl Drop for CursorHandle {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe {
+                winapi::um::winuser::DestroyCursor(self.hcursor);
+            }
+        }
+    }
+}
+
+/// Creates an alpha-blended icon from non-premultiplied RGBA pixels. The icon is a cursor if
+/// `hotspot` is given.
+pub fn create_icon(size: Vec2<Coord>, hotspot: Option<Vec2<Coord>>, pixels: &[[u8; 4]])
+    -> Result<HICON>
+{
+    unsafe {
+        let mut header = MaybeUninit::<BITMAPV5HEADER>::zeroed().assume_init();
+        header.bV5Size = std::mem::size_of::<BITMAPV5HEADER>() as u32;
+        header.bV5Width = size.x;
+        header.bV5Height = -size.y;
+        header.bV5Planes = 1;
+        header.bV5BitCount = 32;
+        header.bV5Compression = winapi::um::wingdi::BI_BITFIELDS;
+        header.bV5RedMask = 0x00ff0000;
+        header.bV5GreenMask = 0x0000ff00;
+        header.bV5BlueMask = 0x000000ff;
+        header.bV5AlphaMask = 0xff000000;
+
+        let mut bits = std::ptr::null_mut();
+        let color = winapi::um::wingdi::CreateDIBSection(
+            std::ptr::null_mut(), &header as *const _ as *const _,
+            winapi::um::wingdi::DIB_RGB_COLORS, &mut bits, std::ptr::null_mut(), 0);
+        if color.is_null() {
+            return Err(err!(RuntimeError("CreateDIBSection"): ??w));
+        }
+        let dst = std::slice::from_raw_parts_mut(bits as *mut [u8; 4], pixels.len());
+        for (dst, &[r, g, b, a]) in dst.iter_mut().zip(pixels) {
+            *dst = [b, g, r, a];
+        }
+
+        // The mask is ignored for 32-bit color bitmaps with alpha, but it must still exist.
+        let mask_stride = (size.x as usize + 15) / 16 * 2;
+        let mask_bits = vec![0u8; mask_stride * size.y as usize];
+        let mask = winapi::um::wingdi::CreateBitmap(size.x, size.y, 1, 1,
+                                                    mask_bits.as_ptr() as *const _);
+        if mask.is_null() {
+            let err = err!(RuntimeError("CreateBitmap"): ??w);
+            delete_bitmap(color);
+            return Err(err);
+        }
+
+        let mut info = ICONINFO {
+            fIcon: if hotspot.is_some() { 0 } else { 1 },
+            xHotspot: hotspot.map(|hotspot| hotspot.x as u32).unwrap_or(0),
+            yHotspot: hotspot.map(|hotspot| hotspot.y as u32).unwrap_or(0),
+            hbmMask: mask,
+            hbmColor: color,
+        };
+        let hicon = winapi::um::winuser::CreateIconIndirect(&mut info);
+        let result = if hicon.is_null() {
+            Err(err!(RuntimeError("CreateIconIndirect"): ??w))
+        } else {
+            Ok(hicon)
+        };
+        delete_bitmap(mask);
+        delete_bitmap(color);
+        result
+    }
+}
+
+/// Creates an alpha-blended cursor from an image.
+fn create_image_cursor(image: &CursorImage) -> Result<CursorHandle> {
+    let hcursor = create_icon(image.size(), Some(image.hotspot()), image.pixels())?;
+    Ok(CursorHandle { hcursor, owned: true })
+}
+
+/// Deletes a GDI bitmap.
+unsafe fn delete_bitmap(bitmap: HBITMAP) {
+    winapi::um::wingdi::DeleteObject(bitmap as *mut _);
+}
+
+/// Loads a shared system cursor.
+fn load_shape_cursor(shape: CursorShape) -> Result<CursorHandle> {
+    let name = match shape {
+        CursorShape::Arrow => winapi::um::winuser::IDC_ARROW,
+        CursorShape::Crosshair => winapi::um::winuser::IDC_CROSS,
+        CursorShape::Hand => winapi::um::winuser::IDC_HAND,
+        CursorShape::Hidden => {
+            return Ok(CursorHandle { hcursor: std::ptr::null_mut(), owned: false });
+        },
+        CursorShape::Move => winapi::um::winuser::IDC_SIZEALL,
+        CursorShape::NotAllowed => winapi::um::winuser::IDC_NO,
+        CursorShape::ResizeEw => winapi::um::winuser::IDC_SIZEWE,
+        CursorShape::ResizeNesw => winapi::um::winuser::IDC_SIZENESW,
+        CursorShape::ResizeNs => winapi::um::winuser::IDC_SIZENS,
+        CursorShape::ResizeNwse => winapi::um::winuser::IDC_SIZENWSE,
+        CursorShape::Text => winapi::um::winuser::IDC_IBEAM,
+        CursorShape::Wait => winapi::um::winuser::IDC_WAIT,
+    };
+
+    unsafe {
+        let hcursor = winapi::um::winuser::LoadCursorW(std::ptr::null_mut(), name);
+        if hcursor.is_null() {
+            return Err(err!(RuntimeError("LoadCursorW"): ??w));
+        }
+        Ok(CursorHandle { hcursor, owned: false })
+    }
+}
@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use winapi::um::winnt::HANDLE;
+use winapi::um::winuser::RAWINPUTDEVICELIST;
+
+use crate::device::{Device, DeviceKind};
+use crate::error::Result;
+
+/// Queries the raw input devices attached to the system.
+pub fn query_devices() -> Result<Vec<Device>> {
+    let size = std::mem::size_of::<RAWINPUTDEVICELIST>() as u32;
+    let mut list;
+
+    unsafe {
+        // A device may be attached between the two calls, in which case the list is too small
+        // and the count is queried again.
+        loop {
+            let mut count = 0;
+            if winapi::um::winuser::GetRawInputDeviceList(std::ptr::null_mut(), &mut count, size)
+                == u32::MAX
+            {
+                return Err(err!(RuntimeError("GetRawInputDeviceList"): ??w));
+            }
+
+            list = vec![std::mem::zeroed::<RAWINPUTDEVICELIST>(); count as usize];
+            match winapi::um::winuser::GetRawInputDeviceList(list.as_mut_ptr(), &mut count, size) {
+                u32::MAX => {
+                    if winapi::um::errhandlingapi::GetLastError()
+                        != winapi::shared::winerror::ERROR_INSUFFICIENT_BUFFER
+                    {
+                        return Err(err!(RuntimeError("GetRawInputDeviceList"): ??w));
+                    }
+                },
+                len => {
+                    list.truncate(len as usize);
+                    break;
+                },
+            }
+        }
+
+        Ok(list.iter().map(|item| {
+            let kind = match item.dwType {
+                winapi::um::winuser::RIM_TYPEKEYBOARD => DeviceKind::Keyboard,
+                winapi::um::winuser::RIM_TYPEMOUSE => DeviceKind::Pointer,
+                _ => DeviceKind::Other,
+            };
+            Device::new(item.hDevice as usize as u64, kind, device_name(item.hDevice))
+        }).collect())
+    }
+}
+
+/// Gets the name of a raw input device, which is the path of its device interface. Returns an
+/// empty string if the name can't be determined.
+unsafe fn device_name(hdevice: HANDLE) -> String {
+    let mut len = 0;
+    winapi::um::winuser::GetRawInputDeviceInfoW(hdevice, winapi::um::winuser::RIDI_DEVICENAME,
+                                                std::ptr::null_mut(), &mut len);
+
+    let mut name = vec![0u16; len as usize];
+    if winapi::um::winuser::GetRawInputDeviceInfoW(hdevice, winapi::um::winuser::RIDI_DEVICENAME,
+                                                   name.as_mut_ptr() as *mut _, &mut len)
+        == u32::MAX
+    {
+        return String::new();
+    }
+
+    let end = name.iter().position(|&ch| ch == 0).unwrap_or(name.len());
+    String::from_utf16_lossy(&name[..end])
+}
@@ -7,8 +7,13 @@
  */
 
 mod client;
+mod cursor;
+mod device;
+mod monitor;
 mod pixel_format;
+mod tray;
 mod window;
 
 pub use self::client::Client;
 pub use self::pixel_format::PixelFormat;
+pub use self::tray::TrayIcon;
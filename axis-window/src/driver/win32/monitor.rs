@@ -0,0 +1,82 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::mem::MaybeUninit;
+
+use vectorial::Vec2;
+use winapi::shared::minwindef::{BOOL, LPARAM};
+use winapi::shared::windef::{HDC, HMONITOR, LPRECT, RECT};
+use winapi::um::wingdi::DEVMODEW;
+use winapi::um::winuser::MONITORINFOEXW;
+
+use crate::error::Result;
+use crate::monitor::Monitor;
+use crate::Coord;
+
+/// Queries the monitors attached to the desktop.
+pub fn query_monitors() -> Result<Vec<Monitor>> {
+    let mut monitors = Vec::new();
+
+    unsafe {
+        if winapi::um::winuser::EnumDisplayMonitors(std::ptr::null_mut(), std::ptr::null(),
+                                                    Some(enum_monitors_proc),
+                                                    &mut monitors as *mut Vec<_> as LPARAM) == 0
+        {
+            return Err(err!(RuntimeError("EnumDisplayMonitors"): ??w));
+        }
+    }
+
+    Ok(monitors)
+}
+
+/// Gets information about a monitor handle.
+unsafe fn query_monitor(hmonitor: HMONITOR) -> Result<Monitor> {
+    let mut info = MaybeUninit::<MONITORINFOEXW>::zeroed().assume_init();
+    info.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+    if winapi::um::winuser::GetMonitorInfoW(hmonitor, &mut info as *mut _ as *mut _) == 0 {
+        return Err(err!(RuntimeError("GetMonitorInfoW"): ??w));
+    }
+
+    let name_len = info.szDevice.iter().position(|&ch| ch == 0).unwrap_or(info.szDevice.len());
+    let name = String::from_utf16_lossy(&info.szDevice[..name_len]);
+
+    let mut devmode = MaybeUninit::<DEVMODEW>::zeroed().assume_init();
+    devmode.dmSize = std::mem::size_of::<DEVMODEW>() as u16;
+    let refresh_rate = match winapi::um::winuser::EnumDisplaySettingsW(
+        info.szDevice.as_ptr(), winapi::um::winuser::ENUM_CURRENT_SETTINGS, &mut devmode)
+    {
+        // Frequencies of 0 and 1 indicate the hardware's default rate.
+        0 => None,
+        _ if devmode.dmDisplayFrequency <= 1 => None,
+        _ => devmode.dmDisplayFrequency.checked_mul(1000),
+    };
+
+    let (pos, size) = rect_to_pos_size(&info.rcMonitor);
+    let (work_pos, work_size) = rect_to_pos_size(&info.rcWork);
+    Ok(Monitor::new(name, pos, size)
+        .with_primary(info.dwFlags & winapi::um::winuser::MONITORINFOF_PRIMARY != 0)
+        .with_refresh_rate(refresh_rate)
+        .with_work_area(work_pos, work_size))
+}
+
+/// Callback for `EnumDisplayMonitors`.
+unsafe extern "system" fn enum_monitors_proc(hmonitor: HMONITOR, _: HDC, _: LPRECT,
+                                             lparam: LPARAM) -> BOOL
+{
+    let monitors = &mut *(lparam as *mut Vec<Monitor>);
+    match query_monitor(hmonitor) {
+        Ok(monitor) => monitors.push(monitor),
+        Err(_err) => error!("can't query monitor: {}", _err),
+    }
+    1
+}
+
+/// Converts a `RECT` to a position and size.
+fn rect_to_pos_size(rect: &RECT) -> (Vec2<Coord>, Vec2<Coord>) {
+    (Vec2::new(rect.left, rect.top), Vec2::new(rect.right - rect.left, rect.bottom - rect.top))
+}
@@ -9,13 +9,13 @@
 use libc::size_t;
 use winapi::um::wingdi::PIXELFORMATDESCRIPTOR;
 
-use crate::pixel_format::IPixelFormat;
+use crate::error::Result;
+use crate::pixel_format::{IPixelFormat, PixelFormatRequirements};
 
 /// Internal data for [PixelFormat].
 #[derive(Clone)]
 enum PixelFormatData {
     Default,
-    #[allow(dead_code)]
     Gdi(i32, PIXELFORMATDESCRIPTOR),
 }
 
@@ -72,4 +72,82 @@ impl PixelFormat {
     }
 }
 
-impl IPixelFormat for PixelFormat {}
+impl PixelFormat {
+    /// Chooses the OpenGL pixel format which best satisfies `requirements` from those the display
+    /// driver describes through GDI.
+    pub(crate) fn choose(requirements: &PixelFormatRequirements) -> Result<PixelFormat> {
+        // GDI pixel format descriptors don't describe multisampling, which can only be queried
+        // with `WGL_ARB_pixel_format` from a current OpenGL context.
+        if requirements.samples > 1 {
+            return Err(err!(Unsupported("multisampled GDI pixel formats")));
+        }
+
+        let pfd_size = std::mem::size_of::<PIXELFORMATDESCRIPTOR>() as u32;
+        let mut best: Option<((bool, u32), PixelFormat)> = None;
+
+        unsafe {
+            let hdc = winapi::um::winuser::GetDC(std::ptr::null_mut());
+            if hdc.is_null() {
+                return Err(err!(RuntimeError("GetDC")));
+            }
+
+            let count = winapi::um::wingdi::DescribePixelFormat(hdc, 1, pfd_size,
+                                                                std::ptr::null_mut());
+            for index in 1..=count {
+                let mut pfd = std::mem::zeroed::<PIXELFORMATDESCRIPTOR>();
+                if winapi::um::wingdi::DescribePixelFormat(hdc, index, pfd_size, &mut pfd) == 0 {
+                    continue;
+                }
+
+                let required_flags = winapi::um::wingdi::PFD_DRAW_TO_WINDOW
+                                     | winapi::um::wingdi::PFD_SUPPORT_OPENGL;
+                let double_buffered = pfd.dwFlags & winapi::um::wingdi::PFD_DOUBLEBUFFER != 0;
+                if pfd.dwFlags & required_flags != required_flags
+                   || pfd.iPixelType != winapi::um::wingdi::PFD_TYPE_RGBA
+                   || !requirements.is_satisfied_by(pfd.cDepthBits, double_buffered, 0,
+                                                    pfd.cStencilBits)
+                {
+                    continue;
+                }
+
+                // Generic formats without `PFD_GENERIC_ACCELERATED` are rendered in software, so
+                // they're only chosen if nothing else fits.
+                let software = pfd.dwFlags & (winapi::um::wingdi::PFD_GENERIC_FORMAT
+                                              | winapi::um::wingdi::PFD_GENERIC_ACCELERATED)
+                               == winapi::um::wingdi::PFD_GENERIC_FORMAT;
+                let key = (software, requirements.score(pfd.cDepthBits, double_buffered, 0,
+                                                        pfd.cStencilBits));
+                if best.as_ref().map_or(true, |&(best_key, _)| key < best_key) {
+                    best = Some((key, PixelFormat { data: PixelFormatData::Gdi(index, pfd) }));
+                }
+            }
+
+            winapi::um::winuser::ReleaseDC(std::ptr::null_mut(), hdc);
+        }
+
+        match best {
+            None => Err(err!(Unsupported("no pixel format satisfies the requirements"))),
+            Some((_, pixel_format)) => Ok(pixel_format),
+        }
+    }
+}
+
+impl IPixelFormat for PixelFormat {
+    fn depth_bits(&self) -> u8 {
+        self.gdi_descriptor().map_or(0, |pfd| pfd.cDepthBits)
+    }
+
+    fn is_double_buffered(&self) -> bool {
+        self.gdi_descriptor().map_or(false, |pfd| {
+            pfd.dwFlags & winapi::um::wingdi::PFD_DOUBLEBUFFER != 0
+        })
+    }
+
+    fn samples(&self) -> u8 {
+        0
+    }
+
+    fn stencil_bits(&self) -> u8 {
+        self.gdi_descriptor().map_or(0, |pfd| pfd.cStencilBits)
+    }
+}
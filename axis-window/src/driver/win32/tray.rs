@@ -0,0 +1,235 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use vectorial::Vec2;
+use winapi::shared::windef::{HICON, HWND};
+use winapi::um::shellapi::NOTIFYICONDATAW;
+
+use crate::driver::win32::client::{Client, EventManager};
+use crate::driver::win32::cursor::create_icon;
+use crate::error::Result;
+use crate::event::Event;
+use crate::ffi;
+use crate::tray::{Icon, ITrayIcon};
+
+/// Message sent by the shell when the user interacts with a tray icon.
+const WM_TRAY_CALLBACK: u32 = winapi::um::winuser::WM_APP + 1;
+
+/// Data shared between a [TrayIcon] and its hidden window.
+struct TrayIconData<W: 'static + Clone> {
+    event_manager: Rc<EventManager<W>>,
+    hicon: Cell<HICON>,
+    hwnd: Cell<HWND>,
+    id: W,
+    taskbar_created: u32,
+    tooltip: RefCell<Vec<u16>>,
+}
+
+impl<W: 'static + Clone> TrayIconData<W> {
+    /// Gets a `TrayIconData` from a `HWND`.
+    unsafe fn get<'a>(hwnd: HWND) -> Option<&'a TrayIconData<W>> {
+        match winapi::um::winuser::GetWindowLongPtrW(hwnd, winapi::um::winuser::GWLP_USERDATA) {
+            0 => None,
+            data => Some(&*(data as *const TrayIconData<W>)),
+        }
+    }
+
+    /// Adds, modifies or deletes the notification area icon.
+    unsafe fn notify(&self, message: u32) -> Result<()> {
+        let mut nid = std::mem::zeroed::<NOTIFYICONDATAW>();
+        nid.cbSize = std::mem::size_of::<NOTIFYICONDATAW>() as u32;
+        nid.hWnd = self.hwnd.get();
+        nid.uFlags = winapi::um::shellapi::NIF_ICON | winapi::um::shellapi::NIF_MESSAGE
+                     | winapi::um::shellapi::NIF_SHOWTIP | winapi::um::shellapi::NIF_TIP;
+        nid.uCallbackMessage = WM_TRAY_CALLBACK;
+        nid.hIcon = self.hicon.get();
+        let tooltip = self.tooltip.borrow();
+        let len = std::cmp::min(tooltip.len(), nid.szTip.len() - 1);
+        nid.szTip[..len].copy_from_slice(&tooltip[..len]);
+
+        if winapi::um::shellapi::Shell_NotifyIconW(message, &mut nid) == 0 {
+            return Err(err!(RuntimeError("Shell_NotifyIconW")));
+        }
+
+        // Version 4 callbacks report selection and context menu requests rather than raw mouse
+        // messages, and include the pointer position.
+        if message == winapi::um::shellapi::NIM_ADD {
+            *nid.u.uVersion_mut() = winapi::um::shellapi::NOTIFYICON_VERSION_4;
+            if winapi::um::shellapi::Shell_NotifyIconW(winapi::um::shellapi::NIM_SETVERSION,
+                                                       &mut nid) == 0
+            {
+                return Err(err!(RuntimeError("Shell_NotifyIconW")));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes the tray icon data from a `HWND`'s `GWLP_USERDATA` field.
+    unsafe fn take(hwnd: HWND) -> Option<Rc<TrayIconData<W>>> {
+        match winapi::um::winuser::GetWindowLongPtrW(hwnd, winapi::um::winuser::GWLP_USERDATA) {
+            0 => None,
+            data => {
+                winapi::um::winuser::SetWindowLongPtrW(hwnd, winapi::um::winuser::GWLP_USERDATA, 0);
+                Some(Rc::from_raw(data as *const TrayIconData<W>))
+            },
+        }
+    }
+}
+
+/// Win32 tray icon type. Each icon has a hidden window which receives its notifications.
+pub struct TrayIcon<W: 'static + Clone> {
+    data: Rc<TrayIconData<W>>,
+}
+
+impl<W: 'static + Clone> TrayIcon<W> {
+    /// Returns the handle of the hidden window which receives the icon's notifications.
+    pub fn hwnd(&self) -> HWND {
+        self.data.hwnd.get()
+    }
+}
+
+impl<W: 'static + Clone> TrayIcon<W> {
+    /// Creates a tray icon and adds it to the notification area.
+    pub(crate) fn new(client: &Client<W>, id: W, icon: &Icon) -> Result<TrayIcon<W>> {
+        let hicon = create_icon(icon.size(), None, icon.pixels())?;
+        let hinstance = ffi::win32::get_exe_handle()?;
+        let taskbar_created: Vec<u16> = "TaskbarCreated".encode_utf16()
+                                                        .chain(std::iter::repeat(0).take(1))
+                                                        .collect();
+        let title = [0u16];
+
+        unsafe {
+            // The window isn't message-only because those don't receive the `TaskbarCreated`
+            // broadcast.
+            let hwnd = winapi::um::winuser::CreateWindowExW(
+                0, client.window_class_name().as_ptr(), title.as_ptr(),
+                winapi::um::winuser::WS_POPUP, 0, 0, 0, 0, std::ptr::null_mut(),
+                std::ptr::null_mut(), hinstance, std::ptr::null_mut());
+            if hwnd.is_null() {
+                let err = err!(RuntimeError("CreateWindowExW"): ??w);
+                winapi::um::winuser::DestroyIcon(hicon);
+                return Err(err);
+            }
+
+            let data = Rc::new(TrayIconData {
+                event_manager: client.event_manager().clone(),
+                hicon: Cell::new(hicon),
+                hwnd: Cell::new(hwnd),
+                id,
+                taskbar_created: winapi::um::winuser::RegisterWindowMessageW(
+                    taskbar_created.as_ptr()),
+                tooltip: RefCell::new(Vec::new()),
+            });
+            winapi::um::winuser::SetWindowLongPtrW(hwnd, winapi::um::winuser::GWLP_USERDATA,
+                                                   Rc::into_raw(data.clone()) as isize);
+            winapi::um::winuser::SetWindowLongPtrW(hwnd, winapi::um::winuser::GWLP_WNDPROC,
+                                                   tray_proc::<W> as usize as isize);
+
+            let tray_icon = TrayIcon { data };
+            tray_icon.data.notify(winapi::um::shellapi::NIM_ADD)?;
+            Ok(tray_icon)
+        }
+    }
+}
+
+impl<W: 'static + Clone> Drop for TrayIcon<W> {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+impl<W: 'static + Clone> ITrayIcon for TrayIcon<W> {
+    type Client = Client<W>;
+
+    fn id(&self) -> &W {
+        &self.data.id
+    }
+
+    fn remove(&self) {
+        let hwnd = self.hwnd();
+        if !hwnd.is_null() {
+            unsafe {
+                let _ = self.data.notify(winapi::um::shellapi::NIM_DELETE);
+                winapi::um::winuser::DestroyWindow(hwnd);
+            }
+        }
+    }
+
+    fn set_icon(&self, icon: &Icon) -> Result<()> {
+        if self.hwnd().is_null() {
+            return Err(err!(ResourceExpired("tray icon removed")));
+        }
+
+        let hicon = create_icon(icon.size(), None, icon.pixels())?;
+        unsafe {
+            winapi::um::winuser::DestroyIcon(self.data.hicon.replace(hicon));
+            self.data.notify(winapi::um::shellapi::NIM_MODIFY)
+        }
+    }
+
+    fn set_tooltip(&self, tooltip: &str) -> Result<()> {
+        if self.hwnd().is_null() {
+            return Err(err!(ResourceExpired("tray icon removed")));
+        }
+
+        *self.data.tooltip.borrow_mut() = tooltip.encode_utf16().collect();
+        unsafe {
+            self.data.notify(winapi::um::shellapi::NIM_MODIFY)
+        }
+    }
+}
+
+/// Message handler for tray icon windows.
+unsafe extern "system" fn tray_proc<W: 'static + Clone>(
+    hwnd: HWND, msg: u32, wparam: usize, lparam: isize) -> isize
+{
+    match msg {
+        winapi::um::winuser::WM_DESTROY => {
+            if let Some(data) = TrayIconData::<W>::take(hwnd) {
+                data.hwnd.set(std::ptr::null_mut());
+                winapi::um::winuser::DestroyIcon(data.hicon.get());
+            }
+            0
+        },
+
+        WM_TRAY_CALLBACK => {
+            if let Some(data) = TrayIconData::<W>::get(hwnd) {
+                match (lparam & 0xffff) as u32 {
+                    winapi::um::shellapi::NIN_KEYSELECT | winapi::um::shellapi::NIN_SELECT => {
+                        data.event_manager.push(Event::TrayActivate {
+                            tray_id: data.id.clone(),
+                        });
+                    },
+                    winapi::um::winuser::WM_CONTEXTMENU => {
+                        data.event_manager.push(Event::TrayMenuRequest {
+                            tray_id: data.id.clone(),
+                            pos: Vec2::new((wparam & 0xffff) as i16 as i32,
+                                           (wparam >> 16 & 0xffff) as i16 as i32),
+                        });
+                    },
+                    _ => (),
+                }
+            }
+            0
+        },
+
+        // The taskbar was recreated, usually because Explorer restarted, so the icon must be
+        // added again.
+        _ => match TrayIconData::<W>::get(hwnd) {
+            Some(data) if data.taskbar_created != 0 && msg == data.taskbar_created => {
+                let _ = data.notify(winapi::um::shellapi::NIM_ADD);
+                0
+            },
+            _ => winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam),
+        },
+    }
+}
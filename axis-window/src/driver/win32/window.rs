@@ -7,40 +7,90 @@
  */
 
 use std::any::TypeId;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::mem::MaybeUninit;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 use vectorial::Vec2;
-use winapi::shared::windef::HWND;
-use winapi::um::winuser::WNDCLASSEXW;
+use winapi::shared::minwindef::BOOL;
+use winapi::shared::windef::{HWND, POINT, RECT};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::dwmapi::DWM_BLURBEHIND;
+use winapi::um::imm::COMPOSITIONFORM;
+use winapi::um::shobjidl_core::ITaskbarList3;
+use winapi::um::winuser::{
+    HRAWINPUT,
+    MINMAXINFO,
+    RAWINPUT,
+    RAWINPUTDEVICE,
+    RAWINPUTHEADER,
+    WNDCLASSEXW,
+};
 
+use crate::cursor::Cursor;
 use crate::driver::win32::client::{Client, EventManager};
+use crate::driver::win32::cursor::CursorHandle;
+use crate::driver::win32::pixel_format::PixelFormat;
 use crate::error::Result;
-use crate::event::Event;
+use crate::event::{Event, MainLoop};
 use crate::ffi;
-use crate::window::{IWindow, IWindowBuilder};
+use crate::key::Key;
+use crate::monitor::Monitor;
+use crate::window::{
+    AttentionKind,
+    ClosePolicy,
+    IWindow,
+    IWindowBuilder,
+    ResizeEdge,
+    Theme,
+    WindowHandle,
+    WindowType,
+};
 use crate::Coord;
 
 /// Win32 window builder.
 pub struct WindowBuilder<W: 'static + Clone> {
+    aspect_ratio: Option<Vec2<Coord>>,
     class_name: Rc<Vec<u16>>,
+    close_policy: ClosePolicy,
     event_manager: Rc<EventManager<W>>,
+    manager: Rc<WindowManager<W>>,
+    max_size: Option<Vec2<Coord>>,
+    min_size: Option<Vec2<Coord>>,
+    modal: bool,
+    owner: Option<Rc<WindowData<W>>>,
+    pixel_format: Option<PixelFormat>,
+    popup: bool,
     pos: Option<Vec2<Coord>>,
     size: Option<Vec2<Coord>>,
     title: String,
+    transparent: bool,
+    window_type: Option<WindowType>,
 }
 
 impl<W: 'static + Clone> WindowBuilder<W> {
     /// Constructs a window builder.
     pub(crate) fn new(client: &Client<W>) -> WindowBuilder<W> {
         WindowBuilder {
+            aspect_ratio: None,
             class_name: client.window_class_name().clone(),
+            close_policy: ClosePolicy::Defer,
             event_manager: client.event_manager().clone(),
+            manager: client.window_manager().clone(),
+            max_size: None,
+            min_size: None,
+            modal: false,
+            owner: None,
+            pixel_format: None,
+            popup: false,
             pos: None,
             size: None,
             title: String::new(),
+            transparent: false,
+            window_type: None,
         }
     }
 }
@@ -51,16 +101,132 @@ impl<W: 'static + Clone> IWindowBuilder for WindowBuilder<W> {
     fn build(&self, id: W) -> Result<Window<W>> {
         Window::new(self, id)
     }
+
+    fn with_aspect_ratio(&mut self, ratio: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.aspect_ratio = Some(ratio);
+        self
+    }
+
+    fn with_close_policy(&mut self, policy: ClosePolicy) -> &mut WindowBuilder<W> {
+        self.close_policy = policy;
+        self
+    }
+
+    fn with_max_size(&mut self, size: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.max_size = Some(size);
+        self
+    }
+
+    fn with_min_size(&mut self, size: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.min_size = Some(size);
+        self
+    }
+
+    fn with_owner(&mut self, owner: &Window<W>, modal: bool) -> &mut WindowBuilder<W> {
+        self.modal = modal;
+        self.owner = Some(owner.data.clone());
+        self
+    }
+
+    fn with_pixel_format(&mut self, pixel_format: &PixelFormat) -> &mut WindowBuilder<W> {
+        self.pixel_format = Some(pixel_format.clone());
+        self
+    }
+
+    fn with_popup(&mut self, popup: bool) -> &mut WindowBuilder<W> {
+        self.popup = popup;
+        self
+    }
+
+    fn with_pos(&mut self, pos: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.pos = Some(pos);
+        self
+    }
+
+    fn with_size(&mut self, size: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.size = Some(size);
+        self
+    }
+
+    fn with_title(&mut self, title: &str) -> &mut WindowBuilder<W> {
+        self.title = title.to_owned();
+        self
+    }
+
+    fn with_transparency(&mut self, transparent: bool) -> &mut WindowBuilder<W> {
+        self.transparent = transparent;
+        self
+    }
+
+    fn with_window_type(&mut self, window_type: WindowType) -> &mut WindowBuilder<W> {
+        self.window_type = Some(window_type);
+        self
+    }
 }
 
 /// Data shared between an `HWND` and a [Window].
 struct WindowData<W: 'static + Clone> {
+    aspect_ratio: Cell<Option<Vec2<Coord>>>,
+    close_policy: ClosePolicy,
+    confine_cursor: Cell<bool>,
+    cursor: RefCell<Option<CursorHandle>>,
+    cursor_visible: Cell<bool>,
     event_manager: Rc<EventManager<W>>,
+    handle_target: u64,
     hwnd: Cell<HWND>,
     id: W,
+    max_size: Cell<Option<Vec2<Coord>>>,
+    min_size: Cell<Option<Vec2<Coord>>>,
+    modal_owner: Cell<HWND>,
+    pointer_locked: Cell<bool>,
+    restore: Cell<Option<(u32, RECT)>>,
+    theme: Cell<Option<Theme>>,
 }
 
 impl<W: 'static + Clone> WindowData<W> {
+    /// Switches the title bar between light and dark to match the window's theme, or the
+    /// system's preferred theme if the window doesn't have one.
+    unsafe fn apply_theme(&self) -> Result<()> {
+        // The attribute was documented as 20 in Windows 10 20H1. Earlier builds used 19.
+        const DWMWA_USE_IMMERSIVE_DARK_MODE: u32 = 20;
+        const DWMWA_USE_IMMERSIVE_DARK_MODE_BEFORE_20H1: u32 = 19;
+
+        let dark: BOOL = match self.theme.get().or_else(query_theme) {
+            Some(Theme::Dark) => 1,
+            _ => 0,
+        };
+        for &attribute in &[DWMWA_USE_IMMERSIVE_DARK_MODE,
+                            DWMWA_USE_IMMERSIVE_DARK_MODE_BEFORE_20H1]
+        {
+            if winapi::um::dwmapi::DwmSetWindowAttribute(
+                self.hwnd.get(), attribute, &dark as *const BOOL as *const _,
+                std::mem::size_of::<BOOL>() as u32) >= 0
+            {
+                return Ok(());
+            }
+        }
+
+        // Title bars are always light on versions of Windows without the attribute.
+        if dark == 0 {
+            return Ok(());
+        }
+        Err(err!(Unsupported("dark title bars")))
+    }
+
+    /// Re-enables the owner of a modal window. This should happen before the window is destroyed
+    /// so that Windows activates the owner rather than some other application.
+    unsafe fn enable_owner(&self) {
+        let owner = self.modal_owner.replace(std::ptr::null_mut());
+        if !owner.is_null() {
+            winapi::um::winuser::EnableWindow(owner, 1);
+        }
+    }
+
+    /// Returns true if the cursor should be clipped to the window while it's active.
+    fn is_cursor_confined(&self) -> bool {
+        self.confine_cursor.get() || self.pointer_locked.get()
+    }
+
     /// Gets a `WindowData` from a `HWND`.
     unsafe fn get<'a>(hwnd: HWND) -> Option<&'a WindowData<W>> {
         match winapi::um::winuser::GetWindowLongPtrW(hwnd, winapi::um::winuser::GWLP_USERDATA) {
@@ -103,6 +269,107 @@ impl<W: 'static + Clone> Window<W> {
 }
 
 impl<W: 'static + Clone> Window<W> {
+    /// Returns the target ID of the window's handles. An `HWND` may be reused after its window is
+    /// destroyed, so it can't serve as the target.
+    pub(crate) fn handle_target(&self) -> u64 {
+        self.data.handle_target
+    }
+}
+
+impl<W: 'static + Clone> Window<W> {
+    /// Destroys a window which failed to initialize in [new](Window::new). Its data is taken from
+    /// the `HWND` first, so no `Destroy` event is reported for a window the caller never got.
+    fn abandon(&self, manager: &WindowManager<W>) {
+        let hwnd = self.data.hwnd.replace(std::ptr::null_mut());
+        unsafe {
+            WindowData::<W>::take(hwnd);
+            winapi::um::winuser::DestroyWindow(hwnd);
+        }
+        manager.unregister(&self.data);
+    }
+
+    /// Clips the cursor to the window if it should be confined, or releases a previous clip.
+    fn apply_cursor_clip(&self, was_confined: bool) -> Result<()> {
+        let hwnd = self.try_hwnd()?;
+
+        unsafe {
+            if self.data.is_cursor_confined() {
+                update_cursor_clip(hwnd);
+            } else if was_confined && winapi::um::winuser::GetForegroundWindow() == hwnd {
+                winapi::um::winuser::ClipCursor(std::ptr::null());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resizes the window to its current size so that the window procedure gets a chance to
+    /// enforce updated size constraints.
+    fn apply_size_constraints(&self) -> Result<()> {
+        let rect = self.window_rect()?;
+        self.set_window_pos(Vec2::new(0, 0),
+                            Vec2::new(rect.right - rect.left, rect.bottom - rect.top),
+                            winapi::um::winuser::SWP_NOACTIVATE
+                            | winapi::um::winuser::SWP_NOMOVE
+                            | winapi::um::winuser::SWP_NOZORDER)
+    }
+
+    /// Starts a system move or resize loop by simulating a mouse press on part of the window
+    /// frame. `hit` is the `WM_NCHITTEST` code for that part.
+    fn begin_frame_drag(&self, hit: isize) -> Result<()> {
+        let hwnd = self.try_hwnd()?;
+
+        unsafe {
+            let mut point = POINT { x: 0, y: 0 };
+            if winapi::um::winuser::GetCursorPos(&mut point) == 0 {
+                return Err(err!(RuntimeError("GetCursorPos"): ??w));
+            }
+
+            // The window captured the mouse when the button was pressed, which would keep the
+            // move or resize loop from tracking it.
+            winapi::um::winuser::ReleaseCapture();
+            if winapi::um::winuser::PostMessageW(
+                hwnd, winapi::um::winuser::WM_NCLBUTTONDOWN, hit as usize,
+                (point.x & 0xffff | (point.y & 0xffff) << 16) as isize) == 0
+            {
+                return Err(err!(RuntimeError("PostMessageW"): ??w));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lets the window's alpha channel show through to the desktop. DWM blends the client area
+    /// using per-pixel alpha when blur-behind is enabled with an empty region.
+    fn enable_transparency(&self) -> Result<()> {
+        let hwnd = self.try_hwnd()?;
+
+        unsafe {
+            let mut enabled = 0;
+            if winapi::um::dwmapi::DwmIsCompositionEnabled(&mut enabled) < 0 || enabled == 0 {
+                return Err(err!(Unsupported("desktop composition is disabled")));
+            }
+
+            let region = winapi::um::wingdi::CreateRectRgn(0, 0, -1, -1);
+            if region.is_null() {
+                return Err(err!(RuntimeError("CreateRectRgn"): ??w));
+            }
+            let blur_behind = DWM_BLURBEHIND {
+                dwFlags: winapi::um::dwmapi::DWM_BB_ENABLE | winapi::um::dwmapi::DWM_BB_BLURREGION,
+                fEnable: 1,
+                hRgnBlur: region,
+                fTransitionOnMaximized: 0,
+            };
+            let result = winapi::um::dwmapi::DwmEnableBlurBehindWindow(hwnd, &blur_behind);
+            winapi::um::wingdi::DeleteObject(region as *mut _);
+            if result < 0 {
+                return Err(err!(RuntimeError{"DwmEnableBlurBehindWindow failed: {:#x}", result}));
+            }
+        }
+
+        Ok(())
+    }
+
     fn get_style(&self) -> Result<u32> {
         Ok(self.get_window_long(winapi::um::winuser::GWL_STYLE)? as u32)
     }
@@ -118,21 +385,87 @@ impl<W: 'static + Clone> Window<W> {
         }
     }
 
+    /// Attaches the window data to a newly created `HWND`, registers the window and applies the
+    /// builder's settings. The caller destroys the window if this fails.
+    fn init(&self, builder: &WindowBuilder<W>) -> Result<()> {
+        let hwnd = self.hwnd();
+
+        unsafe {
+            let data_ptr = Rc::into_raw(self.data.clone());
+            winapi::um::errhandlingapi::SetLastError(0);
+            winapi::um::winuser::SetWindowLongPtrW(hwnd, winapi::um::winuser::GWLP_USERDATA,
+                                                   data_ptr as isize);
+
+            if let Some(err) = ffi::win32::Error::get() {
+                let _ = Rc::from_raw(data_ptr);
+                return Err(err!(RuntimeError("SetWindowLongPtrW"): err));
+            }
+        }
+
+        builder.manager.register(&self.data);
+        self.apply_size_constraints()?;
+
+        // The frame size was computed for the system DPI, but the window may have been created
+        // on a monitor with a different DPI.
+        if let Some(size) = builder.size {
+            if ffi::win32::get_dpi_for_window(hwnd) != ffi::win32::get_dpi_for_system() {
+                self.set_size(size)?;
+            }
+        }
+        if let Some(ref pixel_format) = builder.pixel_format {
+            self.set_gdi_pixel_format(pixel_format)?;
+        }
+        if builder.transparent {
+            self.enable_transparency()?;
+        }
+        unsafe {
+            if let Err(_err) = self.data.apply_theme() {
+                warn!("can't apply theme: {}", _err);
+            }
+        }
+        Ok(())
+    }
+
     fn new(builder: &WindowBuilder<W>, id: W) -> Result<Window<W>> {
-        let style = winapi::um::winuser::WS_OVERLAPPEDWINDOW;
-        let ex_style = 0;
+        // `CW_USEDEFAULT` isn't valid for popups, so they're placed like X11 windows when no
+        // position or size is given.
+        let (style, ex_style) = window_styles(builder.popup, builder.window_type);
+        let (default_pos, default_size) = if style & winapi::um::winuser::WS_POPUP != 0 {
+            (Vec2::new(0, 0), Vec2::new(100, 100))
+        } else {
+            (Vec2::new(winapi::um::winuser::CW_USEDEFAULT, winapi::um::winuser::CW_USEDEFAULT),
+             Vec2::new(winapi::um::winuser::CW_USEDEFAULT, winapi::um::winuser::CW_USEDEFAULT))
+        };
         let class_name = builder.class_name.as_ptr();
         let title: Vec<u16> = builder.title.encode_utf16().chain(std::iter::repeat(0).take(1))
                               .collect();
-        let pos = match builder.pos {
-            None => Vec2::new(winapi::um::winuser::CW_USEDEFAULT,
-                              winapi::um::winuser::CW_USEDEFAULT),
-            Some(pos) => pos,
-        };
+        let pos = builder.pos.unwrap_or(default_pos);
         let size = match builder.size {
-            None => Vec2::new(winapi::um::winuser::CW_USEDEFAULT,
-                              winapi::um::winuser::CW_USEDEFAULT),
-            Some(size) => Vec2::new(std::cmp::max(size.x, 1), std::cmp::max(size.y, 1)),
+            None => default_size,
+            Some(size) => {
+                let mut rect = RECT {
+                    left: 0,
+                    top: 0,
+                    right: std::cmp::max(size.x, 1),
+                    bottom: std::cmp::max(size.y, 1),
+                };
+                unsafe {
+                    if winapi::um::winuser::AdjustWindowRectEx(&mut rect, style, 0, ex_style) == 0 {
+                        return Err(err!(RuntimeError("AdjustWindowRectEx"): ??w));
+                    }
+                }
+                Vec2::new(rect.right - rect.left, rect.bottom - rect.top)
+            },
+        };
+        let owner = match builder.owner {
+            None => std::ptr::null_mut(),
+            Some(ref owner) => {
+                let hwnd = owner.hwnd.get();
+                if hwnd.is_null() {
+                    return Err(err!(ResourceExpired("owner window expired")));
+                }
+                hwnd
+            },
         };
         let hinstance = ffi::win32::get_exe_handle()?;
         let hwnd;
@@ -140,8 +473,8 @@ impl<W: 'static + Clone> Window<W> {
         unsafe {
             hwnd = winapi::um::winuser::CreateWindowExW(ex_style, class_name, title.as_ptr(),
                                                         style, pos.x, pos.y, size.x, size.y,
-                                                        std::ptr::null_mut(), std::ptr::null_mut(),
-                                                        hinstance, std::ptr::null_mut());
+                                                        owner, std::ptr::null_mut(), hinstance,
+                                                        std::ptr::null_mut());
         }
 
         if hwnd.is_null() {
@@ -149,41 +482,185 @@ impl<W: 'static + Clone> Window<W> {
         }
 
         let data = Rc::new(WindowData {
+            aspect_ratio: Cell::new(builder.aspect_ratio),
+            close_policy: builder.close_policy,
+            confine_cursor: Cell::new(false),
+            cursor: RefCell::new(None),
+            cursor_visible: Cell::new(true),
             event_manager: builder.event_manager.clone(),
+            handle_target: WindowHandle::next_target(),
             hwnd: Cell::new(hwnd),
             id,
+            max_size: Cell::new(builder.max_size),
+            min_size: Cell::new(builder.min_size),
+            modal_owner: Cell::new(std::ptr::null_mut()),
+            pointer_locked: Cell::new(false),
+            restore: Cell::new(None),
+            theme: Cell::new(None),
         });
 
+        let window = Window {
+            data,
+        };
+        if let Err(err) = window.init(builder) {
+            window.abandon(&builder.manager);
+            return Err(err);
+        }
+        if builder.modal && !owner.is_null() {
+            unsafe {
+                winapi::um::winuser::EnableWindow(owner, 0);
+            }
+            window.data.modal_owner.set(owner);
+        }
+        Ok(window)
+    }
+
+    /// Updates the cursor immediately if it's over the window. Windows otherwise only updates
+    /// the cursor when it moves.
+    fn refresh_cursor(&self) -> Result<()> {
+        let hwnd = self.try_hwnd()?;
+
         unsafe {
-            let data_ptr = Rc::into_raw(data.clone());
-            winapi::um::errhandlingapi::SetLastError(0);
-            winapi::um::winuser::SetWindowLongPtrW(hwnd, winapi::um::winuser::GWLP_USERDATA,
-                                                   data_ptr as isize);
+            let mut point = POINT { x: 0, y: 0 };
+            if winapi::um::winuser::GetCursorPos(&mut point) != 0
+                && winapi::um::winuser::WindowFromPoint(point) == hwnd
+            {
+                let hit = winapi::um::winuser::SendMessageW(
+                    hwnd, winapi::um::winuser::WM_NCHITTEST, 0,
+                    (point.x & 0xffff | (point.y & 0xffff) << 16) as isize);
+                winapi::um::winuser::SendMessageW(
+                    hwnd, winapi::um::winuser::WM_SETCURSOR, hwnd as usize,
+                    (hit & 0xffff) | (winapi::um::winuser::WM_MOUSEMOVE as isize) << 16);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies a GDI pixel format to the window so that OpenGL contexts can be created for it.
+    /// Windows only allow this once per window.
+    fn set_gdi_pixel_format(&self, pixel_format: &PixelFormat) -> Result<()> {
+        let (index, pfd) = match (pixel_format.gdi_index(), pixel_format.gdi_descriptor()) {
+            (Some(index), Some(pfd)) => (index, pfd),
+            _ => return Ok(()),
+        };
+        let hwnd = self.try_hwnd()?;
+
+        unsafe {
+            let hdc = winapi::um::winuser::GetDC(hwnd);
+            if hdc.is_null() {
+                return Err(err!(RuntimeError("GetDC")));
+            }
 
+            let result = match winapi::um::wingdi::SetPixelFormat(hdc, index, pfd) {
+                0 => Err(err!(RuntimeError("SetPixelFormat"): ??w)),
+                _ => Ok(()),
+            };
+            winapi::um::winuser::ReleaseDC(hwnd, hdc);
+            result
+        }
+    }
+
+    fn set_style(&self, style: u32) -> Result<()> {
+        self.set_window_long(winapi::um::winuser::GWL_STYLE, style as i32)
+    }
+
+    fn set_window_long(&self, index: i32, value: i32) -> Result<()> {
+        unsafe {
+            winapi::um::errhandlingapi::SetLastError(0);
+            winapi::um::winuser::SetWindowLongW(self.try_hwnd()?, index, value);
             if let Some(err) = ffi::win32::Error::get() {
-                let _ = Rc::from_raw(data_ptr);
-                return Err(err!(RuntimeError("SetWindowLongPtrW"): err));
+                return Err(err!(RuntimeError("SetWindowLongW"): err));
             }
         }
 
-        Ok(Window {
-            data,
-        })
+        Ok(())
+    }
+
+    fn set_window_pos(&self, pos: Vec2<Coord>, size: Vec2<Coord>, flags: u32) -> Result<()> {
+        unsafe {
+            if winapi::um::winuser::SetWindowPos(self.try_hwnd()?, std::ptr::null_mut(), pos.x,
+                                                 pos.y, size.x, size.y, flags) == 0
+            {
+                return Err(err!(RuntimeError("SetWindowPos"): ??w));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn window_rect(&self) -> Result<RECT> {
+        unsafe {
+            let mut rect = MaybeUninit::<RECT>::zeroed().assume_init();
+            if winapi::um::winuser::GetWindowRect(self.try_hwnd()?, &mut rect) == 0 {
+                return Err(err!(RuntimeError("GetWindowRect"): ??w));
+            }
+            Ok(rect)
+        }
     }
 }
 
 impl<W: 'static + Clone> IWindow for Window<W> {
     type Client = Client<W>;
 
+    fn begin_move_drag(&self) -> Result<()> {
+        self.begin_frame_drag(winapi::um::winuser::HTCAPTION)
+    }
+
+    fn begin_resize_drag(&self, edge: ResizeEdge) -> Result<()> {
+        self.begin_frame_drag(match edge {
+            ResizeEdge::Bottom => winapi::um::winuser::HTBOTTOM,
+            ResizeEdge::BottomLeft => winapi::um::winuser::HTBOTTOMLEFT,
+            ResizeEdge::BottomRight => winapi::um::winuser::HTBOTTOMRIGHT,
+            ResizeEdge::Left => winapi::um::winuser::HTLEFT,
+            ResizeEdge::Right => winapi::um::winuser::HTRIGHT,
+            ResizeEdge::Top => winapi::um::winuser::HTTOP,
+            ResizeEdge::TopLeft => winapi::um::winuser::HTTOPLEFT,
+            ResizeEdge::TopRight => winapi::um::winuser::HTTOPRIGHT,
+        })
+    }
+
+    fn close(&self) -> Result<()> {
+        unsafe {
+            if winapi::um::winuser::PostMessageW(self.try_hwnd()?, winapi::um::winuser::WM_CLOSE,
+                                                 0, 0) == 0
+            {
+                return Err(err!(RuntimeError("PostMessageW"): ??w));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn confine_cursor(&self, confine: bool) -> Result<()> {
+        self.try_hwnd()?;
+        let was_confined = self.data.is_cursor_confined();
+        self.data.confine_cursor.set(confine);
+        self.apply_cursor_clip(was_confined)
+    }
+
     fn destroy(&self) {
         let hwnd = self.hwnd();
         if !hwnd.is_null() {
             unsafe {
+                self.data.enable_owner();
                 winapi::um::winuser::DestroyWindow(hwnd);
             }
         }
     }
 
+    fn dpi(&self) -> u32 {
+        match self.try_hwnd() {
+            Ok(hwnd) => ffi::win32::get_dpi_for_window(hwnd),
+            Err(_) => 96,
+        }
+    }
+
+    fn handle(&self, main_loop: &MainLoop) -> Result<WindowHandle> {
+        self.try_hwnd()?;
+        Ok(main_loop.window_handle(self.data.handle_target))
+    }
+
     fn id(&self) -> &W {
         &self.data.id
     }
@@ -195,6 +672,247 @@ impl<W: 'static + Clone> IWindow for Window<W> {
         }
     }
 
+    fn pos(&self) -> Result<Vec2<Coord>> {
+        let rect = self.window_rect()?;
+        Ok(Vec2::new(rect.left, rect.top))
+    }
+
+    fn request_user_attention(&self, kind: Option<AttentionKind>) -> Result<()> {
+        // Both kinds flash until the window comes to the foreground. Critical requests also flash
+        // the caption.
+        let flags = match kind {
+            None => winapi::um::winuser::FLASHW_STOP,
+            Some(AttentionKind::Critical) => {
+                winapi::um::winuser::FLASHW_ALL | winapi::um::winuser::FLASHW_TIMERNOFG
+            },
+            Some(AttentionKind::Informational) => {
+                winapi::um::winuser::FLASHW_TRAY | winapi::um::winuser::FLASHW_TIMERNOFG
+            },
+        };
+        let mut info = winapi::um::winuser::FLASHWINFO {
+            cbSize: std::mem::size_of::<winapi::um::winuser::FLASHWINFO>() as u32,
+            hwnd: self.try_hwnd()?,
+            dwFlags: flags,
+            uCount: 0,
+            dwTimeout: 0,
+        };
+
+        // The return value is the previous flash state rather than an error indicator.
+        unsafe {
+            winapi::um::winuser::FlashWindowEx(&mut info);
+        }
+
+        Ok(())
+    }
+
+    fn request_redraw(&self) -> Result<()> {
+        unsafe {
+            if winapi::um::winuser::InvalidateRect(self.try_hwnd()?, std::ptr::null(), 0) == 0 {
+                return Err(err!(RuntimeError("InvalidateRect"): ??w));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_aspect_ratio(&self, ratio: Option<Vec2<Coord>>) -> Result<()> {
+        self.data.aspect_ratio.set(ratio);
+        self.apply_size_constraints()
+    }
+
+    fn set_cursor(&self, cursor: &Cursor) -> Result<()> {
+        self.try_hwnd()?;
+        *self.data.cursor.borrow_mut() = Some(CursorHandle::new(cursor)?);
+        self.refresh_cursor()
+    }
+
+    fn set_cursor_pos(&self, pos: Vec2<Coord>) -> Result<()> {
+        let mut point = POINT { x: pos.x, y: pos.y };
+
+        unsafe {
+            if winapi::um::winuser::ClientToScreen(self.try_hwnd()?, &mut point) == 0 {
+                return Err(err!(RuntimeError("ClientToScreen")));
+            }
+            if winapi::um::winuser::SetCursorPos(point.x, point.y) == 0 {
+                return Err(err!(RuntimeError("SetCursorPos"): ??w));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_cursor_visible(&self, visible: bool) -> Result<()> {
+        self.try_hwnd()?;
+        self.data.cursor_visible.set(visible);
+        self.refresh_cursor()
+    }
+
+    fn set_fullscreen(&self, monitor: Option<&Monitor>) -> Result<()> {
+        let flags = winapi::um::winuser::SWP_FRAMECHANGED
+                    | winapi::um::winuser::SWP_NOOWNERZORDER
+                    | winapi::um::winuser::SWP_NOZORDER;
+
+        match monitor {
+            Some(monitor) => {
+                let style = self.get_style()?;
+                if self.data.restore.get().is_none() {
+                    self.data.restore.set(Some((style, self.window_rect()?)));
+                }
+                self.set_style(style & !winapi::um::winuser::WS_OVERLAPPEDWINDOW
+                               | winapi::um::winuser::WS_POPUP)?;
+                self.set_window_pos(monitor.pos(), monitor.size(), flags)
+            },
+            None => match self.data.restore.take() {
+                None => Ok(()),
+                Some((style, rect)) => {
+                    self.set_style(style)?;
+                    self.set_window_pos(Vec2::new(rect.left, rect.top),
+                                        Vec2::new(rect.right - rect.left, rect.bottom - rect.top),
+                                        flags)
+                },
+            },
+        }
+    }
+
+    fn set_ime_position(&self, pos: Vec2<Coord>) -> Result<()> {
+        let hwnd = self.try_hwnd()?;
+
+        unsafe {
+            let himc = winapi::um::imm::ImmGetContext(hwnd);
+            if himc.is_null() {
+                return Ok(());
+            }
+
+            let mut form = COMPOSITIONFORM {
+                dwStyle: winapi::um::imm::CFS_POINT,
+                ptCurrentPos: POINT { x: pos.x, y: pos.y },
+                rcArea: RECT { left: 0, top: 0, right: 0, bottom: 0 },
+            };
+            let result = winapi::um::imm::ImmSetCompositionWindow(himc, &mut form);
+            winapi::um::imm::ImmReleaseContext(hwnd, himc);
+            if result == 0 {
+                return Err(err!(RuntimeError("ImmSetCompositionWindow"): ??w));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_max_size(&self, size: Option<Vec2<Coord>>) -> Result<()> {
+        self.data.max_size.set(size);
+        self.apply_size_constraints()
+    }
+
+    fn set_min_size(&self, size: Option<Vec2<Coord>>) -> Result<()> {
+        self.data.min_size.set(size);
+        self.apply_size_constraints()
+    }
+
+    fn set_opacity(&self, opacity: f32) -> Result<()> {
+        let hwnd = self.try_hwnd()?;
+        let ex_style = self.get_window_long(winapi::um::winuser::GWL_EXSTYLE)? as u32;
+        let alpha = opacity_to_alpha(opacity)?;
+
+        // Layered windows are composited through an offscreen buffer, so the style is only kept
+        // while the window is actually translucent.
+        if alpha == 255 {
+            return self.set_window_long(winapi::um::winuser::GWL_EXSTYLE,
+                                        (ex_style & !winapi::um::winuser::WS_EX_LAYERED) as i32);
+        }
+
+        self.set_window_long(winapi::um::winuser::GWL_EXSTYLE,
+                             (ex_style | winapi::um::winuser::WS_EX_LAYERED) as i32)?;
+        unsafe {
+            if winapi::um::winuser::SetLayeredWindowAttributes(
+                hwnd, 0, alpha, winapi::um::winuser::LWA_ALPHA) == 0
+            {
+                return Err(err!(RuntimeError("SetLayeredWindowAttributes"): ??w));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_pointer_locked(&self, locked: bool) -> Result<()> {
+        self.try_hwnd()?;
+        if self.data.pointer_locked.get() == locked {
+            return Ok(());
+        }
+
+        unsafe {
+            if !update_raw_mouse(locked) {
+                return Err(err!(RuntimeError("RegisterRawInputDevices"): ??w));
+            }
+        }
+
+        let was_confined = self.data.is_cursor_confined();
+        self.data.pointer_locked.set(locked);
+        self.apply_cursor_clip(was_confined)?;
+        self.refresh_cursor()
+    }
+
+    fn set_pos(&self, pos: Vec2<Coord>) -> Result<()> {
+        self.set_window_pos(pos, Vec2::new(0, 0),
+                            winapi::um::winuser::SWP_NOACTIVATE
+                            | winapi::um::winuser::SWP_NOSIZE
+                            | winapi::um::winuser::SWP_NOZORDER)
+    }
+
+    fn set_progress(&self, progress: Option<f32>) -> Result<()> {
+        const MAX_PROGRESS: u64 = 10000;
+
+        let hwnd = self.try_hwnd()?;
+        let progress = match progress {
+            Some(progress) if progress.is_nan() => {
+                return Err(err!(InvalidArgument("progress is NaN")));
+            },
+            Some(progress) => Some((progress.clamp(0.0, 1.0) * MAX_PROGRESS as f32).round() as u64),
+            None => None,
+        };
+
+        unsafe {
+            with_taskbar_list(|list| match progress {
+                Some(progress) => {
+                    let hr = list.SetProgressState(hwnd, winapi::um::shobjidl_core::TBPF_NORMAL);
+                    if hr < 0 {
+                        return hr;
+                    }
+                    list.SetProgressValue(hwnd, progress, MAX_PROGRESS)
+                },
+                None => list.SetProgressState(hwnd, winapi::um::shobjidl_core::TBPF_NOPROGRESS),
+            })
+        }
+    }
+
+    fn set_size(&self, size: Vec2<Coord>) -> Result<()> {
+        let size = Vec2::new(std::cmp::max(size.x, 1), std::cmp::max(size.y, 1));
+        let size = unsafe { client_to_window_size(self.try_hwnd()?, size) };
+        self.set_window_pos(Vec2::new(0, 0), size,
+                            winapi::um::winuser::SWP_NOACTIVATE
+                            | winapi::um::winuser::SWP_NOMOVE
+                            | winapi::um::winuser::SWP_NOZORDER)
+    }
+
+    fn set_theme(&self, theme: Option<Theme>) -> Result<()> {
+        self.try_hwnd()?;
+        self.data.theme.set(theme);
+        unsafe {
+            self.data.apply_theme()
+        }
+    }
+
+    fn set_title(&self, title: &str) -> Result<()> {
+        let title: Vec<u16> = title.encode_utf16().chain(std::iter::repeat(0).take(1)).collect();
+
+        unsafe {
+            if winapi::um::winuser::SetWindowTextW(self.try_hwnd()?, title.as_ptr()) == 0 {
+                return Err(err!(RuntimeError("SetWindowTextW"): ??w));
+            }
+        }
+
+        Ok(())
+    }
+
     fn set_visible(&self, visible: bool) -> Result<()> {
         unsafe {
             if visible {
@@ -210,6 +928,16 @@ impl<W: 'static + Clone> IWindow for Window<W> {
 
         Ok(())
     }
+
+    fn size(&self) -> Result<Vec2<Coord>> {
+        unsafe {
+            let mut rect = MaybeUninit::<RECT>::zeroed().assume_init();
+            if winapi::um::winuser::GetClientRect(self.try_hwnd()?, &mut rect) == 0 {
+                return Err(err!(RuntimeError("GetClientRect"): ??w));
+            }
+            Ok(Vec2::new(rect.right - rect.left, rect.bottom - rect.top))
+        }
+    }
 }
 
 /// Manages window classes.
@@ -282,22 +1010,85 @@ lazy_static! {
         }));
 }
 
+/// Keeps track of the windows created by a client.
+pub struct WindowManager<W: 'static + Clone> {
+    windows: RefCell<Vec<Weak<WindowData<W>>>>,
+}
+
+impl<W: 'static + Clone> WindowManager<W> {
+    /// Constructs a window manager.
+    pub fn new() -> WindowManager<W> {
+        WindowManager {
+            windows: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Returns handles to all live windows.
+    pub fn windows(&self) -> Vec<Window<W>> {
+        self.windows.borrow()
+            .iter()
+            .filter_map(|data| data.upgrade())
+            .filter(|data| !data.hwnd.get().is_null())
+            .map(|data| Window { data })
+            .collect()
+    }
+}
+
+impl<W: 'static + Clone> WindowManager<W> {
+    /// Registers a window, forgetting any which have been destroyed since.
+    fn register(&self, data: &Rc<WindowData<W>>) {
+        let mut windows = self.windows.borrow_mut();
+        windows.retain(|data| data.upgrade().map_or(false, |data| !data.hwnd.get().is_null()));
+        windows.push(Rc::downgrade(data));
+    }
+
+    /// Forgets a window which was destroyed before it was returned from
+    /// [build](IWindowBuilder::build).
+    fn unregister(&self, data: &Rc<WindowData<W>>) {
+        self.windows.borrow_mut().retain(|weak| weak.as_ptr() != Rc::as_ptr(data));
+    }
+}
+
 /// Window message handler.
 unsafe extern "system" fn window_proc<W: 'static + Clone>(
     hwnd: HWND, msg: u32, wparam: usize, lparam: isize) -> isize
 {
     match msg {
+        winapi::um::winuser::WM_ACTIVATE => {
+            // The cursor clip rectangle is shared by the whole desktop, so it's only held while
+            // the window is active.
+            if WindowData::<W>::get(hwnd).map(|w| w.is_cursor_confined()).unwrap_or(false) {
+                if wparam & 0xffff == winapi::um::winuser::WA_INACTIVE as usize {
+                    winapi::um::winuser::ClipCursor(std::ptr::null());
+                } else {
+                    update_cursor_clip(hwnd);
+                }
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
         winapi::um::winuser::WM_CLOSE => {
             if let Some(window) = WindowData::<W>::get(hwnd) {
                 window.event_manager.push(Event::CloseRequest {
                     window_id: window.id.clone(),
                 });
+                if window.close_policy == ClosePolicy::Destroy {
+                    window.enable_owner();
+                    winapi::um::winuser::DestroyWindow(hwnd);
+                }
             }
             0
         },
 
         winapi::um::winuser::WM_DESTROY => {
             if let Some(window) = WindowData::<W>::take(hwnd) {
+                window.enable_owner();
+                if window.is_cursor_confined() {
+                    winapi::um::winuser::ClipCursor(std::ptr::null());
+                }
+                if window.pointer_locked.get() {
+                    update_raw_mouse(false);
+                }
                 window.hwnd.set(std::ptr::null_mut());
                 window.event_manager.push(Event::Destroy {
                     window_id: window.id.clone(),
@@ -306,6 +1097,228 @@ unsafe extern "system" fn window_proc<W: 'static + Clone>(
             0
         },
 
+        winapi::um::winuser::WM_DEVICECHANGE => {
+            // Raw input devices being added or removed is only announced as a change to the
+            // device tree.
+            if wparam == winapi::um::dbt::DBT_DEVNODES_CHANGED {
+                if let Some(window) = WindowData::<W>::get(hwnd) {
+                    window.event_manager.push_device_changes();
+                }
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_DPICHANGED => {
+            // Windows suggests a new window rectangle which preserves the window's apparent size.
+            let rect = &*(lparam as *const RECT);
+            winapi::um::winuser::SetWindowPos(hwnd, std::ptr::null_mut(), rect.left, rect.top,
+                                              rect.right - rect.left, rect.bottom - rect.top,
+                                              winapi::um::winuser::SWP_NOACTIVATE
+                                              | winapi::um::winuser::SWP_NOZORDER);
+            if let Some(window) = WindowData::<W>::get(hwnd) {
+                window.event_manager.push(Event::ScaleFactorChanged {
+                    window_id: window.id.clone(),
+                    dpi: (wparam & 0xffff) as u32,
+                });
+            }
+            0
+        },
+
+        winapi::um::winuser::WM_GETMINMAXINFO => {
+            if let Some(window) = WindowData::<W>::get(hwnd) {
+                let info = &mut *(lparam as *mut MINMAXINFO);
+                if let Some(size) = window.min_size.get() {
+                    let size = client_to_window_size(hwnd, size);
+                    info.ptMinTrackSize.x = size.x;
+                    info.ptMinTrackSize.y = size.y;
+                }
+                if let Some(size) = window.max_size.get() {
+                    let size = client_to_window_size(hwnd, size);
+                    info.ptMaxTrackSize.x = size.x;
+                    info.ptMaxTrackSize.y = size.y;
+                }
+            }
+            0
+        },
+
+        winapi::um::winuser::WM_IME_COMPOSITION => {
+            let mut lparam = lparam;
+            if let Some(window) = WindowData::<W>::get(hwnd) {
+                let himc = winapi::um::imm::ImmGetContext(hwnd);
+                if !himc.is_null() {
+                    let flags = lparam as u32;
+                    if flags & ffi::win32::GCS_RESULTSTR != 0 {
+                        if let Some(text) = ffi::win32::imm_get_composition_string(
+                            himc, ffi::win32::GCS_RESULTSTR)
+                        {
+                            window.event_manager.push(Event::ImeCommit {
+                                window_id: window.id.clone(),
+                                text: String::from_utf16_lossy(&text),
+                            });
+                        }
+                        // `DefWindowProcW` would otherwise deliver the result string again as
+                        // `WM_IME_CHAR` messages, committing the text twice.
+                        lparam &= !(ffi::win32::GCS_RESULTSTR as isize);
+                    }
+                    if flags & ffi::win32::GCS_COMPSTR != 0 {
+                        if let Some(text) = ffi::win32::imm_get_composition_string(
+                            himc, ffi::win32::GCS_COMPSTR)
+                        {
+                            // The cursor position is reported in UTF-16 code units.
+                            let cursor = match flags & ffi::win32::GCS_CURSORPOS {
+                                0 => None,
+                                _ => match ffi::win32::ImmGetCompositionStringW(
+                                    himc, ffi::win32::GCS_CURSORPOS, std::ptr::null_mut(), 0)
+                                {
+                                    n if n < 0 => None,
+                                    n => {
+                                        let n = std::cmp::min(n as usize, text.len());
+                                        Some(String::from_utf16_lossy(&text[..n]).len())
+                                    },
+                                },
+                            };
+                            window.event_manager.push(Event::ImePreedit {
+                                window_id: window.id.clone(),
+                                text: String::from_utf16_lossy(&text),
+                                cursor,
+                            });
+                        }
+                    }
+                    winapi::um::imm::ImmReleaseContext(hwnd, himc);
+                }
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_IME_ENDCOMPOSITION => {
+            if let Some(window) = WindowData::<W>::get(hwnd) {
+                window.event_manager.push(Event::ImePreedit {
+                    window_id: window.id.clone(),
+                    text: String::new(),
+                    cursor: None,
+                });
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_INPUT => {
+            if let Some(window) = WindowData::<W>::get(hwnd) {
+                if window.pointer_locked.get() {
+                    if let Some(delta) = raw_mouse_delta(lparam as HRAWINPUT) {
+                        window.event_manager.push(Event::MouseMotionRaw {
+                            window_id: window.id.clone(),
+                            delta,
+                        });
+                    }
+                }
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_INPUTLANGCHANGE => {
+            if let Some(window) = WindowData::<W>::get(hwnd) {
+                window.event_manager.push(Event::KeymapChanged);
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_KEYDOWN | winapi::um::winuser::WM_SYSKEYDOWN => {
+            // Bit 30 of `lparam` is set if the key was already down.
+            if let Some(window) = WindowData::<W>::get(hwnd) {
+                window.event_manager.push(Event::KeyDown {
+                    window_id: window.id.clone(),
+                    scancode: lparam_scancode(lparam),
+                    key: vk_to_key(wparam as i32),
+                    repeat: lparam & 0x40000000 != 0,
+                });
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_KEYUP | winapi::um::winuser::WM_SYSKEYUP => {
+            if let Some(window) = WindowData::<W>::get(hwnd) {
+                window.event_manager.push(Event::KeyUp {
+                    window_id: window.id.clone(),
+                    scancode: lparam_scancode(lparam),
+                    key: vk_to_key(wparam as i32),
+                });
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_MOUSEHWHEEL | winapi::um::winuser::WM_MOUSEWHEEL => {
+            // Wheel deltas are already measured in 120ths of a notch.
+            match WindowData::<W>::get(hwnd) {
+                None => winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam),
+                Some(window) => {
+                    let delta = Coord::from(winapi::um::winuser::GET_WHEEL_DELTA_WPARAM(wparam));
+                    window.event_manager.push(Event::Scroll {
+                        window_id: window.id.clone(),
+                        delta: match msg {
+                            winapi::um::winuser::WM_MOUSEHWHEEL => Vec2::new(delta, 0),
+                            _ => Vec2::new(0, delta),
+                        },
+                    });
+                    0
+                },
+            }
+        },
+
+        winapi::um::winuser::WM_MOVE => {
+            if WindowData::<W>::get(hwnd).map(|w| w.is_cursor_confined()).unwrap_or(false) {
+                update_cursor_clip(hwnd);
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_PAINT => {
+            // Validate the update region before dispatching so the application is free to draw
+            // however it likes, and so the message isn't immediately posted again.
+            winapi::um::winuser::ValidateRect(hwnd, std::ptr::null());
+            if let Some(window) = WindowData::<W>::get(hwnd) {
+                window.event_manager.push(Event::RedrawRequested {
+                    window_id: window.id.clone(),
+                });
+            }
+            0
+        },
+
+        winapi::um::winuser::WM_SETCURSOR => {
+            // The low word of `lparam` is the hit-test code of the pointer position. Only the
+            // client area uses the custom cursor so the frame keeps its resize cursors.
+            if lparam & 0xffff == winapi::um::winuser::HTCLIENT {
+                if let Some(window) = WindowData::<W>::get(hwnd) {
+                    if !window.cursor_visible.get() || window.pointer_locked.get() {
+                        winapi::um::winuser::SetCursor(std::ptr::null_mut());
+                        return 1;
+                    }
+                    if let Some(ref cursor) = *window.cursor.borrow() {
+                        winapi::um::winuser::SetCursor(cursor.hcursor());
+                        return 1;
+                    }
+                }
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_SETTINGCHANGE => {
+            // Theme changes are announced with the "ImmersiveColorSet" area name.
+            let area = lparam as *const u16;
+            if !area.is_null() {
+                let len = (0..).take_while(|&i| *area.add(i) != 0).count();
+                let area = std::slice::from_raw_parts(area, len);
+                if area.iter().copied().eq("ImmersiveColorSet".encode_utf16()) {
+                    if let Some(window) = WindowData::<W>::get(hwnd) {
+                        let _ = window.apply_theme();
+                        if let Some(theme) = query_theme() {
+                            window.event_manager.push_theme_change(theme);
+                        }
+                    }
+                }
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
         winapi::um::winuser::WM_SHOWWINDOW => {
             if let Some(window) = WindowData::<W>::get(hwnd) {
                 window.event_manager.push(Event::VisibilityChange {
@@ -316,6 +1329,295 @@ unsafe extern "system" fn window_proc<W: 'static + Clone>(
             0
         },
 
+        winapi::um::winuser::WM_SIZE => {
+            if WindowData::<W>::get(hwnd).map(|w| w.is_cursor_confined()).unwrap_or(false) {
+                update_cursor_clip(hwnd);
+            }
+            winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam)
+        },
+
+        winapi::um::winuser::WM_SIZING => {
+            let ratio = match WindowData::<W>::get(hwnd).and_then(|w| w.aspect_ratio.get()) {
+                Some(ratio) if ratio.x > 0 && ratio.y > 0 => ratio,
+                _ => return winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam),
+            };
+            let rect = &mut *(lparam as *mut RECT);
+            let frame = client_to_window_size(hwnd, Vec2::new(0, 0));
+            let width = rect.right - rect.left - frame.x;
+            let height = rect.bottom - rect.top - frame.y;
+
+            // Adjust whichever dimension the user isn't directly dragging.
+            match wparam as u32 {
+                winapi::um::winuser::WMSZ_TOP | winapi::um::winuser::WMSZ_BOTTOM => {
+                    rect.right = rect.left + height * ratio.x / ratio.y + frame.x;
+                },
+                winapi::um::winuser::WMSZ_TOPLEFT | winapi::um::winuser::WMSZ_TOPRIGHT => {
+                    rect.top = rect.bottom - width * ratio.y / ratio.x - frame.y;
+                },
+                _ => {
+                    rect.bottom = rect.top + width * ratio.y / ratio.x + frame.y;
+                },
+            }
+            1
+        },
+
         _ => winapi::um::winuser::DefWindowProcW(hwnd, msg, wparam, lparam),
     }
 }
+
+/// Converts a client area size to an outer window size using the window's current style and DPI.
+unsafe fn client_to_window_size(hwnd: HWND, size: Vec2<Coord>) -> Vec2<Coord> {
+    let style = winapi::um::winuser::GetWindowLongW(hwnd, winapi::um::winuser::GWL_STYLE);
+    let ex_style = winapi::um::winuser::GetWindowLongW(hwnd, winapi::um::winuser::GWL_EXSTYLE);
+    let mut rect = RECT { left: 0, top: 0, right: size.x, bottom: size.y };
+    ffi::win32::adjust_window_rect_for_dpi(&mut rect, style as u32, ex_style as u32,
+                                           ffi::win32::get_dpi_for_window(hwnd));
+    Vec2::new(rect.right - rect.left, rect.bottom - rect.top)
+}
+
+/// Extracts the scan code from the `lparam` of a keyboard message. Extended keys are prefixed
+/// with `0xe0` in the high byte.
+fn lparam_scancode(lparam: isize) -> u32 {
+    let scancode = (lparam as u32 >> 16) & 0xff;
+    if lparam & 0x01000000 != 0 {
+        scancode | 0xe000
+    } else {
+        scancode
+    }
+}
+
+/// Converts an opacity from 0.0 to 1.0 to a layered window alpha value.
+fn opacity_to_alpha(opacity: f32) -> Result<u8> {
+    if opacity.is_nan() {
+        return Err(err!(InvalidArgument("opacity is NaN")));
+    }
+    Ok((opacity.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Reads the system's preferred app theme from the registry. Returns `None` on versions of
+/// Windows without app themes.
+pub fn query_theme() -> Option<Theme> {
+    let key: Vec<u16> = "Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"
+                        .encode_utf16()
+                        .chain(std::iter::repeat(0).take(1))
+                        .collect();
+    let value: Vec<u16> = "AppsUseLightTheme".encode_utf16()
+                                             .chain(std::iter::repeat(0).take(1))
+                                             .collect();
+    let mut data = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+
+    unsafe {
+        if winapi::um::winreg::RegGetValueW(
+            winapi::um::winreg::HKEY_CURRENT_USER, key.as_ptr(), value.as_ptr(),
+            winapi::um::winreg::RRF_RT_REG_DWORD, std::ptr::null_mut(),
+            &mut data as *mut u32 as *mut _, &mut size)
+            != winapi::shared::winerror::ERROR_SUCCESS as i32
+        {
+            return None;
+        }
+    }
+
+    match data {
+        0 => Some(Theme::Dark),
+        _ => Some(Theme::Light),
+    }
+}
+
+/// Reads the relative motion from a raw mouse input message. Returns `None` for other devices
+/// and for devices which report absolute positions.
+unsafe fn raw_mouse_delta(hrawinput: HRAWINPUT) -> Option<Vec2<Coord>> {
+    let mut input = MaybeUninit::<RAWINPUT>::zeroed().assume_init();
+    let mut size = std::mem::size_of::<RAWINPUT>() as u32;
+    if winapi::um::winuser::GetRawInputData(hrawinput, winapi::um::winuser::RID_INPUT,
+                                            &mut input as *mut _ as *mut _, &mut size,
+                                            std::mem::size_of::<RAWINPUTHEADER>() as u32)
+        == u32::MAX
+    {
+        return None;
+    }
+    if input.header.dwType != winapi::um::winuser::RIM_TYPEMOUSE {
+        return None;
+    }
+
+    let mouse = input.data.mouse();
+    if mouse.usFlags & winapi::um::winuser::MOUSE_MOVE_ABSOLUTE != 0 {
+        return None;
+    }
+    Some(Vec2::new(mouse.lLastX, mouse.lLastY))
+}
+
+/// Clips the cursor to a window's client area if the window is in the foreground.
+unsafe fn update_cursor_clip(hwnd: HWND) {
+    if winapi::um::winuser::GetForegroundWindow() != hwnd {
+        return;
+    }
+
+    let mut rect = MaybeUninit::<RECT>::zeroed().assume_init();
+    if winapi::um::winuser::GetClientRect(hwnd, &mut rect) == 0 {
+        return;
+    }
+    let mut top_left = POINT { x: rect.left, y: rect.top };
+    let mut bottom_right = POINT { x: rect.right, y: rect.bottom };
+    winapi::um::winuser::ClientToScreen(hwnd, &mut top_left);
+    winapi::um::winuser::ClientToScreen(hwnd, &mut bottom_right);
+    let rect = RECT {
+        left: top_left.x,
+        top: top_left.y,
+        right: bottom_right.x,
+        bottom: bottom_right.y,
+    };
+    winapi::um::winuser::ClipCursor(&rect);
+}
+
+/// Starts raw mouse input when the first window locks the pointer, or stops it when the last
+/// window unlocks it. Raw input is registered per-process without a target window, so it follows
+/// the keyboard focus. Returns false if registration failed.
+unsafe fn update_raw_mouse(locked: bool) -> bool {
+    let count = if locked {
+        POINTER_LOCKED_WINDOWS.fetch_add(1, Ordering::SeqCst) + 1
+    } else {
+        POINTER_LOCKED_WINDOWS.fetch_sub(1, Ordering::SeqCst) - 1
+    };
+    if count != usize::from(locked) {
+        return true;
+    }
+
+    let device = RAWINPUTDEVICE {
+        usUsagePage: winapi::shared::hidusage::HID_USAGE_PAGE_GENERIC,
+        usUsage: winapi::shared::hidusage::HID_USAGE_GENERIC_MOUSE,
+        dwFlags: if locked { 0 } else { winapi::um::winuser::RIDEV_REMOVE },
+        hwndTarget: std::ptr::null_mut(),
+    };
+    if winapi::um::winuser::RegisterRawInputDevices(&device, 1,
+                                                    std::mem::size_of::<RAWINPUTDEVICE>() as u32)
+        != 0
+    {
+        return true;
+    }
+
+    // The window's lock state doesn't change if registration fails.
+    if locked {
+        POINTER_LOCKED_WINDOWS.fetch_sub(1, Ordering::SeqCst);
+    } else {
+        POINTER_LOCKED_WINDOWS.fetch_add(1, Ordering::SeqCst);
+    }
+    false
+}
+
+/// Translates a virtual key code to a logical key. Character keys are translated through the
+/// active keyboard layout.
+unsafe fn vk_to_key(vk: i32) -> Key {
+    match vk {
+        winapi::um::winuser::VK_BACK => Key::Backspace,
+        winapi::um::winuser::VK_CAPITAL => Key::CapsLock,
+        winapi::um::winuser::VK_CONTROL
+        | winapi::um::winuser::VK_LCONTROL
+        | winapi::um::winuser::VK_RCONTROL => Key::Control,
+        winapi::um::winuser::VK_DELETE => Key::Delete,
+        winapi::um::winuser::VK_DOWN => Key::Down,
+        winapi::um::winuser::VK_END => Key::End,
+        winapi::um::winuser::VK_ESCAPE => Key::Escape,
+        winapi::um::winuser::VK_F1 ..= winapi::um::winuser::VK_F24 => {
+            Key::F((vk - winapi::um::winuser::VK_F1 + 1) as u8)
+        },
+        winapi::um::winuser::VK_HOME => Key::Home,
+        winapi::um::winuser::VK_INSERT => Key::Insert,
+        winapi::um::winuser::VK_LEFT => Key::Left,
+        winapi::um::winuser::VK_LWIN | winapi::um::winuser::VK_RWIN => Key::Super,
+        winapi::um::winuser::VK_MENU
+        | winapi::um::winuser::VK_LMENU
+        | winapi::um::winuser::VK_RMENU => Key::Alt,
+        winapi::um::winuser::VK_NEXT => Key::PageDown,
+        winapi::um::winuser::VK_PRIOR => Key::PageUp,
+        winapi::um::winuser::VK_RETURN => Key::Enter,
+        winapi::um::winuser::VK_RIGHT => Key::Right,
+        winapi::um::winuser::VK_SHIFT
+        | winapi::um::winuser::VK_LSHIFT
+        | winapi::um::winuser::VK_RSHIFT => Key::Shift,
+        winapi::um::winuser::VK_TAB => Key::Tab,
+        winapi::um::winuser::VK_UP => Key::Up,
+        _ => {
+            // The high bit marks dead keys; the low word is the unshifted character.
+            let ch = winapi::um::winuser::MapVirtualKeyW(vk as u32,
+                                                        winapi::um::winuser::MAPVK_VK_TO_CHAR);
+            match std::char::from_u32(ch & 0xffff) {
+                Some(ch) if ch != '\0' && !ch.is_control() => {
+                    Key::Character(ch.to_lowercase().next().unwrap_or(ch))
+                },
+                _ => Key::Unknown,
+            }
+        },
+    }
+}
+
+/// Chooses the styles and extended styles of a new window. Windows has no window types, so each
+/// type is approximated with the closest styles.
+fn window_styles(popup: bool, window_type: Option<WindowType>) -> (u32, u32) {
+    // Dialogs and utility windows can't be minimized or maximized.
+    let fixed_frame = winapi::um::winuser::WS_OVERLAPPEDWINDOW
+                      & !(winapi::um::winuser::WS_MINIMIZEBOX
+                          | winapi::um::winuser::WS_MAXIMIZEBOX);
+    let (style, ex_style) = match window_type.unwrap_or(WindowType::Normal) {
+        WindowType::Dialog => (fixed_frame, winapi::um::winuser::WS_EX_DLGMODALFRAME),
+        WindowType::Menu => {
+            (winapi::um::winuser::WS_POPUP,
+             winapi::um::winuser::WS_EX_TOOLWINDOW | winapi::um::winuser::WS_EX_TOPMOST)
+        },
+        WindowType::Normal => (winapi::um::winuser::WS_OVERLAPPEDWINDOW, 0),
+        WindowType::Splash => (winapi::um::winuser::WS_POPUP, 0),
+        WindowType::Tooltip => {
+            (winapi::um::winuser::WS_POPUP,
+             winapi::um::winuser::WS_EX_TOOLWINDOW | winapi::um::winuser::WS_EX_TOPMOST
+             | winapi::um::winuser::WS_EX_NOACTIVATE)
+        },
+        WindowType::Utility => (fixed_frame, winapi::um::winuser::WS_EX_TOOLWINDOW),
+    };
+
+    // Popups have no frame and no taskbar button, whatever their type.
+    if popup {
+        let ex_style = ex_style & (winapi::um::winuser::WS_EX_TOPMOST
+                                   | winapi::um::winuser::WS_EX_NOACTIVATE);
+        (winapi::um::winuser::WS_POPUP, ex_style | winapi::um::winuser::WS_EX_TOOLWINDOW)
+    } else {
+        (style, ex_style)
+    }
+}
+
+/// Calls `f` with the shell's taskbar list, initializing COM on the current thread if necessary.
+unsafe fn with_taskbar_list<F: FnOnce(&ITaskbarList3) -> HRESULT>(f: F) -> Result<()> {
+    use winapi::Interface;
+
+    // `RPC_E_CHANGED_MODE` means COM was already initialized with a different threading model,
+    // which is fine for our purposes, but mustn't be balanced with `CoUninitialize`.
+    let init = winapi::um::combaseapi::CoInitializeEx(
+        std::ptr::null_mut(), winapi::um::objbase::COINIT_APARTMENTTHREADED);
+    if init < 0 && init != winapi::shared::winerror::RPC_E_CHANGED_MODE {
+        return Err(err!(RuntimeError{"CoInitializeEx failed: {:#x}", init}));
+    }
+
+    let mut ptr = std::ptr::null_mut();
+    let mut hr = winapi::um::combaseapi::CoCreateInstance(
+        &winapi::um::shobjidl_core::CLSID_TaskbarList, std::ptr::null_mut(),
+        winapi::um::combaseapi::CLSCTX_INPROC_SERVER, &ITaskbarList3::uuidof(), &mut ptr);
+    if hr >= 0 {
+        let list = &*(ptr as *mut ITaskbarList3);
+        hr = list.HrInit();
+        if hr >= 0 {
+            hr = f(list);
+        }
+        list.Release();
+    }
+
+    if init >= 0 {
+        winapi::um::combaseapi::CoUninitialize();
+    }
+    if hr < 0 {
+        return Err(err!(RequestFailed{"ITaskbarList3 failed: {:#x}", hr}));
+    }
+
+    Ok(())
+}
+
+/// Number of windows in the process which have locked the pointer.
+static POINTER_LOCKED_WINDOWS: AtomicUsize = AtomicUsize::new(0);
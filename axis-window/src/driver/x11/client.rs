@@ -6,15 +6,26 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 use std::ffi::CString;
 use std::marker::PhantomData;
+use std::ops::Deref;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 use std::os::raw::c_char;
 use std::rc::Rc;
+use std::time::Duration;
+
+use vectorial::Vec2;
 
 use crate::client::IClient;
-use crate::driver::x11::pixel_format::PixelFormat;
+use crate::device::Devices;
+use crate::driver::x11::device::{init_xinput2, query_devices, ScrollState};
+use crate::driver::x11::ime::{Ime, ImeEvent};
+use crate::driver::x11::keymap::Keymap;
+use crate::driver::x11::monitor::{query_monitors, watch_monitors};
+use crate::driver::x11::pixel_format::{FbConfig, PixelFormat};
+use crate::driver::x11::tray::{TrayIcon, TrayIconManager};
 use crate::driver::x11::window::{
     ChangePropertyMode,
     PropertyData,
@@ -22,8 +33,13 @@ use crate::driver::x11::window::{
     WindowBuilder,
     WindowManager,
 };
-use crate::error::Result;
-use crate::event::{Event, MainLoop, UpdateMode};
+use crate::error::{ErrorKind, Result};
+use crate::event::{ControlFlow, Event, FrameClock, MainLoop, UpdateMode};
+use crate::monitor::Monitors;
+use crate::pixel_format::{IPixelFormat, PixelFormatRequirements};
+use crate::tray::Icon;
+use crate::window::{ClosePolicy, IWindow, Theme};
+use crate::Coord;
 
 /// Connection to an X11 display server.
 pub struct Connection {
@@ -119,6 +135,18 @@ impl Connection {
         }
     }
 
+    pub(crate) fn get_property(&self, window: u32, property: u32, ty: u32, long_length: u32)
+        -> Result<Reply<xcb_sys::xcb_get_property_reply_t>>
+    {
+        unsafe {
+            let cookie = xcb_sys::xcb_get_property(self.xcb, 0, window, property, ty, 0,
+                                                   long_length);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_get_property_reply(self.xcb, cookie, &mut err_ptr);
+            Reply::new("X_GetProperty", reply_ptr, err_ptr)
+        }
+    }
+
     pub(crate) fn intern_atom(&self, name: &str) -> xcb_sys::xcb_intern_atom_cookie_t {
         unsafe {
             xcb_sys::xcb_intern_atom(self.xcb, 0, name.len() as u16, name.as_ptr() as *const c_char)
@@ -131,23 +159,7 @@ impl Connection {
         unsafe {
             let mut err_ptr = std::ptr::null_mut();
             let reply_ptr = xcb_sys::xcb_intern_atom_reply(self.xcb, cookie, &mut err_ptr);
-
-            if reply_ptr.is_null() {
-                if err_ptr.is_null() {
-                    return Err(err!(RequestFailed("X_InternAtom")));
-                } else {
-                    let err = err!(RequestFailed{"X_InternAtom: {:?}", *err_ptr});
-                    libc::free(err_ptr as *mut _);
-                    return Err(err);
-                }
-            }
-
-            let atom = (*reply_ptr).atom;
-            libc::free(reply_ptr as *mut _);
-            if !err_ptr.is_null() {
-                libc::free(err_ptr as *mut _);
-            }
-            Ok(atom)
+            Ok(Reply::new("X_InternAtom", reply_ptr, err_ptr)?.atom)
         }
     }
 }
@@ -188,13 +200,102 @@ impl PartialEq for Connection {
     }
 }
 
+/// Owned XCB reply which is freed when dropped.
+pub struct Reply<T> {
+    ptr: *mut T,
+}
+
+impl<T> Reply<T> {
+    /// Takes ownership of the results of an XCB `*_reply()` function.
+    pub unsafe fn new(request: &'static str, reply_ptr: *mut T,
+                      err_ptr: *mut xcb_sys::xcb_generic_error_t) -> Result<Reply<T>>
+    {
+        if reply_ptr.is_null() {
+            if err_ptr.is_null() {
+                return Err(err!(RequestFailed(request)));
+            } else {
+                let err = err!(RequestFailed{"{}: {:?}", request, *err_ptr});
+                libc::free(err_ptr as *mut _);
+                return Err(err);
+            }
+        }
+
+        if !err_ptr.is_null() {
+            libc::free(err_ptr as *mut _);
+        }
+        Ok(Reply { ptr: reply_ptr })
+    }
+}
+
+impl Reply<xcb_sys::xcb_get_property_reply_t> {
+    /// Returns the property value if its format is 8, or an empty slice otherwise.
+    pub fn value8(&self) -> &[u8] {
+        unsafe {
+            if (*self.ptr).format != 8 {
+                return &[];
+            }
+            let len = xcb_sys::xcb_get_property_value_length(self.ptr) as usize;
+            let data = xcb_sys::xcb_get_property_value(self.ptr) as *const u8;
+            std::slice::from_raw_parts(data, len)
+        }
+    }
+
+    /// Returns the property value if its format is 32, or an empty slice otherwise.
+    pub fn value32(&self) -> &[u32] {
+        unsafe {
+            if (*self.ptr).format != 32 {
+                return &[];
+            }
+            let len = xcb_sys::xcb_get_property_value_length(self.ptr) as usize / 4;
+            let data = xcb_sys::xcb_get_property_value(self.ptr) as *const u32;
+            std::slice::from_raw_parts(data, len)
+        }
+    }
+}
+
+impl<T> Deref for Reply<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe {
+            &*self.ptr
+        }
+    }
+}
+
+impl<T> Drop for Reply<T> {
+    fn drop(&mut self) {
+        unsafe {
+            libc::free(self.ptr as *mut _);
+        }
+    }
+}
+
 /// X11 window system client type.
 pub struct Client<W: 'static + Clone> {
+    app_id: Rc<RefCell<Option<String>>>,
     atoms: Rc<Atoms>,
     connection: Rc<Connection>,
+    dpi: Rc<Cell<u32>>,
+    ime: Option<Rc<Ime>>,
+    keymap: RefCell<Keymap>,
+    last_x_error: RefCell<Option<String>>,
+    monitors_changed: Cell<bool>,
+    native_event_hook: RefCell<Option<Rc<dyn Fn(*const xcb_sys::xcb_generic_event_t) -> bool>>>,
     _phantom: PhantomData<W>,
+    pointer_locks: Rc<Cell<usize>>,
+    pressed_keys: RefCell<HashSet<u8>>,
+    randr_first_event: Option<u8>,
     screens: Rc<Vec<Screen>>,
+    screensaver_inhibited: Cell<bool>,
+    scroll_state: RefCell<ScrollState>,
+    theme: Cell<Option<Theme>>,
+    tray_icon_manager: Rc<TrayIconManager<W>>,
     window_manager: Rc<WindowManager<W>>,
+    xinput2: bool,
+    xkb_first_event: Option<u8>,
+    xsettings_owner: Cell<u32>,
+    xsettings_selection: u32,
 }
 
 impl<W: 'static + Clone> Client<W> {
@@ -203,6 +304,11 @@ impl<W: 'static + Clone> Client<W> {
         &self.connection
     }
 
+    /// Removes the hook set by [set_native_event_hook](Client::set_native_event_hook).
+    pub fn clear_native_event_hook(&self) {
+        *self.native_event_hook.borrow_mut() = None;
+    }
+
     /// Returns the default X11 screen.
     pub fn default_screen(&self) -> Screen {
         self.screens[self.connection.default_screen_num as usize].clone()
@@ -223,24 +329,83 @@ impl<W: 'static + Clone> Client<W> {
         Client::init(Connection::open_raw(name_ptr)?)
     }
 
+    /// Runs a single main loop for several clients, which may be connected to different X11
+    /// display servers. Events from every client are passed to `f`, so windows should have
+    /// distinct IDs across clients. Fails if `clients` is empty.
+    pub fn run_all<F, R>(clients: &[&Client<W>], main_loop: &MainLoop, f: &F) -> Result<()>
+        where F: Fn(Event<W>) -> R, R: Into<ControlFlow>
+    {
+        if clients.is_empty() {
+            return Err(err!(InvalidArgument("no X11 clients to run")));
+        }
+
+        let need_update = Cell::new(true);
+        let f = |event| {
+            match event {
+                Event::Update { .. } => (),
+                _ => need_update.set(true),
+            }
+            main_loop.apply_control_flow(f(event).into());
+        };
+
+        let wake_pipe = WakePipe::new()?;
+        main_loop.set_waker(Some(wake_pipe.waker()))?;
+        let result = Client::run_loop(clients, main_loop, &f, &need_update, &wake_pipe);
+        main_loop.set_waker(None)?;
+        result
+    }
+
     /// Gets an iterator of all available X11 screens.
     pub fn screens(&self) -> impl Iterator<Item = Screen> {
         (*self.screens).clone().into_iter()
     }
+
+    /// Sets a function which receives each X event and error before the client processes it. If
+    /// the hook returns true, the event is consumed and the client doesn't process it. The event
+    /// is freed after the hook returns.
+    pub fn set_native_event_hook<F>(&self, hook: F)
+        where F: 'static + Fn(*const xcb_sys::xcb_generic_event_t) -> bool
+    {
+        *self.native_event_hook.borrow_mut() = Some(Rc::new(hook));
+    }
 }
 
 impl<W: 'static + Clone> Client<W> {
+    pub(crate) fn app_id(&self) -> &Rc<RefCell<Option<String>>> {
+        &self.app_id
+    }
+
     pub(crate) fn atoms(&self) -> &Rc<Atoms> {
         &self.atoms
     }
 
+    pub(crate) fn dpi(&self) -> &Rc<Cell<u32>> {
+        &self.dpi
+    }
+
+    pub(crate) fn ime(&self) -> &Option<Rc<Ime>> {
+        &self.ime
+    }
+
+    pub(crate) fn pointer_locks(&self) -> &Rc<Cell<usize>> {
+        &self.pointer_locks
+    }
+
     pub(crate) fn screens_ref(&self) -> &Rc<Vec<Screen>> {
         &self.screens
     }
 
+    pub(crate) fn tray_icon_manager(&self) -> &Rc<TrayIconManager<W>> {
+        &self.tray_icon_manager
+    }
+
     pub(crate) fn window_manager(&self) -> &Rc<WindowManager<W>> {
         &self.window_manager
     }
+
+    pub(crate) fn xinput2(&self) -> bool {
+        self.xinput2
+    }
 }
 
 impl<W: 'static + Clone> Client<W> {
@@ -251,7 +416,7 @@ impl<W: 'static + Clone> Client<W> {
             result = xcb_sys::xcb_connection_has_error(self.connection.xcb);
         }
 
-        Err(err!(IoError(match result as u32 {
+        let detail = match result as u32 {
             0 => return Ok(()),
             xcb_sys::XCB_CONN_ERROR => "X11 connection error",
             xcb_sys::XCB_CONN_CLOSED_EXT_NOTSUPPORTED => "X11 extension not supported",
@@ -259,23 +424,154 @@ impl<W: 'static + Clone> Client<W> {
             xcb_sys::XCB_CONN_CLOSED_REQ_LEN_EXCEED => "X11 request length exceeded",
             xcb_sys::XCB_CONN_CLOSED_PARSE_ERR => "X11 display name parse error",
             xcb_sys::XCB_CONN_CLOSED_INVALID_SCREEN => "invalid X11 screen",
-            _ => return Err(err!(IoError)),
-        })))
+            _ => "unknown X11 connection error",
+        };
+
+        // XCB doesn't say which request broke the connection, but the most recent X error is
+        // usually the culprit.
+        match *self.last_x_error.borrow() {
+            None => Err(err!(IoError(detail))),
+            Some(ref last_error) => Err(err!(IoError{"{} after {}", detail, last_error})),
+        }
+    }
+
+    /// Constructs a frame clock for the client's monitors. If they can't be queried, updates are
+    /// paced at the default rate.
+    fn frame_clock(&self) -> FrameClock {
+        match self.monitors() {
+            Err(_err) => {
+                warn!("can't query monitors for frame pacing: {}", _err);
+                FrameClock::for_monitors(None)
+            },
+            Ok(monitors) => FrameClock::for_monitors(monitors),
+        }
+    }
+
+    /// Reports input method events, and handles key events which the input method passed back.
+    fn handle_ime_events<F: Fn(Event<W>)>(&self, f: &F) {
+        let ime = match self.ime {
+            None => return,
+            Some(ref ime) => ime,
+        };
+
+        for (xid, event) in ime.process_events() {
+            match event {
+                ImeEvent::Commit(text) => {
+                    if let Some(window) = self.window_manager.get(xid) {
+                        f(Event::ImeCommit {
+                            window_id: window.id().clone(),
+                            text,
+                        });
+                    }
+                },
+                ImeEvent::Key(ev) => self.handle_key_event(&ev, f),
+                ImeEvent::Preedit(text, cursor) => {
+                    if let Some(window) = self.window_manager.get(xid) {
+                        f(Event::ImePreedit {
+                            window_id: window.id().clone(),
+                            text,
+                            cursor,
+                        });
+                    }
+                },
+            }
+        }
+    }
+
+    /// Reports a key press or release which the input method didn't consume.
+    fn handle_key_event<F: Fn(Event<W>)>(&self, ev: &xcb_sys::xcb_key_press_event_t, f: &F) {
+        let pressed = u32::from(ev.response_type & !0x80) == xcb_sys::XCB_KEY_PRESS;
+        if !pressed {
+            self.pressed_keys.borrow_mut().remove(&ev.detail);
+        }
+        if let Some(window) = self.window_manager.get(ev.event) {
+            let window_id = window.id().clone();
+            let scancode = u32::from(ev.detail);
+            let key = self.keymap.borrow().key(ev.detail, xkb_group(ev.state));
+            match pressed {
+                false => f(Event::KeyUp { window_id, scancode, key }),
+                true => {
+                    let repeat = !self.pressed_keys.borrow_mut().insert(ev.detail);
+                    f(Event::KeyDown { window_id, scancode, key, repeat });
+                },
+            }
+        }
+    }
+
+    /// Finds the live window targeted by a [WindowHandle](crate::WindowHandle), if it belongs to
+    /// this client.
+    fn handle_target(&self, target: u64) -> Option<Window<W>> {
+        self.window_manager.windows()
+            .into_iter()
+            .find(|data| data.handle_target() == target && data.try_xid().is_ok())
+            .map(|data| Window::from_data(self, data))
     }
 
     unsafe fn handle_x_event<F: Fn(Event<W>)>(
         &self, event: *const xcb_sys::xcb_generic_event_t, f: &F) -> Result<()>
     {
+        // The hook is cloned so that it can replace itself.
+        let hook = self.native_event_hook.borrow().clone();
+        if let Some(hook) = hook {
+            if hook(event) {
+                return Ok(());
+            }
+        }
+
         match ((*event).response_type & !0x80) as u32 {
+            // Errors for requests without replies are delivered through the event queue.
+            0 => {
+                let detail = describe_x_error(&self.connection,
+                                              event as *const xcb_sys::xcb_generic_error_t);
+                *self.last_x_error.borrow_mut() = Some(detail.clone());
+                f(Event::Error { kind: ErrorKind::RequestFailed, detail });
+            },
+
+            xcb_sys::XCB_BUTTON_PRESS => {
+                let ev = event as *const xcb_sys::xcb_button_press_event_t;
+                if let Some(tray_icon) = self.tray_icon_manager.get((*ev).event) {
+                    let root_pos = Vec2::new(Coord::from((*ev).root_x), Coord::from((*ev).root_y));
+                    if let Some(event) = tray_icon.button_event((*ev).detail, root_pos) {
+                        f(event);
+                    }
+                }
+            },
+
             xcb_sys::XCB_CLIENT_MESSAGE => {
                 let ev = event as *const xcb_sys::xcb_client_message_event_t;
-                if let Some(window) = self.window_manager.get((*ev).window) {
+                if (*ev).window == self.default_screen().root()
+                   && (*ev).type_ == self.atoms.MANAGER && (*ev).format == 32
+                {
+                    // A new manager took ownership of a selection. See ICCCM section 2.8.
+                    let selection = (*ev).data.data32[1];
+                    if selection == self.xsettings_selection {
+                        match watch_xsettings(&self.connection, selection) {
+                            Err(_err) => {
+                                warn!("can't watch XSETTINGS manager: {}", _err);
+                                self.xsettings_owner.set(0);
+                            },
+                            Ok(owner) => {
+                                self.xsettings_owner.set(owner);
+                                self.update_theme(f);
+                            },
+                        }
+                    } else if selection == self.tray_icon_manager.selection() {
+                        // Trays keep embedded icons in their save-set, so the icons survive a
+                        // tray restart and can be docked into the new tray.
+                        for tray_icon in self.tray_icon_manager.icons() {
+                            tray_icon.dock((*ev).data.data32[2]);
+                        }
+                    }
+                } else if let Some(window) = self.window_manager.get((*ev).window) {
                     if (*ev).type_ == self.atoms.WM_PROTOCOLS && (*ev).format == 32 {
                         let protocol = (*ev).data.data32[0];
                         if protocol == self.atoms.WM_DELETE_WINDOW {
                             f(Event::CloseRequest {
                                 window_id: window.id().clone(),
                             });
+                            if window.close_policy() == ClosePolicy::Destroy {
+                                Window::from_data(self, window).destroy();
+                            }
                         }
                     }
                 }
@@ -284,9 +580,117 @@ impl<W: 'static + Clone> Client<W> {
             xcb_sys::XCB_DESTROY_NOTIFY => {
                 let ev = event as *const xcb_sys::xcb_destroy_notify_event_t;
                 if let Some(window) = self.window_manager.unregister((*ev).window) {
+                    if let Some(ref ime) = self.ime {
+                        ime.destroy_context((*ev).window);
+                    }
+                    Window::from_data(self, window.clone()).release_pointer_lock();
                     f(Event::Destroy {
                         window_id: window.id().clone(),
                     });
+                } else if (*ev).window == self.xsettings_owner.get() {
+                    // The previous theme is kept until a new settings manager announces itself.
+                    self.xsettings_owner.set(0);
+                }
+            },
+
+            xcb_sys::XCB_EXPOSE => {
+                let ev = event as *const xcb_sys::xcb_expose_event_t;
+                if (*ev).count == 0 {
+                    if let Some(window) = self.window_manager.get((*ev).window) {
+                        f(Event::RedrawRequested {
+                            window_id: window.id().clone(),
+                        });
+                    } else if let Some(tray_icon) = self.tray_icon_manager.get((*ev).window) {
+                        // Tray icons are drawn by the driver. Failing to draw one isn't worth
+                        // stopping the main loop over.
+                        if let Err(_err) = tray_icon.redraw() {
+                            warn!("can't draw tray icon: {}", _err);
+                        }
+                    }
+                }
+            },
+
+            xcb_sys::XCB_PROPERTY_NOTIFY => {
+                let ev = event as *const xcb_sys::xcb_property_notify_event_t;
+                if (*ev).window == self.default_screen().root()
+                   && (*ev).atom == xcb_sys::XCB_ATOM_RESOURCE_MANAGER
+                {
+                    // The previous DPI is kept if the resource database can't be read.
+                    match query_dpi(&self.connection, &self.default_screen()) {
+                        Err(_err) => warn!("can't read Xft.dpi: {}", _err),
+                        Ok(dpi) => {
+                            if self.dpi.replace(dpi) != dpi {
+                                for window in self.window_manager.windows() {
+                                    f(Event::ScaleFactorChanged {
+                                        window_id: window.id().clone(),
+                                        dpi,
+                                    });
+                                }
+                            }
+                        },
+                    }
+                } else if (*ev).window == self.xsettings_owner.get() && (*ev).window != 0
+                          && (*ev).atom == self.atoms._XSETTINGS_SETTINGS
+                {
+                    self.update_theme(f);
+                }
+            },
+
+            xcb_sys::XCB_FOCUS_IN => {
+                // Unlike the EWMH attention state, the urgency hint must be cleared by the client.
+                let ev = event as *const xcb_sys::xcb_focus_in_event_t;
+                if u32::from((*ev).detail) == xcb_sys::XCB_NOTIFY_DETAIL_POINTER {
+                    return Ok(());
+                }
+                if let Some(window) = self.window_manager.get((*ev).event) {
+                    window.set_focused(true);
+                    if let Some(ref ime) = self.ime {
+                        ime.set_focus((*ev).event, true);
+                    }
+                    let urgent = window.is_urgent();
+                    let window = Window::from_data(self, window);
+                    // The window may already be destroyed, with its `DestroyNotify` still queued.
+                    if urgent {
+                        if let Err(_err) = window.set_urgency(false) {
+                            warn!("can't clear urgency hint: {}", _err);
+                        }
+                    }
+                    self.update_pointer_grab(&window);
+                }
+            },
+
+            xcb_sys::XCB_FOCUS_OUT => {
+                // Keys released while another window has focus aren't reported.
+                self.pressed_keys.borrow_mut().clear();
+                let ev = event as *const xcb_sys::xcb_focus_out_event_t;
+                if u32::from((*ev).detail) == xcb_sys::XCB_NOTIFY_DETAIL_POINTER {
+                    return Ok(());
+                }
+                if let Some(window) = self.window_manager.get((*ev).event) {
+                    window.set_focused(false);
+                    if let Some(ref ime) = self.ime {
+                        ime.set_focus((*ev).event, false);
+                    }
+                    self.update_pointer_grab(&Window::from_data(self, window));
+                }
+            },
+
+            xcb_sys::XCB_GE_GENERIC => {
+                let ev = event as *const xcb_sys::xcb_ge_generic_event_t;
+                let ext_ptr = xcb_sys::xcb_get_extension_data(self.connection.xcb,
+                                                              &raw mut xcb_sys::xcb_input_id);
+                if !ext_ptr.is_null() && (*ext_ptr).major_opcode == (*ev).extension {
+                    self.handle_xi_event(ev, f)?;
+                }
+            },
+
+            xcb_sys::XCB_KEY_PRESS | xcb_sys::XCB_KEY_RELEASE => {
+                // Key events pass through the input method first. Keys which it doesn't consume
+                // are passed back through `handle_ime_events`.
+                let ev = event as *const xcb_sys::xcb_key_press_event_t;
+                match self.ime {
+                    Some(ref ime) if ime.filter_key_event(ev) => (),
+                    _ => self.handle_key_event(&*ev, f),
                 }
             },
 
@@ -296,6 +700,16 @@ impl<W: 'static + Clone> Client<W> {
                     if let Some(event) = window.update_visibility(true) {
                         f(event);
                     }
+                    self.update_pointer_grab(&Window::from_data(self, window));
+                }
+            },
+
+            xcb_sys::XCB_MAPPING_NOTIFY => {
+                let ev = event as *const xcb_sys::xcb_mapping_notify_event_t;
+                if u32::from((*ev).request) == xcb_sys::XCB_MAPPING_KEYBOARD {
+                    *self.keymap.borrow_mut() = Keymap::query(&self.connection,
+                                                              self.xkb_first_event.is_some())?;
+                    f(Event::KeymapChanged);
                 }
             },
 
@@ -305,6 +719,96 @@ impl<W: 'static + Clone> Client<W> {
                     if let Some(event) = window.update_visibility(false) {
                         f(event);
                     }
+                    self.update_pointer_grab(&Window::from_data(self, window));
+                }
+            },
+
+            // `RRScreenChangeNotify` and `RRNotify` both indicate that monitors may have changed.
+            response_type if self.randr_first_event.map_or(false, |first| {
+                (response_type as u8).wrapping_sub(first)
+                    <= xcb_sys::XCB_RANDR_NOTIFY as u8
+            }) => {
+                self.monitors_changed.set(true);
+            },
+
+            // XKB events share a single event code, and are distinguished by `xkbType`.
+            response_type if self.xkb_first_event == Some(response_type as u8) => {
+                let ev = event as *const xcb_sys::xcb_xkb_state_notify_event_t;
+                if u32::from((*ev).xkbType) == xcb_sys::XCB_XKB_STATE_NOTIFY
+                   && u32::from((*ev).changed) & xcb_sys::XCB_XKB_STATE_PART_GROUP_STATE != 0
+                {
+                    f(Event::KeymapChanged);
+                }
+            },
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    unsafe fn handle_xi_event<F: Fn(Event<W>)>(
+        &self, event: *const xcb_sys::xcb_ge_generic_event_t, f: &F) -> Result<()>
+    {
+        match u32::from((*event).event_type) {
+            xcb_sys::XCB_INPUT_DEVICE_CHANGED => {
+                // The classes of master devices change whenever a different slave device is
+                // used, but scroll valuators are tracked for slave devices.
+                let ev = event as *const xcb_sys::xcb_input_device_changed_event_t;
+                if u32::from((*ev).reason) == xcb_sys::XCB_INPUT_CHANGE_REASON_DEVICE_CHANGE {
+                    self.update_scroll_state();
+                }
+            },
+
+            xcb_sys::XCB_INPUT_ENTER => {
+                self.update_scroll_state();
+            },
+
+            xcb_sys::XCB_INPUT_HIERARCHY => {
+                let ev = event as *const xcb_sys::xcb_input_hierarchy_event_t;
+                let infos = std::slice::from_raw_parts(
+                    xcb_sys::xcb_input_hierarchy_infos(ev),
+                    xcb_sys::xcb_input_hierarchy_infos_length(ev) as usize);
+                let added = xcb_sys::XCB_INPUT_HIERARCHY_MASK_MASTER_ADDED
+                            | xcb_sys::XCB_INPUT_HIERARCHY_MASK_SLAVE_ADDED;
+                let removed = xcb_sys::XCB_INPUT_HIERARCHY_MASK_MASTER_REMOVED
+                              | xcb_sys::XCB_INPUT_HIERARCHY_MASK_SLAVE_REMOVED;
+                for info in infos.iter() {
+                    let device_id = u64::from(info.deviceid);
+                    if info.flags & added as u32 != 0 {
+                        f(Event::DeviceAdded { device_id });
+                    } else if info.flags & removed as u32 != 0 {
+                        f(Event::DeviceRemoved { device_id });
+                    }
+                }
+                self.update_scroll_state();
+            },
+
+            xcb_sys::XCB_INPUT_MOTION => {
+                let ev = event as *const xcb_sys::xcb_input_motion_event_t;
+                if let Some(window) = self.window_manager.get((*ev).event) {
+                    if let Some(delta) = self.scroll_state.borrow().delta(ev) {
+                        f(Event::Scroll {
+                            window_id: window.id().clone(),
+                            delta,
+                        });
+                    }
+                }
+            },
+
+            xcb_sys::XCB_INPUT_RAW_MOTION => {
+                let ev = event as *const xcb_sys::xcb_input_raw_motion_event_t;
+                let delta = raw_motion_delta(ev);
+                for window in self.window_manager.windows() {
+                    if window.is_pointer_locked() {
+                        let delta = window.accumulate_raw_motion(delta);
+                        if delta.x != 0 || delta.y != 0 {
+                            f(Event::MouseMotionRaw {
+                                window_id: window.id().clone(),
+                                delta,
+                            });
+                        }
+                    }
                 }
             },
 
@@ -336,54 +840,165 @@ impl<W: 'static + Clone> Client<W> {
             }
         }
 
+        // Watch the default root window for changes to the resource database so that changes to
+        // `Xft.dpi` can be reported. `MANAGER` messages are sent with the structure notify mask.
+        let default_screen = &screens[connection.default_screen_num as usize];
+        let dpi = query_dpi(&connection, default_screen)?;
+        let values = [(xcb_sys::XCB_EVENT_MASK_PROPERTY_CHANGE
+                       | xcb_sys::XCB_EVENT_MASK_STRUCTURE_NOTIFY) as u32];
+        unsafe {
+            xcb_sys::xcb_change_window_attributes(connection.xcb, default_screen.root(),
+                                                  xcb_sys::XCB_CW_EVENT_MASK,
+                                                  values.as_ptr() as *const _);
+        }
+
+        // The settings manager publishes desktop settings such as the GTK theme name through
+        // XSETTINGS. There may not be one outside of GNOME-like desktops.
+        let xsettings_selection = connection.intern_atom_reply(
+            connection.intern_atom(&format!("_XSETTINGS_S{}", default_screen.num())))?;
+        // A broken settings manager only costs the theme.
+        let xsettings_owner = match watch_xsettings(&connection, xsettings_selection) {
+            Err(_err) => {
+                warn!("can't watch XSETTINGS manager: {}", _err);
+                0
+            },
+            Ok(owner) => owner,
+        };
+        let theme = match query_theme(&connection, &atoms, xsettings_owner) {
+            Err(_err) => {
+                warn!("can't read XSETTINGS theme: {}", _err);
+                None
+            },
+            Ok(theme) => theme,
+        };
+
+        // RandR reports monitor changes, after which the refresh rate used to pace updates is
+        // requeried.
+        let randr_first_event = watch_monitors(&connection, default_screen.root());
+
+        // Tray icons are redocked when a new tray manager announces itself.
+        let tray_selection = connection.intern_atom_reply(
+            connection.intern_atom(&format!("_NET_SYSTEM_TRAY_S{}", default_screen.num())))?;
+
+        // XInput 2 reports device hierarchy changes and smooth scrolling.
+        let xinput2 = init_xinput2(&connection, default_screen.root());
+        let scroll_state = match xinput2 {
+            false => ScrollState::default(),
+            true => ScrollState::query(&connection)?,
+        };
+
+        // XKB describes every keyboard group, and reports group switches. Without it, only the
+        // groups visible through the core keyboard mapping are available.
+        let xkb_first_event = init_xkb(&connection);
+        let keymap = Keymap::query(&connection, xkb_first_event.is_some())?;
+
+        // Without detectable auto-repeat, repeated keys are reported as release/press pairs.
+        if xkb_first_event.is_none() || !enable_detectable_auto_repeat(&connection) {
+            warn!("XKB detectable auto-repeat is unavailable");
+        }
+
+        // Input methods compose text which can't be typed directly. Without one, only key events
+        // are reported.
+        let ime = Ime::open(&connection).map(Rc::new);
+
         Ok(Client {
+            app_id: Rc::new(RefCell::new(None)),
             atoms,
             connection: connection,
+            dpi: Rc::new(Cell::new(dpi)),
+            ime,
+            keymap: RefCell::new(keymap),
+            last_x_error: RefCell::new(None),
+            monitors_changed: Cell::new(false),
+            native_event_hook: RefCell::new(None),
             _phantom: PhantomData,
+            pointer_locks: Rc::new(Cell::new(0)),
+            pressed_keys: RefCell::new(HashSet::new()),
+            randr_first_event,
             screens: Rc::new(screens),
+            screensaver_inhibited: Cell::new(false),
+            scroll_state: RefCell::new(scroll_state),
+            theme: Cell::new(theme),
+            tray_icon_manager: Rc::new(TrayIconManager::new(tray_selection)),
             window_manager: Rc::new(WindowManager::new()),
+            xinput2,
+            xkb_first_event,
+            xsettings_owner: Cell::new(xsettings_owner),
+            xsettings_selection,
         })
     }
-}
-
-impl<W: 'static + Clone> IClient for Client<W> {
-    type PixelFormat = PixelFormat;
-    type Window = Window<W>;
-    type WindowBuilder = WindowBuilder<W>;
-    type WindowId = W;
-
-    fn default_pixel_format(&self) -> PixelFormat {
-        self.default_screen().default_pixel_format()
-    }
 
-    fn run<F: Fn(Event<W>)>(&self, main_loop: &MainLoop, f: &F) -> Result<()> {
-        let need_update = Cell::new(true);
-        let f = |event| {
-            match event {
-                Event::Update { .. } => (),
-                _ => need_update.set(true),
-            }
-            f(event);
-        };
+    /// Runs the main loop for one or more clients until it quits. Proxies wake the loop through
+    /// `wake_pipe` while it's waiting for X events.
+    fn run_loop<F: Fn(Event<W>)>(clients: &[&Client<W>], main_loop: &MainLoop, f: &F,
+                                 need_update: &Cell<bool>, wake_pipe: &WakePipe) -> Result<()>
+    {
+        let mut frame_clock = None;
 
         'main_loop: while !main_loop.is_quit_requested() {
             unsafe {
-                xcb_sys::xcb_flush(self.connection.xcb);
-                self.check_connection()?;
-
                 // Handle pending events.
-                'poll_loop: loop {
-                    let event_ptr = xcb_sys::xcb_poll_for_event(self.connection.xcb);
-                    if event_ptr.is_null() {
-                        break 'poll_loop;
+                for client in clients {
+                    xcb_sys::xcb_flush(client.connection.xcb);
+                    client.check_connection()?;
+
+                    'poll_loop: loop {
+                        let event_ptr = xcb_sys::xcb_poll_for_event(client.connection.xcb);
+                        if event_ptr.is_null() {
+                            break 'poll_loop;
+                        }
+                        client.handle_x_event(event_ptr, f)?;
+                        libc::free(event_ptr as *mut _);
+                        if main_loop.is_quit_requested() {
+                            break 'main_loop;
+                        }
+                    }
+
+                    client.handle_ime_events(f);
+                    if main_loop.is_quit_requested() {
+                        break 'main_loop;
+                    }
+                }
+
+                // Handle events posted by proxies.
+                for data in main_loop.take_user_events()? {
+                    f(Event::User { data });
+                    if main_loop.is_quit_requested() {
+                        break 'main_loop;
                     }
-                    self.handle_x_event(event_ptr, &f)?;
-                    libc::free(event_ptr as *mut _);
+                }
+
+                // Apply commands sent through window handles. They're sent from other threads,
+                // so failures can only be logged.
+                for (target, command) in main_loop.take_window_commands()? {
+                    let window = clients.iter().find_map(|client| client.handle_target(target));
+                    if let Some(window) = window {
+                        if let Err(_err) = command.apply(&window) {
+                            warn!("window command failed: {}", _err);
+                        }
+                    }
+                }
+
+                // Handle expired timers.
+                for timer_id in main_loop.take_expired_timers() {
+                    f(Event::Timer { timer_id });
                     if main_loop.is_quit_requested() {
                         break 'main_loop;
                     }
                 }
 
+                // A requested wake-up time counts as a reason to update.
+                if main_loop.take_wait_expired() {
+                    need_update.set(true);
+                }
+
+                // The refresh rate may have changed along with the monitors.
+                for client in clients {
+                    if client.monitors_changed.take() {
+                        frame_clock = None;
+                    }
+                }
+
                 // Emit update event and possibly wait for more events.
                 match main_loop.update_mode() {
                     UpdateMode::Passive => {
@@ -394,28 +1009,324 @@ impl<W: 'static + Clone> IClient for Client<W> {
                             }
                         }
 
-                        let event_ptr = xcb_sys::xcb_wait_for_event(self.connection.xcb);
-                        if event_ptr.is_null() {
-                            self.check_connection()?;
-                            return Err(err!(IoError));
-                        }
-                        self.handle_x_event(event_ptr, &f)?;
-                        libc::free(event_ptr as *mut _);
+                        Client::wait_for_events(clients, f, Some(wake_pipe),
+                                                main_loop.next_timeout())?;
                     },
 
-                    UpdateMode::Active | UpdateMode::Sync => {
+                    UpdateMode::Active => {
                         f(Event::Update { update_mode: UpdateMode::Active });
                     },
+
+                    UpdateMode::Sync => {
+                        // Updates are paced to the refresh rate reported by RandR rather than
+                        // following the actual v-blank.
+                        let frame_clock = frame_clock.get_or_insert_with(|| {
+                            clients[0].frame_clock()
+                        });
+                        match frame_clock.time_until_frame() {
+                            None => {
+                                frame_clock.advance();
+                                f(Event::Update { update_mode: UpdateMode::Sync });
+                            },
+                            Some(frame_timeout) => {
+                                let timeout = match main_loop.next_timeout() {
+                                    None => frame_timeout,
+                                    Some(timeout) => std::cmp::min(timeout, frame_timeout),
+                                };
+                                Client::wait_for_events(clients, f, Some(wake_pipe),
+                                                        Some(timeout))?;
+                            },
+                        }
+                    },
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Grabs or releases the pointer after a window's focus or mapping changed. The pointer may
+    /// already be grabbed by another client, which isn't worth stopping the main loop over.
+    fn update_pointer_grab(&self, window: &Window<W>) {
+        if let Err(_err) = window.update_pointer_grab() {
+            warn!("can't grab pointer: {}", _err);
+        }
+    }
+
+    /// Requeries the scroll valuators of every device. Devices may be unplugged while they're
+    /// queried, so the previous state is kept on failure.
+    fn update_scroll_state(&self) {
+        match ScrollState::query(&self.connection) {
+            Err(_err) => warn!("can't query scroll valuators: {}", _err),
+            Ok(scroll_state) => *self.scroll_state.borrow_mut() = scroll_state,
+        }
+    }
+
+    /// Rereads the theme from the current settings manager and reports it if it changed. The
+    /// previous theme is kept if it can't be read.
+    fn update_theme<F: Fn(Event<W>)>(&self, f: &F) {
+        match query_theme(&self.connection, &self.atoms, self.xsettings_owner.get()) {
+            Err(_err) => warn!("can't read XSETTINGS theme: {}", _err),
+            Ok(None) => (),
+            Ok(Some(theme)) => {
+                if self.theme.replace(Some(theme)) != Some(theme) {
+                    f(Event::ThemeChanged { theme });
+                }
+            },
+        }
+    }
+
+    /// Blocks until an X event or input method event arrives for any of `clients`, a proxy wakes
+    /// the main loop, or `timeout` elapses.
+    unsafe fn wait_for_events<F: Fn(Event<W>)>(clients: &[&Client<W>], f: &F,
+                                               wake_pipe: Option<&WakePipe>,
+                                               timeout: Option<Duration>) -> Result<()>
+    {
+        // The update handler may have made round trips which queued events without leaving
+        // anything to read from the socket.
+        for client in clients {
+            let event_ptr = xcb_sys::xcb_poll_for_queued_event(client.connection.xcb);
+            if !event_ptr.is_null() {
+                client.handle_x_event(event_ptr, f)?;
+                libc::free(event_ptr as *mut _);
+                return Ok(());
+            }
+            // Requests to the input method are flushed here. Its callbacks may already have
+            // reported events while key events were filtered.
+            if let Some(ref ime) = client.ime {
+                if ime.has_pending_events() {
+                    client.handle_ime_events(f);
+                    return Ok(());
+                }
+            }
+        }
+
+        let mut fds = Vec::with_capacity(clients.len() * 2 + 1);
+        for client in clients {
+            xcb_sys::xcb_flush(client.connection.xcb);
+            fds.push(libc::pollfd {
+                fd: xcb_sys::xcb_get_file_descriptor(client.connection.xcb),
+                events: libc::POLLIN,
+                revents: 0,
+            });
+            if let Some(ref ime) = client.ime {
+                fds.push(libc::pollfd {
+                    fd: ime.fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                });
+            }
+        }
+        fds.push(libc::pollfd {
+            fd: wake_pipe.map(|pipe| pipe.read_fd).unwrap_or(-1),
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        let timeout = match timeout {
+            None => -1,
+            Some(timeout) => timeout_millis(timeout),
+        };
+        if libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err!(IoError("poll"): err));
+            }
+        }
+        if let Some(wake_pipe) = wake_pipe {
+            wake_pipe.drain();
+        }
+        Ok(())
+    }
+}
+
+impl<W: 'static + Clone> IClient for Client<W> {
+    type PixelFormat = PixelFormat;
+    type TrayIcon = TrayIcon<W>;
+    type Window = Window<W>;
+    type WindowBuilder = WindowBuilder<W>;
+    type WindowId = W;
+
+    /// Chooses a pixel format on the default X11 screen.
+    fn choose_pixel_format(&self, requirements: &PixelFormatRequirements) -> Result<PixelFormat> {
+        self.default_screen().choose_pixel_format(requirements)
+    }
+
+    fn create_tray_icon(&self, id: W, icon: &Icon) -> Result<TrayIcon<W>> {
+        TrayIcon::new(self, id, icon)
+    }
+
+    fn default_pixel_format(&self) -> PixelFormat {
+        self.default_screen().default_pixel_format()
+    }
+
+    /// Returns the XInput 2 devices, including master devices.
+    fn devices(&self) -> Result<Devices> {
+        if !self.xinput2 {
+            return Err(err!(Unsupported("XInput 2 is required to list devices")));
+        }
+        Ok(Devices::new(query_devices(&self.connection)?))
+    }
+
+    fn inhibit_screensaver(&self, inhibit: bool) -> Result<()> {
+        if self.screensaver_inhibited.get() == inhibit {
+            return Ok(());
+        }
+
+        let xcb = self.connection.xcb;
+
+        unsafe {
+            let ext_ptr = xcb_sys::xcb_get_extension_data(xcb,
+                                                          &raw mut xcb_sys::xcb_screensaver_id);
+            if ext_ptr.is_null() || (*ext_ptr).present == 0 {
+                return Err(err!(Unsupported("MIT-SCREEN-SAVER extension unavailable")));
+            }
+
+            // `ScreenSaverSuspend` was added in version 1.1 of the extension.
+            let cookie = xcb_sys::xcb_screensaver_query_version(xcb, 1, 1);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_screensaver_query_version_reply(xcb, cookie,
+                                                                         &mut err_ptr);
+            let reply = Reply::new("X_ScreenSaverQueryVersion", reply_ptr, err_ptr)?;
+            if (reply.server_major_version, reply.server_minor_version) < (1, 1) {
+                return Err(err!(Unsupported("MIT-SCREEN-SAVER 1.1 unavailable")));
+            }
+
+            // The server counts suspensions per client and releases them when the connection
+            // closes, so each suspension must be matched by exactly one resumption.
+            xcb_sys::xcb_screensaver_suspend(xcb, u32::from(inhibit));
+        }
+
+        self.screensaver_inhibited.set(inhibit);
+        Ok(())
+    }
+
+    /// Returns the monitors attached to the default X11 screen.
+    fn monitors(&self) -> Result<Monitors> {
+        Ok(Monitors::new(query_monitors(&self.connection, &self.atoms, &self.default_screen())?))
+    }
+
+    fn poll_events(&self) -> Result<Vec<Event<W>>> {
+        let events = RefCell::new(Vec::new());
+        let f = |event| events.borrow_mut().push(event);
+
+        unsafe {
+            xcb_sys::xcb_flush(self.connection.xcb);
+            self.check_connection()?;
+
+            loop {
+                let event_ptr = xcb_sys::xcb_poll_for_event(self.connection.xcb);
+                if event_ptr.is_null() {
+                    break;
                 }
+                let result = self.handle_x_event(event_ptr, &f);
+                libc::free(event_ptr as *mut _);
+                result?;
             }
         }
 
+        self.handle_ime_events(&f);
+
+        Ok(events.into_inner())
+    }
+
+    fn run<F, R>(&self, main_loop: &MainLoop, f: &F) -> Result<()>
+        where F: Fn(Event<W>) -> R, R: Into<ControlFlow>
+    {
+        Client::run_all(&[self], main_loop, f)
+    }
+
+    /// Sets `WM_CLASS` and `_GTK_APPLICATION_ID` on the client's windows, including windows
+    /// built later.
+    fn set_app_id(&self, app_id: &str) -> Result<()> {
+        *self.app_id.borrow_mut() = Some(app_id.to_string());
+        for window in self.windows() {
+            window.set_app_id(app_id)?;
+        }
         Ok(())
     }
 
+    /// Returns the theme published through XSETTINGS by the desktop's settings manager.
+    fn theme(&self) -> Option<Theme> {
+        self.theme.get()
+    }
+
+    fn wait_events(&self, timeout: Option<Duration>) -> Result<Vec<Event<W>>> {
+        let events = self.poll_events()?;
+        if !events.is_empty() {
+            return Ok(events);
+        }
+
+        // Queued events are handled by `wait_for_events` itself.
+        let events = RefCell::new(Vec::new());
+        unsafe {
+            Client::wait_for_events(&[self], &|event| events.borrow_mut().push(event), None,
+                                    timeout)?;
+        }
+        let mut events = events.into_inner();
+        events.extend(self.poll_events()?);
+        Ok(events)
+    }
+
     fn window(&self) -> WindowBuilder<W> {
         WindowBuilder::new(self)
     }
+
+    fn windows(&self) -> Vec<Window<W>> {
+        // Destroyed windows stay registered until their `DestroyNotify` event arrives.
+        self.window_manager.windows()
+            .into_iter()
+            .filter(|data| data.try_xid().is_ok())
+            .map(|data| Window::from_data(self, data))
+            .collect()
+    }
+}
+
+/// Self-pipe used by main loop proxies to wake a waiting main loop.
+struct WakePipe {
+    read_fd: RawFd,
+    write_fd: RawFd,
+}
+
+impl WakePipe {
+    /// Discards pending wake-ups.
+    fn drain(&self) {
+        let mut buf = [0u8; 64];
+
+        unsafe {
+            while libc::read(self.read_fd, buf.as_mut_ptr() as *mut _, buf.len()) > 0 {}
+        }
+    }
+
+    fn new() -> Result<WakePipe> {
+        let mut fds = [0; 2];
+
+        unsafe {
+            if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) != 0 {
+                return Err(err!(IoError("pipe2"): std::io::Error::last_os_error()));
+            }
+        }
+
+        Ok(WakePipe { read_fd: fds[0], write_fd: fds[1] })
+    }
+
+    /// Returns a function which wakes the main loop. The pipe must outlive the function.
+    fn waker(&self) -> Box<dyn Fn() + Send> {
+        let write_fd = self.write_fd;
+
+        // A full pipe already has a wake-up pending, so write errors are ignored.
+        Box::new(move || unsafe {
+            libc::write(write_fd, [1u8].as_ptr() as *const _, 1);
+        })
+    }
+}
+
+impl Drop for WakePipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read_fd);
+            libc::close(self.write_fd);
+        }
+    }
 }
 
 /// X11 screen type.
@@ -427,6 +1338,22 @@ pub struct Screen {
 }
 
 impl Screen {
+    /// Chooses the OpenGL pixel format which best satisfies `requirements`.
+    pub fn choose_pixel_format(&self, requirements: &PixelFormatRequirements)
+        -> Result<PixelFormat>
+    {
+        // Visuals with an alpha channel are avoided because compositing managers would blend the
+        // window with whatever is behind it.
+        self.gl_pixel_formats()?
+            .into_iter()
+            .filter(|pf| requirements.is_satisfied_by(pf.depth_bits(), pf.is_double_buffered(),
+                                                      pf.samples(), pf.stencil_bits()))
+            .min_by_key(|pf| (requirements.score(pf.depth_bits(), pf.is_double_buffered(),
+                                                 pf.samples(), pf.stencil_bits()),
+                              pf.alpha_mask() != 0))
+            .ok_or_else(|| err!(Unsupported("no pixel format satisfies the requirements")))
+    }
+
     /// Gets the underlying connection.
     pub fn connection(&self) -> &Rc<Connection> {
         &self.connection
@@ -449,6 +1376,88 @@ impl Screen {
         panic!("can't find X11 root visual");
     }
 
+    /// Gets the pixel formats which support OpenGL rendering to windows. Each is a visual paired
+    /// with a GLX framebuffer configuration.
+    pub fn gl_pixel_formats(&self) -> Result<Vec<PixelFormat>> {
+        const GLX_DEPTH_SIZE: u32 = 12;
+        const GLX_DOUBLEBUFFER: u32 = 5;
+        const GLX_DRAWABLE_TYPE: u32 = 0x8010;
+        const GLX_FBCONFIG_ID: u32 = 0x8013;
+        const GLX_RENDER_TYPE: u32 = 0x8011;
+        const GLX_RGBA_BIT: u32 = 0x1;
+        const GLX_SAMPLES: u32 = 100001;
+        const GLX_STENCIL_SIZE: u32 = 13;
+        const GLX_VISUAL_ID: u32 = 0x800b;
+        const GLX_WINDOW_BIT: u32 = 0x1;
+
+        let xcb = self.connection.xcb;
+        let reply;
+
+        unsafe {
+            let ext_ptr = xcb_sys::xcb_get_extension_data(xcb, &raw mut xcb_sys::xcb_glx_id);
+            if ext_ptr.is_null() || (*ext_ptr).present == 0 {
+                return Err(err!(Unsupported("GLX extension unavailable")));
+            }
+
+            let cookie = xcb_sys::xcb_glx_get_fb_configs(xcb, u32::from(self.num));
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_glx_get_fb_configs_reply(xcb, cookie, &mut err_ptr);
+            reply = Reply::new("X_GLXGetFBConfigs", reply_ptr, err_ptr)?;
+        }
+
+        // The reply holds `num_properties` attribute/value pairs for each configuration.
+        let num_properties = reply.num_properties as usize;
+        let properties = unsafe {
+            std::slice::from_raw_parts(
+                xcb_sys::xcb_glx_get_fb_configs_property_list(&*reply),
+                xcb_sys::xcb_glx_get_fb_configs_property_list_length(&*reply) as usize)
+        };
+        let visuals: Vec<PixelFormat> = self.pixel_formats().collect();
+        let mut pixel_formats = Vec::new();
+        if num_properties == 0 {
+            return Ok(pixel_formats);
+        }
+
+        for config in properties.chunks_exact(num_properties * 2) {
+            let mut fb_config = FbConfig {
+                depth_bits: 0,
+                double_buffered: false,
+                id: 0,
+                samples: 0,
+                stencil_bits: 0,
+            };
+            let mut drawable_type = 0;
+            let mut render_type = 0;
+            let mut visual_id = 0;
+
+            for pair in config.chunks_exact(2) {
+                let value = pair[1];
+                let bits = u8::try_from(value).unwrap_or(u8::MAX);
+                match pair[0] {
+                    GLX_DEPTH_SIZE => fb_config.depth_bits = bits,
+                    GLX_DOUBLEBUFFER => fb_config.double_buffered = value != 0,
+                    GLX_DRAWABLE_TYPE => drawable_type = value,
+                    GLX_FBCONFIG_ID => fb_config.id = value,
+                    GLX_RENDER_TYPE => render_type = value,
+                    GLX_SAMPLES => fb_config.samples = bits,
+                    GLX_STENCIL_SIZE => fb_config.stencil_bits = bits,
+                    GLX_VISUAL_ID => visual_id = value,
+                    _ => (),
+                }
+            }
+
+            // Configurations without a visual can only be used for offscreen rendering.
+            if drawable_type & GLX_WINDOW_BIT == 0 || render_type & GLX_RGBA_BIT == 0 {
+                continue;
+            }
+            if let Some(visual) = visuals.iter().find(|pf| pf.visual_id() == visual_id) {
+                pixel_formats.push(visual.clone().with_fb_config(fb_config));
+            }
+        }
+
+        Ok(pixel_formats)
+    }
+
     /// Gets the screen number.
     pub fn num(&self) -> u8 {
         self.num
@@ -484,6 +1493,11 @@ impl Screen {
         }
     }
 
+    /// Gets a pixel format with an alpha channel, if the screen has one.
+    pub fn transparent_pixel_format(&self) -> Option<PixelFormat> {
+        self.pixel_formats().find(|pf| pf.alpha_mask() != 0)
+    }
+
     /// Gets the XCB screen struct pointer.
     pub fn xcb_screen_ptr(&self) -> *mut xcb_sys::xcb_screen_t {
         self.screen_ptr
@@ -523,11 +1537,13 @@ impl Iterator for PixelFormats {
 
     fn next(&mut self) -> Option<PixelFormat> {
         unsafe {
+            // Each depth has its own list of visuals, some of which may be empty.
             while self.visual_iter.rem == 0 {
-                match self.depth_iter.rem {
-                    0 => return None,
-                    _ => xcb_sys::xcb_depth_next(&mut self.depth_iter),
+                if self.depth_iter.rem <= 1 {
+                    return None;
                 }
+                xcb_sys::xcb_depth_next(&mut self.depth_iter);
+                self.visual_iter = xcb_sys::xcb_depth_visuals_iterator(self.depth_iter.data);
             }
 
             let next = PixelFormat::new(&self.connection, self.screen_num,
@@ -538,6 +1554,220 @@ impl Iterator for PixelFormats {
     }
 }
 
+/// Describes an X error, including the name of the request which caused it.
+unsafe fn describe_x_error(connection: &Connection, err: *const xcb_sys::xcb_generic_error_t)
+                           -> String
+{
+    let error_name = match (*err).error_code {
+        code @ 1..=17 => CORE_ERROR_NAMES[code as usize - 1].to_string(),
+        code => format!("error {}", code),
+    };
+    let request_name = match (*err).major_code {
+        major @ 1..=119 => CORE_REQUEST_NAMES[major as usize - 1].to_string(),
+        127 => "NoOperation".to_string(),
+        major => {
+            let minor = (*err).minor_code;
+            match extension_name(connection, major) {
+                Some(name) => format!("{}:{}", name, minor),
+                None => format!("request {}:{}", major, minor),
+            }
+        },
+    };
+
+    format!("{} in {} (resource 0x{:x}, sequence {})",
+            error_name, request_name, (*err).resource_id, (*err).full_sequence)
+}
+
+/// Asks the server not to send synthetic key release events for auto-repeated keys. Returns false
+/// if the server doesn't support the flag. XKB must have been initialized by [init_xkb].
+fn enable_detectable_auto_repeat(connection: &Connection) -> bool {
+    let flag = xcb_sys::XCB_XKB_PER_CLIENT_FLAG_DETECTABLE_AUTO_REPEAT as u32;
+
+    unsafe {
+        let cookie = xcb_sys::xcb_xkb_per_client_flags(
+            connection.xcb, xcb_sys::XCB_XKB_ID_USE_CORE_KBD as u16, flag, flag, 0, 0, 0);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_xkb_per_client_flags_reply(connection.xcb, cookie,
+                                                                &mut err_ptr);
+        match Reply::new("XkbPerClientFlags", reply_ptr, err_ptr) {
+            Ok(ref reply) => reply.value & flag != 0,
+            Err(_) => false,
+        }
+    }
+}
+
+/// Finds the name of the extension which owns a major opcode. Only extensions used by the driver
+/// are recognized.
+fn extension_name(connection: &Connection, major_opcode: u8) -> Option<&'static str> {
+    unsafe {
+        let extensions = [
+            ("GLX", &raw mut xcb_sys::xcb_glx_id),
+            ("MIT-SCREEN-SAVER", &raw mut xcb_sys::xcb_screensaver_id),
+            ("RANDR", &raw mut xcb_sys::xcb_randr_id),
+            ("RENDER", &raw mut xcb_sys::xcb_render_id),
+            ("XInputExtension", &raw mut xcb_sys::xcb_input_id),
+            ("XKEYBOARD", &raw mut xcb_sys::xcb_xkb_id),
+        ];
+
+        for (name, ext) in extensions {
+            let ext_ptr = xcb_sys::xcb_get_extension_data(connection.xcb, ext);
+            if !ext_ptr.is_null() && (*ext_ptr).present != 0
+               && (*ext_ptr).major_opcode == major_opcode
+            {
+                return Some(name);
+            }
+        }
+    }
+
+    None
+}
+
+/// Finds a string setting in the value of an `_XSETTINGS_SETTINGS` property. Returns `None` if
+/// the setting is missing or the value is malformed.
+fn find_xsettings_string<'a>(data: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    const TYPE_INTEGER: u8 = 0;
+    const TYPE_STRING: u8 = 1;
+    const TYPE_COLOR: u8 = 2;
+
+    // The first byte gives the byte order of the remaining fields, and strings are padded to a
+    // multiple of four bytes.
+    let msb_first = *data.first()? != 0;
+    let card16 = |offset: usize| -> Option<usize> {
+        let bytes = data.get(offset..offset + 2)?.try_into().ok()?;
+        let value = if msb_first { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) };
+        Some(usize::from(value))
+    };
+    let card32 = |offset: usize| -> Option<usize> {
+        let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+        let value = if msb_first { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) };
+        usize::try_from(value).ok()
+    };
+    let pad = |len: usize| (len + 3) & !3;
+
+    let num_settings = card32(8)?;
+    let mut offset = 12;
+    for _ in 0..num_settings {
+        let setting_type = *data.get(offset)?;
+        let name_len = card16(offset + 2)?;
+        let setting_name = data.get(offset + 4..offset + 4 + name_len)?;
+
+        // Skip the name and the serial of the setting's last change.
+        offset += 4 + pad(name_len) + 4;
+        match setting_type {
+            TYPE_COLOR => offset += 8,
+            TYPE_INTEGER => offset += 4,
+            TYPE_STRING => {
+                let len = card32(offset)?;
+                let value = data.get(offset + 4..offset + 4 + len)?;
+                if setting_name == name.as_bytes() {
+                    return Some(value);
+                }
+                offset += 4 + pad(len);
+            },
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+/// Initializes XKB and selects keyboard group change events. Returns the XKB extension's first
+/// event code, or `None` if XKB is unavailable.
+fn init_xkb(connection: &Connection) -> Option<u8> {
+    unsafe {
+        let ext_ptr = xcb_sys::xcb_get_extension_data(connection.xcb, &raw mut xcb_sys::xcb_xkb_id);
+        if ext_ptr.is_null() || (*ext_ptr).present == 0 {
+            return None;
+        }
+
+        let cookie = xcb_sys::xcb_xkb_use_extension(connection.xcb, 1, 0);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_xkb_use_extension_reply(connection.xcb, cookie,
+                                                             &mut err_ptr);
+        match Reply::new("XkbUseExtension", reply_ptr, err_ptr) {
+            Ok(ref reply) if reply.supported != 0 => (),
+            _ => return None,
+        }
+
+        let group_state = xcb_sys::XCB_XKB_STATE_PART_GROUP_STATE as u16;
+        let mut details: xcb_sys::xcb_xkb_select_events_details_t = std::mem::zeroed();
+        details.affectState = group_state;
+        details.stateDetails = group_state;
+        xcb_sys::xcb_xkb_select_events(connection.xcb, xcb_sys::XCB_XKB_ID_USE_CORE_KBD as u16,
+                                       xcb_sys::XCB_XKB_EVENT_TYPE_STATE_NOTIFY as u16, 0, 0, 0,
+                                       0, &details as *const _ as *const _);
+
+        Some((*ext_ptr).first_event)
+    }
+}
+
+/// Gets the DPI from the `Xft.dpi` resource, falling back to the physical DPI of the screen.
+fn query_dpi(connection: &Connection, screen: &Screen) -> Result<u32> {
+    let resources = connection.get_property(screen.root(), xcb_sys::XCB_ATOM_RESOURCE_MANAGER,
+                                            xcb_sys::XCB_ATOM_STRING, u32::MAX)?;
+    for line in String::from_utf8_lossy(resources.value8()).lines() {
+        if let Some(value) = line.strip_prefix("Xft.dpi:") {
+            if let Ok(dpi) = value.trim().parse::<f32>() {
+                if dpi >= 1.0 {
+                    return Ok(dpi.round() as u32);
+                }
+            }
+        }
+    }
+
+    unsafe {
+        let screen_ptr = screen.xcb_screen_ptr();
+        let width_px = u32::from((*screen_ptr).width_in_pixels);
+        let width_mm = u32::from((*screen_ptr).width_in_millimeters);
+        if width_mm == 0 {
+            return Ok(96);
+        }
+        Ok((width_px * 254 + width_mm * 5) / (width_mm * 10))
+    }
+}
+
+/// Reads the preferred theme from the XSETTINGS `Net/ThemeName` setting published by the
+/// settings manager window `owner`. Themes whose names contain "dark", such as `Adwaita-dark`,
+/// are considered dark.
+fn query_theme(connection: &Connection, atoms: &Atoms, owner: u32) -> Result<Option<Theme>> {
+    if owner == 0 {
+        return Ok(None);
+    }
+
+    let settings = connection.get_property(owner, atoms._XSETTINGS_SETTINGS,
+                                           atoms._XSETTINGS_SETTINGS, u32::MAX)?;
+    Ok(find_xsettings_string(settings.value8(), "Net/ThemeName").map(|name| {
+        match String::from_utf8_lossy(name).to_ascii_lowercase().contains("dark") {
+            false => Theme::Light,
+            true => Theme::Dark,
+        }
+    }))
+}
+
+/// Finds the owner of an `_XSETTINGS_S<n>` selection and watches it for changes and destruction.
+/// Returns zero if no settings manager is running.
+fn watch_xsettings(connection: &Connection, selection: u32) -> Result<u32> {
+    let owner;
+
+    unsafe {
+        let cookie = xcb_sys::xcb_get_selection_owner(connection.xcb, selection);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_get_selection_owner_reply(connection.xcb, cookie,
+                                                               &mut err_ptr);
+        owner = Reply::new("X_GetSelectionOwner", reply_ptr, err_ptr)?.owner;
+
+        if owner != 0 {
+            let values = [(xcb_sys::XCB_EVENT_MASK_PROPERTY_CHANGE
+                           | xcb_sys::XCB_EVENT_MASK_STRUCTURE_NOTIFY) as u32];
+            xcb_sys::xcb_change_window_attributes(connection.xcb, owner,
+                                                  xcb_sys::XCB_CW_EVENT_MASK,
+                                                  values.as_ptr() as *const _);
+        }
+    }
+
+    Ok(owner)
+}
+
 /// Defines the `Atoms` type.
 macro_rules! define_atoms {
     { $($name:ident,)* } => {
@@ -560,6 +1790,102 @@ macro_rules! define_atoms {
 }
 
 define_atoms! {
+    _GTK_APPLICATION_ID,
+    _GTK_THEME_VARIANT,
+    MANAGER,
+    _NET_CURRENT_DESKTOP,
+    _NET_FRAME_EXTENTS,
+    _NET_SYSTEM_TRAY_OPCODE,
+    _NET_WM_MOVERESIZE,
+    _NET_WM_NAME,
+    _NET_WM_STATE,
+    _NET_WM_STATE_DEMANDS_ATTENTION,
+    _NET_WM_STATE_FULLSCREEN,
+    _NET_WM_STATE_MODAL,
+    _NET_WM_WINDOW_OPACITY,
+    _NET_WM_WINDOW_TYPE,
+    _NET_WM_WINDOW_TYPE_DIALOG,
+    _NET_WM_WINDOW_TYPE_MENU,
+    _NET_WM_WINDOW_TYPE_NORMAL,
+    _NET_WM_WINDOW_TYPE_SPLASH,
+    _NET_WM_WINDOW_TYPE_TOOLTIP,
+    _NET_WM_WINDOW_TYPE_UTILITY,
+    _NET_WM_XAPP_PROGRESS,
+    _NET_WORKAREA,
+    UTF8_STRING,
     WM_DELETE_WINDOW,
     WM_PROTOCOLS,
+    _XEMBED_INFO,
+    _XSETTINGS_SETTINGS,
+}
+
+/// Extracts the unaccelerated X and Y motion from an XInput2 raw motion event.
+unsafe fn raw_motion_delta(ev: *const xcb_sys::xcb_input_raw_motion_event_t) -> Vec2<f64> {
+    let mask = std::slice::from_raw_parts(
+        xcb_sys::xcb_input_raw_button_press_valuator_mask(ev),
+        xcb_sys::xcb_input_raw_button_press_valuator_mask_length(ev) as usize);
+    let values = std::slice::from_raw_parts(
+        xcb_sys::xcb_input_raw_button_press_axisvalues_raw(ev),
+        xcb_sys::xcb_input_raw_button_press_axisvalues_raw_length(ev) as usize);
+
+    // Values are only present for valuators set in the mask. Valuators 0 and 1 are the X and Y
+    // axes of a pointer device.
+    let mut delta = Vec2::new(0.0, 0.0);
+    let mut values = values.iter();
+    for axis in 0..2 {
+        if mask.first().map(|&bits| bits & 1 << axis != 0).unwrap_or(false) {
+            if let Some(value) = values.next() {
+                let value = f64::from(value.integral) + f64::from(value.frac) / 4294967296.0;
+                if axis == 0 { delta.x = value } else { delta.y = value }
+            }
+        }
+    }
+    delta
+}
+
+/// Converts a timeout to milliseconds for `poll`, rounding up so timers aren't woken early.
+fn timeout_millis(timeout: Duration) -> i32 {
+    let millis = (timeout.as_nanos() + 999_999) / 1_000_000;
+    i32::try_from(millis).unwrap_or(i32::MAX)
 }
+
+/// Gets the keyboard group from the state of a core input event. XKB reports it in bits 13 and 14
+/// to clients which have initialized XKB.
+fn xkb_group(state: u16) -> u8 {
+    ((state >> 13) & 3) as u8
+}
+
+/// Names of core X errors, starting with error code 1.
+const CORE_ERROR_NAMES: [&str; 17] = [
+    "BadRequest", "BadValue", "BadWindow", "BadPixmap", "BadAtom", "BadCursor", "BadFont",
+    "BadMatch", "BadDrawable", "BadAccess", "BadAlloc", "BadColormap", "BadGContext", "BadIDChoice",
+    "BadName", "BadLength", "BadImplementation",
+];
+
+/// Names of core X requests, starting with major opcode 1.
+const CORE_REQUEST_NAMES: [&str; 119] = [
+    "CreateWindow", "ChangeWindowAttributes", "GetWindowAttributes", "DestroyWindow",
+    "DestroySubwindows", "ChangeSaveSet", "ReparentWindow", "MapWindow", "MapSubwindows",
+    "UnmapWindow", "UnmapSubwindows", "ConfigureWindow", "CirculateWindow", "GetGeometry",
+    "QueryTree", "InternAtom", "GetAtomName", "ChangeProperty", "DeleteProperty", "GetProperty",
+    "ListProperties", "SetSelectionOwner", "GetSelectionOwner", "ConvertSelection", "SendEvent",
+    "GrabPointer", "UngrabPointer", "GrabButton", "UngrabButton", "ChangeActivePointerGrab",
+    "GrabKeyboard", "UngrabKeyboard", "GrabKey", "UngrabKey", "AllowEvents", "GrabServer",
+    "UngrabServer", "QueryPointer", "GetMotionEvents", "TranslateCoordinates", "WarpPointer",
+    "SetInputFocus", "GetInputFocus", "QueryKeymap", "OpenFont", "CloseFont", "QueryFont",
+    "QueryTextExtents", "ListFonts", "ListFontsWithInfo", "SetFontPath", "GetFontPath",
+    "CreatePixmap", "FreePixmap", "CreateGC", "ChangeGC", "CopyGC", "SetDashes",
+    "SetClipRectangles", "FreeGC", "ClearArea", "CopyArea", "CopyPlane", "PolyPoint", "PolyLine",
+    "PolySegment", "PolyRectangle", "PolyArc", "FillPoly", "PolyFillRectangle", "PolyFillArc",
+    "PutImage", "GetImage", "PolyText8", "PolyText16", "ImageText8", "ImageText16",
+    "CreateColormap", "FreeColormap", "CopyColormapAndFree", "InstallColormap", "UninstallColormap",
+    "ListInstalledColormaps", "AllocColor", "AllocNamedColor", "AllocColorCells",
+    "AllocColorPlanes", "FreeColors", "StoreColors", "StoreNamedColor", "QueryColors",
+    "LookupColor", "CreateCursor", "CreateGlyphCursor", "FreeCursor", "RecolorCursor",
+    "QueryBestSize", "QueryExtension", "ListExtensions", "ChangeKeyboardMapping",
+    "GetKeyboardMapping", "ChangeKeyboardControl", "GetKeyboardControl", "Bell",
+    "ChangePointerControl", "GetPointerControl", "SetScreenSaver", "GetScreenSaver", "ChangeHosts",
+    "ListHosts", "SetAccessControl", "SetCloseDownMode", "KillClient", "RotateProperties",
+    "ForceScreenSaver", "SetPointerMapping", "GetPointerMapping", "SetModifierMapping",
+    "GetModifierMapping",
+];
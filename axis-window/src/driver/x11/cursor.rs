@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use vectorial::Vec2;
+
+use crate::cursor::{Cursor, CursorImage, CursorShape};
+use crate::driver::x11::client::{Connection, Reply};
+use crate::error::Result;
+
+/// Creates a server-side cursor. The caller is responsible for freeing it.
+pub fn create_cursor(connection: &Connection, root: u32, cursor: &Cursor) -> Result<u32> {
+    match *cursor {
+        Cursor::Image(ref image) => create_image_cursor(connection, root, image),
+        Cursor::Shape(CursorShape::Hidden) => {
+            let image = CursorImage::new(Vec2::new(1, 1), Vec2::new(0, 0), vec![[0; 4]])?;
+            create_image_cursor(connection, root, &image)
+        },
+        Cursor::Shape(shape) => create_shape_cursor(connection, shape),
+    }
+}
+
+/// Creates a cursor from the standard X11 cursor font.
+fn create_shape_cursor(connection: &Connection, shape: CursorShape) -> Result<u32> {
+    const FONT_NAME: &[u8] = b"cursor";
+    let glyph: u16 = match shape {
+        CursorShape::Arrow => 68, // XC_left_ptr
+        CursorShape::Crosshair => 34, // XC_crosshair
+        CursorShape::Hand => 60, // XC_hand2
+        CursorShape::Hidden => unreachable!(),
+        CursorShape::Move => 52, // XC_fleur
+        CursorShape::NotAllowed => 0, // XC_X_cursor
+        CursorShape::ResizeEw => 108, // XC_sb_h_double_arrow
+        CursorShape::ResizeNesw => 12, // XC_bottom_left_corner
+        CursorShape::ResizeNs => 116, // XC_sb_v_double_arrow
+        CursorShape::ResizeNwse => 14, // XC_bottom_right_corner
+        CursorShape::Text => 152, // XC_xterm
+        CursorShape::Wait => 150, // XC_watch
+    };
+    let xcb = connection.xcb_connection_ptr();
+
+    unsafe {
+        let font = xcb_sys::xcb_generate_id(xcb);
+        xcb_sys::xcb_open_font(xcb, font, FONT_NAME.len() as u16, FONT_NAME.as_ptr() as *const _);
+        let cursor = xcb_sys::xcb_generate_id(xcb);
+        xcb_sys::xcb_create_glyph_cursor(xcb, cursor, font, font, glyph, glyph + 1,
+                                         0, 0, 0, 0xffff, 0xffff, 0xffff);
+        xcb_sys::xcb_close_font(xcb, font);
+        Ok(cursor)
+    }
+}
+
+/// Creates an ARGB cursor with the RENDER extension.
+fn create_image_cursor(connection: &Connection, root: u32, image: &CursorImage) -> Result<u32> {
+    let xcb = connection.xcb_connection_ptr();
+    let format = find_argb32_format(connection)?;
+    let size = image.size();
+    let hotspot = image.hotspot();
+
+    // RENDER expects premultiplied ARGB in the server's byte order.
+    let lsb_first = unsafe {
+        (*xcb_sys::xcb_get_setup(xcb)).image_byte_order == xcb_sys::XCB_IMAGE_ORDER_LSB_FIRST as u8
+    };
+    let mut data = Vec::with_capacity(image.pixels().len() * 4);
+    for &[r, g, b, a] in image.pixels() {
+        let premultiply = |c: u8| (u32::from(c) * u32::from(a) / 255);
+        let pixel = u32::from(a) << 24 | premultiply(r) << 16 | premultiply(g) << 8
+            | premultiply(b);
+        data.extend_from_slice(&if lsb_first { pixel.to_le_bytes() } else { pixel.to_be_bytes() });
+    }
+
+    unsafe {
+        let pixmap = xcb_sys::xcb_generate_id(xcb);
+        xcb_sys::xcb_create_pixmap(xcb, 32, pixmap, root, size.x as u16, size.y as u16);
+        let gc = xcb_sys::xcb_generate_id(xcb);
+        xcb_sys::xcb_create_gc(xcb, gc, pixmap, 0, std::ptr::null());
+        xcb_sys::xcb_put_image(xcb, xcb_sys::XCB_IMAGE_FORMAT_Z_PIXMAP as u8, pixmap, gc,
+                               size.x as u16, size.y as u16, 0, 0, 0, 32, data.len() as u32,
+                               data.as_ptr());
+        xcb_sys::xcb_free_gc(xcb, gc);
+
+        let picture = xcb_sys::xcb_generate_id(xcb);
+        xcb_sys::xcb_render_create_picture(xcb, picture, pixmap, format, 0, std::ptr::null());
+        xcb_sys::xcb_free_pixmap(xcb, pixmap);
+
+        let cursor = xcb_sys::xcb_generate_id(xcb);
+        xcb_sys::xcb_render_create_cursor(xcb, cursor, picture, hotspot.x as u16,
+                                          hotspot.y as u16);
+        xcb_sys::xcb_render_free_picture(xcb, picture);
+        Ok(cursor)
+    }
+}
+
+/// Finds the standard 32-bit ARGB picture format.
+fn find_argb32_format(connection: &Connection) -> Result<u32> {
+    let xcb = connection.xcb_connection_ptr();
+
+    unsafe {
+        let ext_ptr = xcb_sys::xcb_get_extension_data(xcb, &raw mut xcb_sys::xcb_render_id);
+        if ext_ptr.is_null() || (*ext_ptr).present == 0 {
+            return Err(err!(Unsupported("RENDER extension is required for image cursors")));
+        }
+
+        let cookie = xcb_sys::xcb_render_query_pict_formats(xcb);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_render_query_pict_formats_reply(xcb, cookie, &mut err_ptr);
+        let reply = Reply::new("RenderQueryPictFormats", reply_ptr, err_ptr)?;
+        let formats = std::slice::from_raw_parts(
+            xcb_sys::xcb_render_query_pict_formats_formats(&*reply),
+            xcb_sys::xcb_render_query_pict_formats_formats_length(&*reply) as usize);
+
+        for format in formats {
+            let direct = &format.direct;
+            if format.type_ == xcb_sys::XCB_RENDER_PICT_TYPE_DIRECT as u8 && format.depth == 32
+                && direct.alpha_shift == 24 && direct.alpha_mask == 0xff
+                && direct.red_shift == 16 && direct.red_mask == 0xff
+                && direct.green_shift == 8 && direct.green_mask == 0xff
+                && direct.blue_shift == 0 && direct.blue_mask == 0xff
+            {
+                return Ok(format.id);
+            }
+        }
+    }
+
+    Err(err!(Unsupported("no ARGB32 picture format")))
+}
@@ -0,0 +1,271 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::cell::Cell;
+
+use vectorial::Vec2;
+
+use crate::device::{Device, DeviceKind};
+use crate::driver::x11::client::{Connection, Reply};
+use crate::error::Result;
+use crate::Coord;
+
+/// Converts the absolute positions of XInput 2 scroll valuators into scroll deltas.
+#[derive(Default)]
+pub struct ScrollState {
+    valuators: Vec<ScrollValuator>,
+}
+
+impl ScrollState {
+    /// Returns the scroll delta of an XInput 2 motion event in 120ths of a notch, or `None` if the
+    /// event didn't scroll.
+    pub unsafe fn delta(&self, ev: *const xcb_sys::xcb_input_motion_event_t)
+        -> Option<Vec2<Coord>>
+    {
+        let mask = std::slice::from_raw_parts(
+            xcb_sys::xcb_input_button_press_valuator_mask(ev),
+            xcb_sys::xcb_input_button_press_valuator_mask_length(ev) as usize);
+        let values = std::slice::from_raw_parts(
+            xcb_sys::xcb_input_button_press_axisvalues(ev),
+            xcb_sys::xcb_input_button_press_axisvalues_length(ev) as usize);
+
+        // Values are only present for valuators set in the mask. Valuator numbers belong to the
+        // slave device which generated the event.
+        let mut notches = Vec2::new(0.0, 0.0);
+        let mut values = values.iter();
+        for number in 0..mask.len() * 32 {
+            if mask[number / 32] & 1 << (number % 32) == 0 {
+                continue;
+            }
+            let value = match values.next() {
+                None => break,
+                Some(value) => f64::from(value.integral) + f64::from(value.frac) / 4294967296.0,
+            };
+            let valuator = self.valuators.iter().find(|valuator| {
+                valuator.device_id == (*ev).sourceid && usize::from(valuator.number) == number
+            });
+            if let Some(valuator) = valuator {
+                if let Some(last_value) = valuator.last_value.replace(Some(value)) {
+                    // Scrolling down increases the vertical valuator.
+                    let delta = (value - last_value) / valuator.increment;
+                    if valuator.vertical {
+                        notches.y -= delta;
+                    } else {
+                        notches.x += delta;
+                    }
+                }
+            }
+        }
+
+        let delta = Vec2::new((notches.x * 120.0).round() as Coord,
+                              (notches.y * 120.0).round() as Coord);
+        if delta.x == 0 && delta.y == 0 {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+
+    /// Queries the scroll valuators of every device. Each valuator's position is seeded with its
+    /// current value so that the first scroll event reports a delta. The pointer may have
+    /// scrolled other windows while it was outside of ours, so this is also called when the
+    /// pointer enters a window.
+    pub fn query(connection: &Connection) -> Result<ScrollState> {
+        let mut valuators = Vec::new();
+
+        unsafe {
+            for_each_device(connection, |info| {
+                // A scroll class describes a valuator which is reported by a valuator class of
+                // the same device.
+                let mut values = Vec::new();
+                let first = valuators.len();
+                let mut iter = xcb_sys::xcb_input_xi_device_info_classes_iterator(info);
+                while iter.rem > 0 {
+                    match u32::from((*iter.data).type_) {
+                        xcb_sys::XCB_INPUT_DEVICE_CLASS_TYPE_SCROLL => {
+                            let class = iter.data as *const xcb_sys::xcb_input_scroll_class_t;
+                            let increment = f64::from((*class).increment.integral)
+                                            + f64::from((*class).increment.frac) / 4294967296.0;
+                            if increment != 0.0 {
+                                valuators.push(ScrollValuator {
+                                    device_id: (*class).sourceid,
+                                    increment,
+                                    last_value: Cell::new(None),
+                                    number: (*class).number,
+                                    vertical: u32::from((*class).scroll_type)
+                                              == xcb_sys::XCB_INPUT_SCROLL_TYPE_VERTICAL,
+                                });
+                            }
+                        },
+                        xcb_sys::XCB_INPUT_DEVICE_CLASS_TYPE_VALUATOR => {
+                            let class = iter.data as *const xcb_sys::xcb_input_valuator_class_t;
+                            let value = f64::from((*class).value.integral)
+                                        + f64::from((*class).value.frac) / 4294967296.0;
+                            values.push(((*class).number, value));
+                        },
+                        _ => (),
+                    }
+                    xcb_sys::xcb_input_device_class_next(&mut iter);
+                }
+                for valuator in valuators[first..].iter() {
+                    if let Some(&(_, value)) = values.iter().find(|v| v.0 == valuator.number) {
+                        valuator.last_value.set(Some(value));
+                    }
+                }
+            })?;
+        }
+
+        Ok(ScrollState { valuators })
+    }
+}
+
+/// Scroll valuator of an XInput 2 device.
+struct ScrollValuator {
+    device_id: u16,
+    increment: f64,
+    last_value: Cell<Option<f64>>,
+    number: u16,
+    vertical: bool,
+}
+
+/// Announces XInput 2 support and selects device hierarchy events on the root window. Returns
+/// false if XInput 2 is unavailable.
+pub fn init_xinput2(connection: &Connection, root: u32) -> bool {
+    let xcb = connection.xcb_connection_ptr();
+
+    unsafe {
+        let ext_ptr = xcb_sys::xcb_get_extension_data(xcb, &raw mut xcb_sys::xcb_input_id);
+        if ext_ptr.is_null() || (*ext_ptr).present == 0 {
+            return false;
+        }
+
+        // Version 2.1 added smooth scrolling. The version must be the same in every
+        // `XIQueryVersion` request sent by a client.
+        let cookie = xcb_sys::xcb_input_xi_query_version(xcb, 2, 2);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_input_xi_query_version_reply(xcb, cookie, &mut err_ptr);
+        match Reply::new("XIQueryVersion", reply_ptr, err_ptr) {
+            Ok(ref reply) if reply.major_version >= 2 => (),
+            _ => return false,
+        }
+    }
+
+    select_xi_events(xcb, root, xcb_sys::XCB_INPUT_DEVICE_ALL as u16,
+                     (xcb_sys::XCB_INPUT_XI_EVENT_MASK_DEVICE_CHANGED
+                      | xcb_sys::XCB_INPUT_XI_EVENT_MASK_HIERARCHY) as u32);
+    true
+}
+
+/// Queries the input devices known to the server.
+pub fn query_devices(connection: &Connection) -> Result<Vec<Device>> {
+    let mut devices = Vec::new();
+
+    unsafe {
+        for_each_device(connection, |info| {
+            let name = std::slice::from_raw_parts(
+                xcb_sys::xcb_input_xi_device_info_name(info) as *const u8,
+                xcb_sys::xcb_input_xi_device_info_name_length(info) as usize);
+            let name = String::from_utf8_lossy(name).into_owned();
+            let id = u64::from((*info).deviceid);
+
+            // The attachment of a master device is its paired master, which isn't reported.
+            let attachment = Some(u64::from((*info).attachment));
+            let (kind, master, attachment) = match u32::from((*info).type_) {
+                xcb_sys::XCB_INPUT_DEVICE_TYPE_MASTER_KEYBOARD => {
+                    (DeviceKind::Keyboard, true, None)
+                },
+                xcb_sys::XCB_INPUT_DEVICE_TYPE_MASTER_POINTER => {
+                    (DeviceKind::Pointer, true, None)
+                },
+                xcb_sys::XCB_INPUT_DEVICE_TYPE_SLAVE_KEYBOARD => {
+                    (DeviceKind::Keyboard, false, attachment)
+                },
+                xcb_sys::XCB_INPUT_DEVICE_TYPE_SLAVE_POINTER => {
+                    (DeviceKind::Pointer, false, attachment)
+                },
+                _ => (floating_device_kind(info), false, None),
+            };
+            devices.push(Device::new(id, kind, name).with_attachment(attachment)
+                                                     .with_master(master));
+        })?;
+    }
+
+    Ok(devices)
+}
+
+/// Selects XInput 2 pointer events for scrolling on a window. Does nothing if the XInput
+/// extension is unavailable.
+pub fn select_scroll_events(xcb: *mut xcb_sys::xcb_connection_t, window: u32) {
+    unsafe {
+        let ext_ptr = xcb_sys::xcb_get_extension_data(xcb, &raw mut xcb_sys::xcb_input_id);
+        if ext_ptr.is_null() || (*ext_ptr).present == 0 {
+            return;
+        }
+    }
+
+    select_xi_events(xcb, window, xcb_sys::XCB_INPUT_DEVICE_ALL_MASTER as u16,
+                     (xcb_sys::XCB_INPUT_XI_EVENT_MASK_ENTER
+                      | xcb_sys::XCB_INPUT_XI_EVENT_MASK_MOTION) as u32);
+}
+
+/// Sets a window's XInput 2 event mask for a device. This replaces any mask previously selected
+/// for the same window and device.
+pub fn select_xi_events(xcb: *mut xcb_sys::xcb_connection_t, window: u32, device_id: u16,
+                        mask: u32)
+{
+    #[repr(C)]
+    struct EventMask {
+        head: xcb_sys::xcb_input_event_mask_t,
+        mask: u32,
+    }
+
+    let mask = EventMask {
+        head: xcb_sys::xcb_input_event_mask_t {
+            deviceid: device_id,
+            mask_len: 1,
+        },
+        mask,
+    };
+
+    unsafe {
+        xcb_sys::xcb_input_xi_select_events(xcb, window, 1, &mask.head);
+    }
+}
+
+/// Guesses the kind of a floating device from its input classes.
+unsafe fn floating_device_kind(info: *const xcb_sys::xcb_input_xi_device_info_t) -> DeviceKind {
+    let mut kind = DeviceKind::Other;
+    let mut iter = xcb_sys::xcb_input_xi_device_info_classes_iterator(info);
+    while iter.rem > 0 {
+        match u32::from((*iter.data).type_) {
+            xcb_sys::XCB_INPUT_DEVICE_CLASS_TYPE_KEY => return DeviceKind::Keyboard,
+            xcb_sys::XCB_INPUT_DEVICE_CLASS_TYPE_VALUATOR => kind = DeviceKind::Pointer,
+            _ => (),
+        }
+        xcb_sys::xcb_input_device_class_next(&mut iter);
+    }
+    kind
+}
+
+/// Calls `f` with each device reported by `XIQueryDevice`.
+unsafe fn for_each_device<F>(connection: &Connection, mut f: F) -> Result<()>
+    where F: FnMut(*const xcb_sys::xcb_input_xi_device_info_t)
+{
+    let xcb = connection.xcb_connection_ptr();
+    let cookie = xcb_sys::xcb_input_xi_query_device(xcb, xcb_sys::XCB_INPUT_DEVICE_ALL as u16);
+    let mut err_ptr = std::ptr::null_mut();
+    let reply_ptr = xcb_sys::xcb_input_xi_query_device_reply(xcb, cookie, &mut err_ptr);
+    let reply = Reply::new("XIQueryDevice", reply_ptr, err_ptr)?;
+
+    let mut iter = xcb_sys::xcb_input_xi_query_device_infos_iterator(&*reply);
+    while iter.rem > 0 {
+        f(iter.data);
+        xcb_sys::xcb_input_xi_device_info_next(&mut iter);
+    }
+    Ok(())
+}
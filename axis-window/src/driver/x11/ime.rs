@@ -0,0 +1,614 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+#[cfg(feature = "x11-sys")]
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "x11-sys")]
+use std::collections::HashMap;
+#[cfg(feature = "x11-sys")]
+use std::ffi::CStr;
+use std::os::fd::RawFd;
+#[cfg(feature = "x11-sys")]
+use std::os::raw::{c_char, c_int, c_uint, c_ulong};
+#[cfg(feature = "x11-sys")]
+use std::rc::Rc;
+#[cfg(not(feature = "x11-sys"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use vectorial::Vec2;
+
+use crate::driver::x11::client::Connection;
+use crate::error::Result;
+#[cfg(feature = "x11-sys")]
+use crate::ffi::xlib::{
+    KeyPress,
+    KeyRelease,
+    QueuedAfterFlush,
+    XBufferOverflow,
+    XCloseIM,
+    XCreateIC,
+    XDestroyIC,
+    XFilterEvent,
+    XGetIMValues,
+    XIC,
+    XIM,
+    XIMAbsolutePosition,
+    XIMBackwardChar,
+    XIMCallback,
+    XIMForwardChar,
+    XIMLineEnd,
+    XIMLineStart,
+    XIMPreeditCallbacks,
+    XIMPreeditCaretCallbackStruct,
+    XIMPreeditDrawCallbackStruct,
+    XIMPreeditNothing,
+    XIMProc,
+    XIMStatusNothing,
+    XIMStyle,
+    XIMStyles,
+    XIMText,
+    XLookupBoth,
+    XLookupChars,
+    XNClientWindow,
+    XNDestroyCallback,
+    XNFocusWindow,
+    XNInputStyle,
+    XNPreeditAttributes,
+    XNPreeditCaretCallback,
+    XNPreeditDoneCallback,
+    XNPreeditDrawCallback,
+    XNPreeditStartCallback,
+    XNQueryInputStyle,
+    XNSpotLocation,
+    XOpenIM,
+    XPoint,
+    XPointer,
+    XSetICFocus,
+    XSetICValues,
+    XSetIMValues,
+    XSetLocaleModifiers,
+    XSupportsLocale,
+    XUnsetICFocus,
+    XVaCreateNestedList,
+    Xutf8LookupString,
+    xn,
+};
+
+/// Event reported by an input method for one of the client's windows.
+#[cfg_attr(not(feature = "x11-sys"), allow(dead_code))]
+pub enum ImeEvent {
+    /// Composed text was committed.
+    Commit(String),
+    /// A key event which the input method didn't consume. It's handled like any other key event.
+    Key(xcb_sys::xcb_key_press_event_t),
+    /// The composition text changed. The cursor is a byte offset into the text.
+    Preedit(String, Option<usize>),
+}
+
+/// Connection to an X input method, with an input context for each window.
+///
+/// The client's own display connection can't be used: XCB owns its event queue, but Xlib's input
+/// method protocol waits for replies by reading the Xlib event queue. The input method gets a
+/// display connection of its own, whose events are processed by
+/// [process_events](Ime::process_events).
+#[cfg(feature = "x11-sys")]
+pub struct Ime {
+    contexts: RefCell<HashMap<u32, Box<InputContext>>>,
+    destroyed: Box<Cell<bool>>,
+    display: *mut x11_sys::Display,
+    events: Rc<RefCell<Vec<(u32, ImeEvent)>>>,
+    im: XIM,
+    serial: Cell<c_ulong>,
+    style: XIMStyle,
+}
+
+#[cfg(feature = "x11-sys")]
+impl Ime {
+    /// Creates an input context for a window. Preedit callbacks are only used if the input method
+    /// supports them. Otherwise, the input method draws the composition text itself.
+    pub fn create_context(&self, xid: u32) -> Result<()> {
+        let im = match self.im() {
+            None => return Err(err!(Unsupported("input method server closed"))),
+            Some(im) => im,
+        };
+        let mut context = Box::new(InputContext {
+            caret: Cell::new(0),
+            events: self.events.clone(),
+            ic: std::ptr::null_mut(),
+            preedit: RefCell::new(Vec::new()),
+            xid,
+        });
+        let window = c_ulong::from(xid);
+        let end = std::ptr::null::<c_char>();
+
+        unsafe {
+            context.ic = if self.style & XIMPreeditCallbacks != 0 {
+                // Xlib copies the callback structures, so they only need to outlive `XCreateIC`.
+                let client_data = &*context as *const InputContext as XPointer;
+                let callback = |f: XIMProc| XIMCallback { client_data, callback: Some(f) };
+                let start = callback(preedit_start);
+                let done = callback(preedit_done);
+                let draw = callback(preedit_draw);
+                let caret = callback(preedit_caret);
+                let attributes = XVaCreateNestedList(
+                    0,
+                    xn(XNPreeditStartCallback), &start as *const XIMCallback,
+                    xn(XNPreeditDoneCallback), &done as *const XIMCallback,
+                    xn(XNPreeditDrawCallback), &draw as *const XIMCallback,
+                    xn(XNPreeditCaretCallback), &caret as *const XIMCallback,
+                    end);
+                let ic = XCreateIC(im, xn(XNInputStyle), self.style, xn(XNClientWindow), window,
+                                   xn(XNFocusWindow), window, xn(XNPreeditAttributes), attributes,
+                                   end);
+                x11_sys::XFree(attributes);
+                ic
+            } else {
+                XCreateIC(im, xn(XNInputStyle), self.style, xn(XNClientWindow), window,
+                          xn(XNFocusWindow), window, end)
+            };
+        }
+
+        if context.ic.is_null() {
+            return Err(err!(RuntimeError("XCreateIC failed")));
+        }
+        self.contexts.borrow_mut().insert(xid, context);
+        Ok(())
+    }
+
+    /// Destroys a window's input context, if it has one.
+    pub fn destroy_context(&self, xid: u32) {
+        let context = self.contexts.borrow_mut().remove(&xid);
+        if let Some(context) = context {
+            if self.im().is_some() {
+                unsafe {
+                    XDestroyIC(context.ic);
+                }
+            }
+        }
+    }
+
+    /// Returns the file descriptor of the input method's display connection.
+    pub fn fd(&self) -> RawFd {
+        unsafe {
+            x11_sys::XConnectionNumber(self.display)
+        }
+    }
+
+    /// Passes a key event to the input method. Returns true if the input method consumed it. Keys
+    /// which the input method doesn't use may be passed back later as [ImeEvent::Key].
+    pub unsafe fn filter_key_event(&self, ev: *const xcb_sys::xcb_key_press_event_t) -> bool {
+        if self.ic((*ev).event).is_none() {
+            return false;
+        }
+
+        // Xlib recognizes events which it passed back by their serial numbers, so each event
+        // needs a distinct one.
+        let serial = self.serial.get().wrapping_add(1);
+        self.serial.set(serial);
+
+        let mut event: x11_sys::XEvent = std::mem::zeroed();
+        event.xkey.type_ = match u32::from((*ev).response_type & !0x80) {
+            xcb_sys::XCB_KEY_PRESS => KeyPress,
+            _ => KeyRelease,
+        };
+        event.xkey.serial = serial;
+        event.xkey.send_event = c_int::from((*ev).response_type & 0x80 != 0);
+        event.xkey.display = self.display;
+        event.xkey.window = c_ulong::from((*ev).event);
+        event.xkey.root = c_ulong::from((*ev).root);
+        event.xkey.subwindow = c_ulong::from((*ev).child);
+        event.xkey.time = c_ulong::from((*ev).time);
+        event.xkey.x = c_int::from((*ev).event_x);
+        event.xkey.y = c_int::from((*ev).event_y);
+        event.xkey.x_root = c_int::from((*ev).root_x);
+        event.xkey.y_root = c_int::from((*ev).root_y);
+        event.xkey.state = c_uint::from((*ev).state);
+        event.xkey.keycode = c_uint::from((*ev).detail);
+        event.xkey.same_screen = c_int::from((*ev).same_screen);
+        XFilterEvent(&mut event, 0) != 0
+    }
+
+    /// Returns true if [process_events](Ime::process_events) has anything to report without
+    /// waiting. Pending requests to the input method are flushed.
+    pub fn has_pending_events(&self) -> bool {
+        if !self.events.borrow().is_empty() {
+            return true;
+        }
+
+        unsafe {
+            x11_sys::XEventsQueued(self.display, QueuedAfterFlush) > 0
+        }
+    }
+
+    /// Opens the input method selected by the `XMODIFIERS` environment variable, or the default
+    /// input method for the locale. Text is exchanged in the locale's encoding, so the
+    /// application should set a UTF-8 locale.
+    pub fn open(connection: &Connection) -> Option<Ime> {
+        unsafe {
+            if XSupportsLocale() == 0 {
+                warn!("locale not supported by Xlib; input methods are unavailable");
+                return None;
+            }
+            XSetLocaleModifiers(b"\0".as_ptr() as *const c_char);
+
+            let name = x11_sys::XDisplayString(connection.xlib_display_ptr());
+            let display = x11_sys::XOpenDisplay(name);
+            if display.is_null() {
+                warn!("can't open X display for input method");
+                return None;
+            }
+
+            let im = XOpenIM(display, std::ptr::null_mut(), std::ptr::null_mut(),
+                             std::ptr::null_mut());
+            if im.is_null() {
+                warn!("can't open X input method");
+                x11_sys::XCloseDisplay(display);
+                return None;
+            }
+
+            let style = match choose_style(im) {
+                None => {
+                    warn!("X input method has no supported input style");
+                    XCloseIM(im);
+                    x11_sys::XCloseDisplay(display);
+                    return None;
+                },
+                Some(style) => style,
+            };
+
+            // The input method server may exit, which invalidates the input method and all of
+            // its input contexts.
+            let ime = Ime {
+                contexts: RefCell::new(HashMap::new()),
+                destroyed: Box::new(Cell::new(false)),
+                display,
+                events: Rc::new(RefCell::new(Vec::new())),
+                im,
+                serial: Cell::new(0),
+                style,
+            };
+            let callback = XIMCallback {
+                client_data: &*ime.destroyed as *const Cell<bool> as XPointer,
+                callback: Some(im_destroyed),
+            };
+            XSetIMValues(im, xn(XNDestroyCallback), &callback as *const XIMCallback,
+                         std::ptr::null::<c_char>());
+            Some(ime)
+        }
+    }
+
+    /// Reads events from the input method's display connection, and returns the input method
+    /// events which were reported since the last call.
+    pub fn process_events(&self) -> Vec<(u32, ImeEvent)> {
+        unsafe {
+            while x11_sys::XPending(self.display) > 0 {
+                let mut event: x11_sys::XEvent = std::mem::zeroed();
+                x11_sys::XNextEvent(self.display, &mut event);
+
+                // Input method protocol messages are consumed by the filter. Committed text and
+                // keys which the input method didn't use are put back as key events.
+                if XFilterEvent(&mut event, 0) != 0 {
+                    continue;
+                }
+                if event.type_ == KeyPress || event.type_ == KeyRelease {
+                    self.handle_key_event(&mut event.xkey);
+                }
+            }
+        }
+
+        std::mem::take(&mut *self.events.borrow_mut())
+    }
+
+    /// Tells the input method whether a window has keyboard focus.
+    pub fn set_focus(&self, xid: u32, focused: bool) {
+        if let Some(ic) = self.ic(xid) {
+            unsafe {
+                match focused {
+                    false => XUnsetICFocus(ic),
+                    true => XSetICFocus(ic),
+                }
+            }
+        }
+    }
+
+    /// Moves a window's composition text to a position in client coordinates. Input methods
+    /// which don't use the spot location ignore it.
+    pub fn set_spot(&self, xid: u32, pos: Vec2<i16>) {
+        if let Some(ic) = self.ic(xid) {
+            let spot = XPoint { x: pos.x, y: pos.y };
+            let end = std::ptr::null::<c_char>();
+
+            unsafe {
+                let attributes = XVaCreateNestedList(0, xn(XNSpotLocation), &spot as *const XPoint,
+                                                     end);
+                XSetICValues(ic, xn(XNPreeditAttributes), attributes, end);
+                x11_sys::XFree(attributes);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "x11-sys")]
+impl Ime {
+    /// Handles a key event which passed through the filter. Committed text arrives as a key press
+    /// with a keycode of zero.
+    unsafe fn handle_key_event(&self, event: &mut x11_sys::XKeyEvent) {
+        let xid = event.window as u32;
+        let ic = match self.ic(xid) {
+            None => return,
+            Some(ic) => ic,
+        };
+
+        if event.keycode != 0 {
+            let mut ev: xcb_sys::xcb_key_press_event_t = std::mem::zeroed();
+            ev.response_type = match event.type_ {
+                KeyPress => xcb_sys::XCB_KEY_PRESS,
+                _ => xcb_sys::XCB_KEY_RELEASE,
+            } as u8;
+            ev.detail = event.keycode as u8;
+            ev.time = event.time as u32;
+            ev.root = event.root as u32;
+            ev.event = xid;
+            ev.child = event.subwindow as u32;
+            ev.root_x = event.x_root as i16;
+            ev.root_y = event.y_root as i16;
+            ev.event_x = event.x as i16;
+            ev.event_y = event.y as i16;
+            ev.state = event.state as u16;
+            ev.same_screen = event.same_screen as u8;
+            self.events.borrow_mut().push((xid, ImeEvent::Key(ev)));
+        } else if event.type_ == KeyPress {
+            if let Some(text) = lookup_string(ic, event) {
+                self.events.borrow_mut().push((xid, ImeEvent::Commit(text)));
+            }
+        }
+    }
+
+    /// Returns a window's input context, unless the input method was destroyed.
+    fn ic(&self, xid: u32) -> Option<XIC> {
+        self.im()?;
+        self.contexts.borrow().get(&xid).map(|context| context.ic)
+    }
+
+    /// Returns the input method, unless its server has closed it.
+    fn im(&self) -> Option<XIM> {
+        match self.destroyed.get() {
+            false => Some(self.im),
+            true => None,
+        }
+    }
+}
+
+#[cfg(feature = "x11-sys")]
+impl Drop for Ime {
+    fn drop(&mut self) {
+        unsafe {
+            if self.im().is_some() {
+                for context in self.contexts.get_mut().values() {
+                    XDestroyIC(context.ic);
+                }
+                XCloseIM(self.im);
+            }
+            x11_sys::XCloseDisplay(self.display);
+        }
+    }
+}
+
+/// Input method placeholder for builds without Xlib. Input methods are never available.
+#[cfg(not(feature = "x11-sys"))]
+pub enum Ime {}
+
+#[cfg(not(feature = "x11-sys"))]
+impl Ime {
+    pub fn create_context(&self, _xid: u32) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn destroy_context(&self, _xid: u32) {
+        match *self {}
+    }
+
+    pub fn fd(&self) -> RawFd {
+        match *self {}
+    }
+
+    pub unsafe fn filter_key_event(&self, _ev: *const xcb_sys::xcb_key_press_event_t) -> bool {
+        match *self {}
+    }
+
+    pub fn has_pending_events(&self) -> bool {
+        match *self {}
+    }
+
+    pub fn open(_connection: &Connection) -> Option<Ime> {
+        if !UNAVAILABLE_REPORTED.swap(true, Ordering::Relaxed) {
+            warn!("input methods require the x11-sys feature");
+        }
+        None
+    }
+
+    pub fn process_events(&self) -> Vec<(u32, ImeEvent)> {
+        match *self {}
+    }
+
+    pub fn set_focus(&self, _xid: u32, _focused: bool) {
+        match *self {}
+    }
+
+    pub fn set_spot(&self, _xid: u32, _pos: Vec2<i16>) {
+        match *self {}
+    }
+}
+
+/// Per-window input method state, which is passed to the preedit callbacks.
+#[cfg(feature = "x11-sys")]
+struct InputContext {
+    caret: Cell<usize>,
+    events: Rc<RefCell<Vec<(u32, ImeEvent)>>>,
+    ic: XIC,
+    preedit: RefCell<Vec<char>>,
+    xid: u32,
+}
+
+#[cfg(feature = "x11-sys")]
+impl InputContext {
+    /// Clears the composition text.
+    fn clear(&self) {
+        self.preedit.borrow_mut().clear();
+        self.caret.set(0);
+    }
+
+    /// Reports the current composition text.
+    fn report_preedit(&self) {
+        let preedit = self.preedit.borrow();
+        let caret = std::cmp::min(self.caret.get(), preedit.len());
+        let text = preedit.iter().collect();
+        let cursor = preedit[..caret].iter().map(|c| c.len_utf8()).sum();
+        self.events.borrow_mut().push((self.xid, ImeEvent::Preedit(text, Some(cursor))));
+    }
+}
+
+/// Chooses the input style for input contexts. Composition text is preferably reported to the
+/// application. Otherwise, the input method draws it in a window of its own.
+#[cfg(feature = "x11-sys")]
+unsafe fn choose_style(im: XIM) -> Option<XIMStyle> {
+    let mut styles: *mut XIMStyles = std::ptr::null_mut();
+    let failed = XGetIMValues(im, xn(XNQueryInputStyle), &mut styles as *mut *mut XIMStyles,
+                              std::ptr::null::<c_char>());
+    if !failed.is_null() || styles.is_null() {
+        return None;
+    }
+
+    let supported = std::slice::from_raw_parts((*styles).supported_styles,
+                                               usize::from((*styles).count_styles));
+    let style = [XIMPreeditCallbacks | XIMStatusNothing, XIMPreeditNothing | XIMStatusNothing]
+        .into_iter()
+        .find(|style| supported.contains(style));
+    x11_sys::XFree(styles as *mut _);
+    style
+}
+
+/// Decodes the text of a preedit draw callback. Multi-byte text is in the locale's encoding,
+/// which is assumed to be UTF-8, and wide characters are UTF-32.
+#[cfg(feature = "x11-sys")]
+unsafe fn decode_text(text: &XIMText) -> Vec<char> {
+    if text.encoding_is_wchar != 0 {
+        std::slice::from_raw_parts(text.string as *const u32, usize::from(text.length))
+            .iter()
+            .filter_map(|&c| char::from_u32(c))
+            .collect()
+    } else {
+        CStr::from_ptr(text.string as *const c_char).to_string_lossy().chars().collect()
+    }
+}
+
+#[cfg(feature = "x11-sys")]
+unsafe extern "C" fn im_destroyed(_im: XIC, client_data: XPointer, _call_data: XPointer)
+    -> c_int
+{
+    (*(client_data as *const Cell<bool>)).set(true);
+    0
+}
+
+/// Returns the text committed by a key press with a keycode of zero.
+#[cfg(feature = "x11-sys")]
+unsafe fn lookup_string(ic: XIC, event: &mut x11_sys::XKeyEvent) -> Option<String> {
+    let mut buf = vec![0u8; 64];
+    let mut status = 0;
+    let mut len = Xutf8LookupString(ic, event, buf.as_mut_ptr() as *mut c_char, buf.len() as c_int,
+                                    std::ptr::null_mut(), &mut status);
+
+    // The text stays buffered until it's looked up with a large enough buffer.
+    if status == XBufferOverflow {
+        buf.resize(len as usize, 0);
+        len = Xutf8LookupString(ic, event, buf.as_mut_ptr() as *mut c_char, buf.len() as c_int,
+                                std::ptr::null_mut(), &mut status);
+    }
+
+    match status {
+        XLookupBoth | XLookupChars => {
+            buf.truncate(std::cmp::max(len, 0) as usize);
+            Some(String::from_utf8_lossy(&buf).into_owned())
+        },
+        _ => None,
+    }
+}
+
+/// Moves the composition cursor. The new position is returned to the input method.
+#[cfg(feature = "x11-sys")]
+unsafe extern "C" fn preedit_caret(_ic: XIC, client_data: XPointer, call_data: XPointer)
+    -> c_int
+{
+    let context = &*(client_data as *const InputContext);
+    let data = &mut *(call_data as *mut XIMPreeditCaretCallbackStruct);
+    let len = context.preedit.borrow().len();
+    let caret = context.caret.get();
+    let caret = match data.direction {
+        XIMAbsolutePosition => std::cmp::max(data.position, 0) as usize,
+        XIMBackwardChar => caret.saturating_sub(1),
+        XIMForwardChar => caret + 1,
+        XIMLineEnd => len,
+        XIMLineStart => 0,
+        _ => caret,
+    };
+    let caret = std::cmp::min(caret, len);
+    context.caret.set(caret);
+    data.position = caret as c_int;
+    context.report_preedit();
+    0
+}
+
+#[cfg(feature = "x11-sys")]
+unsafe extern "C" fn preedit_done(_ic: XIC, client_data: XPointer, _call_data: XPointer)
+    -> c_int
+{
+    let context = &*(client_data as *const InputContext);
+    context.clear();
+    context.events.borrow_mut().push((context.xid, ImeEvent::Preedit(String::new(), None)));
+    0
+}
+
+/// Replaces part of the composition text. A null text deletes the changed range, and a null
+/// string means that only the text's highlighting changed.
+#[cfg(feature = "x11-sys")]
+unsafe extern "C" fn preedit_draw(_ic: XIC, client_data: XPointer, call_data: XPointer)
+    -> c_int
+{
+    let context = &*(client_data as *const InputContext);
+    let data = &*(call_data as *const XIMPreeditDrawCallbackStruct);
+
+    {
+        let mut preedit = context.preedit.borrow_mut();
+        let first = std::cmp::min(std::cmp::max(data.chg_first, 0) as usize, preedit.len());
+        let end = std::cmp::min(first + std::cmp::max(data.chg_length, 0) as usize,
+                                preedit.len());
+        if data.text.is_null() {
+            preedit.drain(first..end);
+        } else if !(*data.text).string.is_null() {
+            preedit.splice(first..end, decode_text(&*data.text));
+        }
+        context.caret.set(std::cmp::min(std::cmp::max(data.caret, 0) as usize, preedit.len()));
+    }
+
+    context.report_preedit();
+    0
+}
+
+/// Starts composition. Returns -1 to place no limit on the length of the composition text.
+#[cfg(feature = "x11-sys")]
+unsafe extern "C" fn preedit_start(_ic: XIC, client_data: XPointer, _call_data: XPointer)
+    -> c_int
+{
+    (*(client_data as *const InputContext)).clear();
+    -1
+}
+
+/// Set once the lack of input method support has been logged, so it isn't repeated for every
+/// client.
+#[cfg(not(feature = "x11-sys"))]
+static UNAVAILABLE_REPORTED: AtomicBool = AtomicBool::new(false);
@@ -0,0 +1,302 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use crate::driver::x11::client::{Connection, Reply};
+use crate::error::Result;
+use crate::key::Key;
+
+/// Keyboard mapping from keycodes to the unshifted keysym of each keyboard group.
+pub struct Keymap {
+    keys: Vec<Vec<u32>>,
+    min_keycode: u8,
+}
+
+impl Keymap {
+    /// Returns the logical key for a keycode in a keyboard group. Groups beyond those defined
+    /// for the key wrap around, which is XKB's default behavior.
+    pub fn key(&self, keycode: u8, group: u8) -> Key {
+        let groups = match keycode.checked_sub(self.min_keycode) {
+            None => return Key::Unknown,
+            Some(index) => match self.keys.get(index as usize) {
+                None => return Key::Unknown,
+                Some(groups) => groups,
+            },
+        };
+
+        match groups.len() {
+            0 => Key::Unknown,
+            len => keysym_to_key(groups[group as usize % len]),
+        }
+    }
+
+    /// Queries the server's current keyboard mapping. If `xkb` is true, the mapping is queried
+    /// through XKB, which describes every keyboard group. Otherwise, only the two groups which
+    /// are visible through the core protocol are available.
+    pub fn query(connection: &Connection, xkb: bool) -> Result<Keymap> {
+        if xkb {
+            match Keymap::query_xkb(connection) {
+                Ok(keymap) => return Ok(keymap),
+                Err(_err) => warn!("can't query XKB keyboard mapping: {}", _err),
+            }
+        }
+
+        Keymap::query_core(connection)
+    }
+}
+
+impl Keymap {
+    fn query_core(connection: &Connection) -> Result<Keymap> {
+        let xcb = connection.xcb_connection_ptr();
+
+        unsafe {
+            let setup = xcb_sys::xcb_get_setup(xcb);
+            let min_keycode = (*setup).min_keycode;
+            let count = (*setup).max_keycode - min_keycode + 1;
+
+            let cookie = xcb_sys::xcb_get_keyboard_mapping(xcb, min_keycode, count);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_get_keyboard_mapping_reply(xcb, cookie, &mut err_ptr);
+            let reply = Reply::new("X_GetKeyboardMapping", reply_ptr, err_ptr)?;
+            let keysyms = std::slice::from_raw_parts(
+                xcb_sys::xcb_get_keyboard_mapping_keysyms(&*reply),
+                xcb_sys::xcb_get_keyboard_mapping_keysyms_length(&*reply) as usize);
+
+            // The first and third keysyms of each keycode are the unshifted symbols of the first
+            // and second groups.
+            let keysyms_per_keycode = usize::from(reply.keysyms_per_keycode).max(1);
+            let keys = keysyms.chunks(keysyms_per_keycode).map(|keysyms| {
+                keysyms.iter().step_by(2).take(2).copied().filter(|&keysym| keysym != 0).collect()
+            }).collect();
+
+            Ok(Keymap { keys, min_keycode })
+        }
+    }
+
+    fn query_xkb(connection: &Connection) -> Result<Keymap> {
+        let xcb = connection.xcb_connection_ptr();
+        let parts = xcb_sys::XCB_XKB_MAP_PART_KEY_SYMS as u16;
+
+        unsafe {
+            let cookie = xcb_sys::xcb_xkb_get_map(xcb, xcb_sys::XCB_XKB_ID_USE_CORE_KBD as u16,
+                                                  parts, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                                                  0, 0, 0);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_xkb_get_map_reply(xcb, cookie, &mut err_ptr);
+            let reply = Reply::new("XkbGetMap", reply_ptr, err_ptr)?;
+
+            let mut map: xcb_sys::xcb_xkb_get_map_map_t = std::mem::zeroed();
+            xcb_sys::xcb_xkb_get_map_map_unpack(xcb_sys::xcb_xkb_get_map_map(&*reply),
+                                                reply.nTypes, reply.nKeySyms, reply.nKeyActions,
+                                                reply.totalActions, reply.totalKeyBehaviors,
+                                                reply.virtualMods, reply.totalKeyExplicit,
+                                                reply.totalModMapKeys, reply.totalVModMapKeys,
+                                                reply.present, &mut map);
+
+            // Each key's symbols are grouped by keyboard group, with `width` shift levels per
+            // group. The low nibble of `groupInfo` is the number of groups.
+            let mut keys = Vec::new();
+            let mut iter = xcb_sys::xcb_xkb_get_map_map_syms_rtrn_iterator(&*reply, &map);
+            while iter.rem > 0 {
+                let sym_map = iter.data;
+                let keysyms = std::slice::from_raw_parts(
+                    xcb_sys::xcb_xkb_key_sym_map_syms(sym_map),
+                    xcb_sys::xcb_xkb_key_sym_map_syms_length(sym_map) as usize);
+                let num_groups = usize::from((*sym_map).groupInfo & 0x0f);
+                let width = usize::from((*sym_map).width).max(1);
+                keys.push(keysyms.iter().step_by(width).take(num_groups).copied().collect());
+                xcb_sys::xcb_xkb_key_sym_map_next(&mut iter);
+            }
+
+            Ok(Keymap { keys, min_keycode: reply.firstKeySym })
+        }
+    }
+}
+
+/// Converts a keysym to a logical key.
+fn keysym_to_key(keysym: u32) -> Key {
+    match keysym {
+        // Latin-1 keysyms are equal to their code points.
+        0x20..=0x7e | 0xa0..=0xff => match char::from_u32(keysym) {
+            None => Key::Unknown,
+            Some(ch) => Key::Character(ch.to_lowercase().next().unwrap_or(ch)),
+        },
+        // Unicode keysyms.
+        0x01000100..=0x0110ffff => match char::from_u32(keysym - 0x01000000) {
+            None => Key::Unknown,
+            Some(ch) => Key::Character(ch.to_lowercase().next().unwrap_or(ch)),
+        },
+        // Arabic, Cyrillic, Greek, Hebrew and Thai keysyms predate Unicode keysyms, but are still
+        // used by many layouts.
+        0x05c1..=0x05f2
+        | 0x06a1..=0x06ff
+        | 0x07c1..=0x07d9 | 0x07e1..=0x07f9
+        | 0x0ce0..=0x0cfa
+        | 0x0da1..=0x0df9 => match legacy_keysym_to_char(keysym) {
+            None => Key::Unknown,
+            Some(ch) => Key::Character(ch.to_lowercase().next().unwrap_or(ch)),
+        },
+        // Dead keys are reported as their spacing characters, as they are on Win32.
+        0xfe50..=0xfe5c => Key::Character(DEAD_KEY_CHARS[(keysym - 0xfe50) as usize]),
+        0xff08 => Key::Backspace,
+        0xff09 => Key::Tab,
+        0xff0d | 0xff8d => Key::Enter,
+        0xff1b => Key::Escape,
+        0xff50 => Key::Home,
+        0xff51 => Key::Left,
+        0xff52 => Key::Up,
+        0xff53 => Key::Right,
+        0xff54 => Key::Down,
+        0xff55 => Key::PageUp,
+        0xff56 => Key::PageDown,
+        0xff57 => Key::End,
+        0xff63 => Key::Insert,
+        0xffbe..=0xffd5 => Key::F((keysym - 0xffbe + 1) as u8),
+        0xffe1 | 0xffe2 => Key::Shift,
+        0xffe3 | 0xffe4 => Key::Control,
+        0xffe5 => Key::CapsLock,
+        0xffe9 | 0xffea => Key::Alt,
+        0xffeb | 0xffec => Key::Super,
+        0xffff => Key::Delete,
+        _ => Key::Unknown,
+    }
+}
+
+/// Converts a keysym from one of the legacy non-Latin blocks to a character.
+fn legacy_keysym_to_char(keysym: u32) -> Option<char> {
+    let code_point = match keysym {
+        // Arabic and Hebrew keysyms follow ISO 8859-6 and ISO 8859-8.
+        0x05c1..=0x05da | 0x05e0..=0x05f2 => keysym + 0x0060,
+        0x0ce0..=0x0cfa => keysym - 0x0710,
+        // Letters outside of Russian, mostly Serbian, Macedonian, Ukrainian and Belarusian, have
+        // small letters at 0x6a1 and capitals at 0x6b1.
+        0x06a1..=0x06af => u32::from(CYRILLIC_EXTENDED_CODE_POINTS[(keysym - 0x06a1) as usize]),
+        0x06b0 => 0x2116,
+        0x06b1..=0x06bf => match CYRILLIC_EXTENDED_CODE_POINTS[(keysym - 0x06b1) as usize] {
+            0x0491 => 0x0490,
+            small => u32::from(small) - 0x0050,
+        },
+        // Other Cyrillic keysyms follow KOI8-R, with small letters at 0x6c0 and capitals at 0x6e0.
+        0x06c0..=0x06df => 0x0430 + u32::from(KOI8_CYRILLIC_OFFSETS[(keysym & 0x1f) as usize]),
+        0x06e0..=0x06ff => 0x0410 + u32::from(KOI8_CYRILLIC_OFFSETS[(keysym & 0x1f) as usize]),
+        // Greek keysyms follow ISO 8859-7, except that the capital sigma is one place early and
+        // the small sigma and final sigma are swapped.
+        0x07d2 => 0x03a3,
+        0x07f2 => 0x03c3,
+        0x07f3 => 0x03c2,
+        0x07c1..=0x07d9 | 0x07e1..=0x07f9 => keysym - 0x0430,
+        // Thai keysyms follow TIS-620.
+        0x0da1..=0x0df9 => keysym + 0x0060,
+        _ => return None,
+    };
+
+    char::from_u32(code_point)
+}
+
+/// Code points of the small Cyrillic letters from `Serbian_dje` to `Cyrillic_dzhe`.
+const CYRILLIC_EXTENDED_CODE_POINTS: [u16; 15] = [
+    0x0452, 0x0453, 0x0451, 0x0454, 0x0455, 0x0456, 0x0457, 0x0458, 0x0459, 0x045a, 0x045b, 0x045c,
+    0x0491, 0x045e, 0x045f,
+];
+
+/// Spacing characters of the dead key keysyms, starting with `dead_grave`.
+const DEAD_KEY_CHARS: [char; 13] = [
+    '`', '\u{b4}', '^', '~', '\u{af}', '\u{2d8}', '\u{2d9}', '\u{a8}', '\u{2da}', '\u{2dd}',
+    '\u{2c7}', '\u{b8}', '\u{2db}',
+];
+
+/// Offsets from U+0430 of the small Cyrillic letters in KOI8-R order.
+const KOI8_CYRILLIC_OFFSETS: [u8; 32] = [
+    0x1e, 0x00, 0x01, 0x16, 0x04, 0x05, 0x14, 0x03, 0x15, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+    0x0f, 0x1f, 0x10, 0x11, 0x12, 0x13, 0x06, 0x02, 0x1c, 0x1b, 0x07, 0x18, 0x1d, 0x19, 0x17, 0x1a,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keysym_to_key_lowercases_characters() {
+        let cases = [
+            (0x0041, 'a'), // A
+            (0x00c9, '\u{e9}'), // Eacute
+            (0x010003a9, '\u{3c9}'), // U+03A9
+            (0x06e1, '\u{430}'), // Cyrillic_A
+            (0x06b1, '\u{452}'), // Serbian_DJE
+            (0x07c1, '\u{3b1}'), // Greek_ALPHA
+            (0x07d2, '\u{3c3}'), // Greek_SIGMA
+        ];
+
+        for (keysym, ch) in cases {
+            assert_eq!(keysym_to_key(keysym), Key::Character(ch), "keysym {:#x}", keysym);
+        }
+    }
+
+    #[test]
+    fn keysym_to_key_rejects_gaps() {
+        for keysym in [0x05db, 0x06a0, 0x07da, 0x07e0, 0x0cdf, 0x0da0] {
+            assert_eq!(keysym_to_key(keysym), Key::Unknown, "keysym {:#x}", keysym);
+        }
+    }
+
+    #[test]
+    fn legacy_keysyms() {
+        let cases = [
+            // Arabic
+            (0x05c1, '\u{621}'), // Arabic_hamza
+            (0x05da, '\u{63a}'), // Arabic_ghain
+            (0x05e0, '\u{640}'), // Arabic_tatweel
+            (0x05f2, '\u{652}'), // Arabic_sukun
+            // Cyrillic outside of KOI8-R
+            (0x06a1, '\u{452}'), // Serbian_dje
+            (0x06a3, '\u{451}'), // Cyrillic_io
+            (0x06ad, '\u{491}'), // Ukrainian_ghe_with_upturn
+            (0x06af, '\u{45f}'), // Cyrillic_dzhe
+            (0x06b0, '\u{2116}'), // numerosign
+            (0x06b1, '\u{402}'), // Serbian_DJE
+            (0x06b3, '\u{401}'), // Cyrillic_IO
+            (0x06bd, '\u{490}'), // Ukrainian_GHE_WITH_UPTURN
+            (0x06bf, '\u{40f}'), // Cyrillic_DZHE
+            // Cyrillic in KOI8-R order
+            (0x06c0, '\u{44e}'), // Cyrillic_yu
+            (0x06c1, '\u{430}'), // Cyrillic_a
+            (0x06d6, '\u{436}'), // Cyrillic_zhe
+            (0x06df, '\u{44a}'), // Cyrillic_hardsign
+            (0x06e0, '\u{42e}'), // Cyrillic_YU
+            (0x06e1, '\u{410}'), // Cyrillic_A
+            (0x06f6, '\u{416}'), // Cyrillic_ZHE
+            (0x06ff, '\u{42a}'), // Cyrillic_HARDSIGN
+            // Greek
+            (0x07c1, '\u{391}'), // Greek_ALPHA
+            (0x07d2, '\u{3a3}'), // Greek_SIGMA
+            (0x07d9, '\u{3a9}'), // Greek_OMEGA
+            (0x07e1, '\u{3b1}'), // Greek_alpha
+            (0x07f2, '\u{3c3}'), // Greek_sigma
+            (0x07f3, '\u{3c2}'), // Greek_finalsmallsigma
+            (0x07f9, '\u{3c9}'), // Greek_omega
+            // Hebrew
+            (0x0ce0, '\u{5d0}'), // hebrew_aleph
+            (0x0cfa, '\u{5ea}'), // hebrew_taw
+            // Thai
+            (0x0da1, '\u{e01}'), // Thai_kokai
+            (0x0df9, '\u{e59}'), // Thai_leksun
+        ];
+
+        for (keysym, ch) in cases {
+            assert_eq!(legacy_keysym_to_char(keysym), Some(ch), "keysym {:#x}", keysym);
+        }
+    }
+
+    #[test]
+    fn koi8_capitals_match_small_letters() {
+        for keysym in 0x06c0..=0x06df {
+            let small = legacy_keysym_to_char(keysym).unwrap();
+            let capital = legacy_keysym_to_char(keysym + 0x20).unwrap();
+            assert_eq!(capital.to_lowercase().next(), Some(small), "keysym {:#x}", keysym);
+        }
+    }
+}
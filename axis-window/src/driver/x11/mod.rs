@@ -7,9 +7,16 @@
  */
 
 mod client;
+mod cursor;
+mod device;
+mod ime;
+mod keymap;
+mod monitor;
 mod pixel_format;
+mod tray;
 mod window;
 
 pub use self::client::{Client, Connection, Screen};
 pub use self::pixel_format::{InvalidVisualClass, PixelFormat, VisualClass};
+pub use self::tray::TrayIcon;
 pub use self::window::{Window, WindowBuilder};
@@ -0,0 +1,216 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use vectorial::Vec2;
+
+use crate::driver::x11::client::{Atoms, Connection, Reply, Screen};
+use crate::error::Result;
+use crate::monitor::Monitor;
+use crate::Coord;
+
+/// Queries the monitors attached to an X11 screen.
+///
+/// Monitors are enumerated with RandR 1.5 if available. Otherwise, the whole screen is reported as
+/// a single monitor.
+pub fn query_monitors(connection: &Connection, atoms: &Atoms, screen: &Screen)
+    -> Result<Vec<Monitor>>
+{
+    let xcb = connection.xcb_connection_ptr();
+    let root = screen.root();
+    let work_area = query_work_area(connection, atoms, root)?;
+    let mut monitors = Vec::new();
+
+    unsafe {
+        if !query_randr_1_5(connection)? {
+            let screen_ptr = screen.xcb_screen_ptr();
+            let size = Vec2::new(Coord::from((*screen_ptr).width_in_pixels),
+                                 Coord::from((*screen_ptr).height_in_pixels));
+            let monitor = Monitor::new(format!("screen {}", screen.num()), Vec2::new(0, 0), size)
+                .with_primary(screen.num() == connection.default_screen_num());
+            return Ok(vec![apply_work_area(monitor, work_area)]);
+        }
+
+        let cookie = xcb_sys::xcb_randr_get_monitors(xcb, root, 1);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_randr_get_monitors_reply(xcb, cookie, &mut err_ptr);
+        let reply = Reply::new("RRGetMonitors", reply_ptr, err_ptr)?;
+        let resources = query_screen_resources(connection, root)?;
+
+        let mut iter = xcb_sys::xcb_randr_get_monitors_monitors_iterator(&*reply);
+        while iter.rem > 0 {
+            let info = iter.data;
+            let name = query_atom_name(connection, (*info).name)?;
+            let pos = Vec2::new(Coord::from((*info).x), Coord::from((*info).y));
+            let size = Vec2::new(Coord::from((*info).width), Coord::from((*info).height));
+            let outputs = std::slice::from_raw_parts(
+                xcb_sys::xcb_randr_monitor_info_outputs(info),
+                xcb_sys::xcb_randr_monitor_info_outputs_length(info) as usize);
+            let refresh_rate = match outputs.first() {
+                None => None,
+                Some(&output) => query_refresh_rate(connection, &resources, output)?,
+            };
+            let monitor = Monitor::new(name, pos, size)
+                .with_primary((*info).primary != 0)
+                .with_refresh_rate(refresh_rate);
+            monitors.push(apply_work_area(monitor, work_area));
+            xcb_sys::xcb_randr_monitor_info_next(&mut iter);
+        }
+    }
+
+    Ok(monitors)
+}
+
+/// Clips a monitor's work area to the desktop work area.
+fn apply_work_area(monitor: Monitor, work_area: Option<[Coord; 4]>) -> Monitor {
+    let [x, y, w, h] = match work_area {
+        None => return monitor,
+        Some(work_area) => work_area,
+    };
+    let left = std::cmp::max(monitor.pos().x, x);
+    let top = std::cmp::max(monitor.pos().y, y);
+    let right = std::cmp::min(monitor.pos().x + monitor.size().x, x + w);
+    let bottom = std::cmp::min(monitor.pos().y + monitor.size().y, y + h);
+    if right <= left || bottom <= top {
+        return monitor;
+    }
+    monitor.with_work_area(Vec2::new(left, top), Vec2::new(right - left, bottom - top))
+}
+
+/// Gets the name of an atom.
+fn query_atom_name(connection: &Connection, atom: u32) -> Result<String> {
+    let xcb = connection.xcb_connection_ptr();
+
+    unsafe {
+        let cookie = xcb_sys::xcb_get_atom_name(xcb, atom);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_get_atom_name_reply(xcb, cookie, &mut err_ptr);
+        let reply = Reply::new("X_GetAtomName", reply_ptr, err_ptr)?;
+        let name = std::slice::from_raw_parts(
+            xcb_sys::xcb_get_atom_name_name(&*reply) as *const u8,
+            xcb_sys::xcb_get_atom_name_name_length(&*reply) as usize);
+        Ok(String::from_utf8_lossy(name).into_owned())
+    }
+}
+
+/// Checks whether the server supports RandR 1.5, which is required for `RRGetMonitors`.
+fn query_randr_1_5(connection: &Connection) -> Result<bool> {
+    let xcb = connection.xcb_connection_ptr();
+
+    unsafe {
+        let ext_ptr = xcb_sys::xcb_get_extension_data(xcb, &raw mut xcb_sys::xcb_randr_id);
+        if ext_ptr.is_null() || (*ext_ptr).present == 0 {
+            return Ok(false);
+        }
+
+        let cookie = xcb_sys::xcb_randr_query_version(xcb, 1, 5);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_randr_query_version_reply(xcb, cookie, &mut err_ptr);
+        let reply = Reply::new("RRQueryVersion", reply_ptr, err_ptr)?;
+        Ok((reply.major_version, reply.minor_version) >= (1, 5))
+    }
+}
+
+/// Computes the refresh rate of the CRTC driving an output in millihertz.
+fn query_refresh_rate(connection: &Connection,
+                      resources: &Reply<xcb_sys::xcb_randr_get_screen_resources_current_reply_t>,
+                      output: u32) -> Result<Option<u32>>
+{
+    let xcb = connection.xcb_connection_ptr();
+
+    unsafe {
+        let cookie = xcb_sys::xcb_randr_get_output_info(xcb, output, resources.config_timestamp);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_randr_get_output_info_reply(xcb, cookie, &mut err_ptr);
+        let output_info = Reply::new("RRGetOutputInfo", reply_ptr, err_ptr)?;
+        if output_info.crtc == 0 {
+            return Ok(None);
+        }
+
+        let cookie = xcb_sys::xcb_randr_get_crtc_info(xcb, output_info.crtc,
+                                                      resources.config_timestamp);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_randr_get_crtc_info_reply(xcb, cookie, &mut err_ptr);
+        let crtc_info = Reply::new("RRGetCrtcInfo", reply_ptr, err_ptr)?;
+
+        let modes = std::slice::from_raw_parts(
+            xcb_sys::xcb_randr_get_screen_resources_current_modes(&**resources),
+            xcb_sys::xcb_randr_get_screen_resources_current_modes_length(&**resources) as usize);
+        for mode in modes {
+            if mode.id == crtc_info.mode {
+                let total = u64::from(mode.htotal) * u64::from(mode.vtotal);
+                if total == 0 {
+                    return Ok(None);
+                }
+                return Ok(u32::try_from(u64::from(mode.dot_clock) * 1000 / total).ok());
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Gets the current screen resources for a root window.
+fn query_screen_resources(connection: &Connection, root: u32)
+    -> Result<Reply<xcb_sys::xcb_randr_get_screen_resources_current_reply_t>>
+{
+    let xcb = connection.xcb_connection_ptr();
+
+    unsafe {
+        let cookie = xcb_sys::xcb_randr_get_screen_resources_current(xcb, root);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_randr_get_screen_resources_current_reply(xcb, cookie,
+                                                                              &mut err_ptr);
+        Reply::new("RRGetScreenResourcesCurrent", reply_ptr, err_ptr)
+    }
+}
+
+/// Gets the `[x, y, width, height]` work area of the current desktop from `_NET_WORKAREA`.
+fn query_work_area(connection: &Connection, atoms: &Atoms, root: u32)
+    -> Result<Option<[Coord; 4]>>
+{
+    let desktop = connection.get_property(root, atoms._NET_CURRENT_DESKTOP,
+                                          xcb_sys::XCB_ATOM_CARDINAL, 1)?;
+    let desktop = desktop.value32().first().cloned().unwrap_or(0) as usize;
+    let work_area = connection.get_property(root, atoms._NET_WORKAREA, xcb_sys::XCB_ATOM_CARDINAL,
+                                            u32::MAX)?;
+
+    Ok(match work_area.value32().get(desktop * 4..desktop * 4 + 4) {
+        None => None,
+        Some(values) => Some([values[0] as Coord, values[1] as Coord,
+                              values[2] as Coord, values[3] as Coord]),
+    })
+}
+
+/// Requests RandR notifications for changes to the screen's outputs and CRTCs. Returns the
+/// extension's first event code, or `None` if RandR 1.2 is unavailable.
+pub fn watch_monitors(connection: &Connection, root: u32) -> Option<u8> {
+    let xcb = connection.xcb_connection_ptr();
+
+    unsafe {
+        let ext_ptr = xcb_sys::xcb_get_extension_data(xcb, &raw mut xcb_sys::xcb_randr_id);
+        if ext_ptr.is_null() || (*ext_ptr).present == 0 {
+            return None;
+        }
+
+        // CRTC and output notifications were added in RandR 1.2.
+        let cookie = xcb_sys::xcb_randr_query_version(xcb, 1, 5);
+        let mut err_ptr = std::ptr::null_mut();
+        let reply_ptr = xcb_sys::xcb_randr_query_version_reply(xcb, cookie, &mut err_ptr);
+        match Reply::new("RRQueryVersion", reply_ptr, err_ptr) {
+            Ok(ref reply) if (reply.major_version, reply.minor_version) >= (1, 2) => (),
+            _ => return None,
+        }
+
+        let mask = xcb_sys::XCB_RANDR_NOTIFY_MASK_SCREEN_CHANGE
+                   | xcb_sys::XCB_RANDR_NOTIFY_MASK_CRTC_CHANGE
+                   | xcb_sys::XCB_RANDR_NOTIFY_MASK_OUTPUT_CHANGE;
+        xcb_sys::xcb_randr_select_input(xcb, root, mask as u16);
+
+        Some((*ext_ptr).first_event)
+    }
+}
@@ -12,16 +12,37 @@ use std::rc::Rc;
 use crate::driver::x11::client::Connection;
 use crate::pixel_format::IPixelFormat;
 
-/// X11 pixel format type.
+/// OpenGL attributes of a GLX framebuffer configuration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct FbConfig {
+    pub depth_bits: u8,
+    pub double_buffered: bool,
+    pub id: u32,
+    pub samples: u8,
+    pub stencil_bits: u8,
+}
+
+/// X11 pixel format type. This is a visual, optionally paired with a GLX framebuffer
+/// configuration.
 #[derive(Clone)]
 pub struct PixelFormat {
     connection: Rc<Connection>,
     depth: u8,
+    fb_config: Option<FbConfig>,
     screen_num: u8,
     visualtype_ptr: *mut xcb_sys::xcb_visualtype_t,
 }
 
 impl PixelFormat {
+    /// Gets the mask of pixel bits not covered by the color masks, which compositing managers
+    /// treat as alpha. This is zero unless the visual is a 32-bit true color visual.
+    pub fn alpha_mask(&self) -> u32 {
+        if self.depth != 32 || self.visual_class() != VisualClass::TrueColor {
+            return 0;
+        }
+        !(self.red_mask() | self.green_mask() | self.blue_mask())
+    }
+
     /// Gets the number of bits per RGB value.
     pub fn bits_per_rgb_value(&self) -> u8 {
         unsafe {
@@ -46,6 +67,12 @@ impl PixelFormat {
         self.depth
     }
 
+    /// Returns the ID of the GLX framebuffer configuration if `self` was chosen for OpenGL
+    /// rendering.
+    pub fn glx_fb_config_id(&self) -> Option<u32> {
+        self.fb_config.map(|fb_config| fb_config.id)
+    }
+
     /// Gets the green mask.
     pub fn green_mask(&self) -> u32 {
         unsafe {
@@ -92,19 +119,42 @@ impl PixelFormat {
         PixelFormat {
             connection: connection.clone(),
             depth,
+            fb_config: None,
             screen_num,
             visualtype_ptr,
         }
     }
+
+    /// Pairs the visual with a GLX framebuffer configuration.
+    pub(crate) fn with_fb_config(self, fb_config: FbConfig) -> PixelFormat {
+        PixelFormat { fb_config: Some(fb_config), ..self }
+    }
 }
 
 impl Eq for PixelFormat {}
 
-impl IPixelFormat for PixelFormat {}
+impl IPixelFormat for PixelFormat {
+    fn depth_bits(&self) -> u8 {
+        self.fb_config.map_or(0, |fb_config| fb_config.depth_bits)
+    }
+
+    fn is_double_buffered(&self) -> bool {
+        self.fb_config.map_or(false, |fb_config| fb_config.double_buffered)
+    }
+
+    fn samples(&self) -> u8 {
+        self.fb_config.map_or(0, |fb_config| fb_config.samples)
+    }
+
+    fn stencil_bits(&self) -> u8 {
+        self.fb_config.map_or(0, |fb_config| fb_config.stencil_bits)
+    }
+}
 
 impl PartialEq for PixelFormat {
     fn eq(&self, rhs: &PixelFormat) -> bool {
         self.visualtype_ptr == rhs.visualtype_ptr
+        && self.glx_fb_config_id() == rhs.glx_fb_config_id()
     }
 }
 
@@ -0,0 +1,348 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use vectorial::Vec2;
+
+use crate::driver::x11::client::{Atoms, Client, Connection, Reply};
+use crate::driver::x11::pixel_format::PixelFormat;
+use crate::driver::x11::window::ChangePropertyMode;
+use crate::error::Result;
+use crate::event::Event;
+use crate::tray::{Icon, ITrayIcon};
+use crate::Coord;
+
+/// Data shared between a [TrayIcon] and a [TrayIconManager].
+pub struct TrayIconData<W: 'static + Clone> {
+    atoms: Rc<Atoms>,
+    connection: Rc<Connection>,
+    gc: u32,
+    icon: RefCell<Icon>,
+    id: W,
+    pixel_format: PixelFormat,
+    xid: Cell<Option<u32>>,
+}
+
+impl<W: 'static + Clone> TrayIconData<W> {
+    /// Translates a button press on the icon to a tray event.
+    pub fn button_event(&self, button: u8, root_pos: Vec2<Coord>) -> Option<Event<W>> {
+        match button {
+            1 => Some(Event::TrayActivate { tray_id: self.id.clone() }),
+            3 => Some(Event::TrayMenuRequest { tray_id: self.id.clone(), pos: root_pos }),
+            _ => None,
+        }
+    }
+
+    /// Asks the tray manager window `tray` to embed the icon.
+    pub fn dock(&self, tray: u32) {
+        const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+
+        let xid = match self.xid.get() {
+            None => return,
+            Some(xid) => xid,
+        };
+        let xcb = self.connection.xcb_connection_ptr();
+
+        unsafe {
+            let mut event: xcb_sys::xcb_client_message_event_t = std::mem::zeroed();
+            event.response_type = xcb_sys::XCB_CLIENT_MESSAGE as u8;
+            event.format = 32;
+            event.window = tray;
+            event.type_ = self.atoms._NET_SYSTEM_TRAY_OPCODE;
+            event.data.data32 = [xcb_sys::XCB_CURRENT_TIME, SYSTEM_TRAY_REQUEST_DOCK, xid, 0, 0];
+            xcb_sys::xcb_send_event(xcb, 0, tray, xcb_sys::XCB_EVENT_MASK_NO_EVENT as u32,
+                                    &event as *const _ as *const _);
+        }
+    }
+
+    /// Draws the icon centered in the tray window. The tray's background shows through the
+    /// window, so it's read back and the icon is blended over it. Nothing is drawn until the
+    /// tray maps the window, since its contents can't be read back before then. The window is
+    /// exposed once it's mapped.
+    pub fn redraw(&self) -> Result<()> {
+        let xid = match self.xid.get() {
+            None => return Ok(()),
+            Some(xid) => xid,
+        };
+        let xcb = self.connection.xcb_connection_ptr();
+        let icon = self.icon.borrow();
+
+        unsafe {
+            let cookie = xcb_sys::xcb_get_window_attributes(xcb, xid);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_get_window_attributes_reply(xcb, cookie, &mut err_ptr);
+            let attributes = Reply::new("X_GetWindowAttributes", reply_ptr, err_ptr)?;
+            if u32::from(attributes.map_state) != xcb_sys::XCB_MAP_STATE_VIEWABLE {
+                return Ok(());
+            }
+
+            let cookie = xcb_sys::xcb_get_geometry(xcb, xid);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_get_geometry_reply(xcb, cookie, &mut err_ptr);
+            let geometry = Reply::new("X_GetGeometry", reply_ptr, err_ptr)?;
+            let window_size = Vec2::new(Coord::from(geometry.width),
+                                        Coord::from(geometry.height));
+            let size = Vec2::new(std::cmp::min(icon.size().x, window_size.x),
+                                 std::cmp::min(icon.size().y, window_size.y));
+            let offset = Vec2::new((window_size.x - size.x) / 2, (window_size.y - size.y) / 2);
+
+            xcb_sys::xcb_clear_area(xcb, 0, xid, 0, 0, 0, 0);
+            let cookie = xcb_sys::xcb_get_image(xcb, xcb_sys::XCB_IMAGE_FORMAT_Z_PIXMAP as u8, xid,
+                                                offset.x as i16, offset.y as i16, size.x as u16,
+                                                size.y as u16, u32::MAX);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_get_image_reply(xcb, cookie, &mut err_ptr);
+            let image = Reply::new("X_GetImage", reply_ptr, err_ptr)?;
+            let data = std::slice::from_raw_parts_mut(
+                xcb_sys::xcb_get_image_data(&*image),
+                xcb_sys::xcb_get_image_data_length(&*image) as usize);
+            let msb_first = (*xcb_sys::xcb_get_setup(xcb)).image_byte_order
+                            == xcb_sys::XCB_IMAGE_ORDER_MSB_FIRST as u8;
+
+            for y in 0..size.y as usize {
+                for x in 0..size.x as usize {
+                    let src = icon.pixels()[y * icon.size().x as usize + x];
+                    let chunk = &mut data[(y * size.x as usize + x) * 4..][..4];
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(chunk);
+                    let pixel = if msb_first {
+                        u32::from_be_bytes(bytes)
+                    } else {
+                        u32::from_le_bytes(bytes)
+                    };
+                    let pixel = self.blend(pixel, src);
+                    chunk.copy_from_slice(&if msb_first {
+                        pixel.to_be_bytes()
+                    } else {
+                        pixel.to_le_bytes()
+                    });
+                }
+            }
+
+            xcb_sys::xcb_put_image(xcb, xcb_sys::XCB_IMAGE_FORMAT_Z_PIXMAP as u8, xid, self.gc,
+                                   size.x as u16, size.y as u16, offset.x as i16,
+                                   offset.y as i16, 0, self.pixel_format.depth(),
+                                   data.len() as u32, data.as_ptr());
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: 'static + Clone> TrayIconData<W> {
+    /// Blends a non-premultiplied RGBA color over a pixel in the tray window's format.
+    fn blend(&self, dst: u32, src: [u8; 4]) -> u32 {
+        let pf = &self.pixel_format;
+        let alpha = u32::from(src[3]);
+        let mix = |mask: u32, value: u8| {
+            let dst = u32::from(unpack_channel(dst, mask));
+            pack_channel(((u32::from(value) * alpha + dst * (255 - alpha)) / 255) as u8, mask)
+        };
+
+        mix(pf.red_mask(), src[0]) | mix(pf.green_mask(), src[1]) | mix(pf.blue_mask(), src[2])
+            | mix(pf.alpha_mask(), 255)
+    }
+}
+
+/// Manages a map of X11 resource IDs to [TrayIconData] objects.
+pub struct TrayIconManager<W: 'static + Clone> {
+    map: RefCell<HashMap<u32, Rc<TrayIconData<W>>>>,
+    selection: u32,
+}
+
+impl<W: 'static + Clone> TrayIconManager<W> {
+    /// Gets the tray icon with the specified X11 resource ID.
+    pub fn get(&self, xid: u32) -> Option<Rc<TrayIconData<W>>> {
+        self.map.borrow().get(&xid).cloned()
+    }
+
+    /// Returns all registered tray icons.
+    pub fn icons(&self) -> Vec<Rc<TrayIconData<W>>> {
+        self.map.borrow().values().cloned().collect()
+    }
+
+    /// Constructs a tray icon manager for the `_NET_SYSTEM_TRAY_S<n>` selection atom
+    /// `selection`.
+    pub fn new(selection: u32) -> TrayIconManager<W> {
+        TrayIconManager {
+            map: RefCell::new(HashMap::new()),
+            selection,
+        }
+    }
+
+    /// Registers a tray icon.
+    pub fn register(&self, xid: u32, data: Rc<TrayIconData<W>>) {
+        self.map.borrow_mut().insert(xid, data);
+    }
+
+    /// Returns the `_NET_SYSTEM_TRAY_S<n>` selection atom for the default screen.
+    pub fn selection(&self) -> u32 {
+        self.selection
+    }
+
+    /// Removes the tray icon with the specified X11 resource ID.
+    pub fn unregister(&self, xid: u32) -> Option<Rc<TrayIconData<W>>> {
+        let data = self.map.borrow_mut().remove(&xid);
+        if let Some(ref data) = data {
+            data.xid.set(None);
+        }
+        data
+    }
+}
+
+/// X11 tray icon type. Icons are docked into the tray using the freedesktop.org system tray
+/// protocol, which embeds a small window with XEmbed.
+pub struct TrayIcon<W: 'static + Clone> {
+    atoms: Rc<Atoms>,
+    connection: Rc<Connection>,
+    data: Rc<TrayIconData<W>>,
+    manager: Rc<TrayIconManager<W>>,
+}
+
+impl<W: 'static + Clone> TrayIcon<W> {
+    /// Returns the X11 resource ID for the tray icon's window, or `None` if it was removed.
+    pub fn xid(&self) -> Option<u32> {
+        self.data.xid.get()
+    }
+}
+
+impl<W: 'static + Clone> TrayIcon<W> {
+    /// Creates a tray icon window and asks the tray manager of the default screen to dock it.
+    pub(crate) fn new(client: &Client<W>, id: W, icon: &Icon) -> Result<TrayIcon<W>> {
+        const XEMBED_MAPPED: u32 = 1;
+
+        let connection = client.connection().clone();
+        let xcb = connection.xcb_connection_ptr();
+        let atoms = client.atoms().clone();
+        let screen = client.default_screen();
+        let pixel_format = screen.default_pixel_format();
+        if pixel_format.depth() != 24 && pixel_format.depth() != 32 {
+            return Err(err!(Unsupported("tray icons require a 24 or 32-bit visual")));
+        }
+
+        let manager = client.tray_icon_manager().clone();
+        let tray;
+
+        unsafe {
+            let cookie = xcb_sys::xcb_get_selection_owner(xcb, manager.selection());
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_get_selection_owner_reply(xcb, cookie, &mut err_ptr);
+            tray = Reply::new("X_GetSelectionOwner", reply_ptr, err_ptr)?.owner;
+        }
+        if tray == 0 {
+            return Err(err!(Unsupported("no system tray is running")));
+        }
+
+        // The parent-relative background lets the tray show through where the icon is
+        // transparent.
+        let values = [
+            xcb_sys::XCB_BACK_PIXMAP_PARENT_RELATIVE,
+            (xcb_sys::XCB_EVENT_MASK_BUTTON_PRESS
+             | xcb_sys::XCB_EVENT_MASK_EXPOSURE
+             | xcb_sys::XCB_EVENT_MASK_STRUCTURE_NOTIFY) as u32,
+        ];
+        let xid;
+        let gc;
+
+        unsafe {
+            xid = xcb_sys::xcb_generate_id(xcb);
+            xcb_sys::xcb_create_window(xcb, pixel_format.depth(), xid, screen.root(), 0, 0,
+                                       icon.size().x as u16, icon.size().y as u16, 0,
+                                       xcb_sys::XCB_WINDOW_CLASS_INPUT_OUTPUT as u16,
+                                       pixel_format.visual_id(),
+                                       xcb_sys::XCB_CW_BACK_PIXMAP | xcb_sys::XCB_CW_EVENT_MASK,
+                                       values.as_ptr() as *const _);
+            gc = xcb_sys::xcb_generate_id(xcb);
+            xcb_sys::xcb_create_gc(xcb, gc, xid, 0, std::ptr::null());
+        }
+
+        connection.change_property(ChangePropertyMode::Replace, xid, atoms._XEMBED_INFO,
+                                   atoms._XEMBED_INFO, &[0, XEMBED_MAPPED][..]);
+
+        let data = Rc::new(TrayIconData {
+            atoms: atoms.clone(),
+            connection: connection.clone(),
+            gc,
+            icon: RefCell::new(icon.clone()),
+            id,
+            pixel_format,
+            xid: Cell::new(Some(xid)),
+        });
+        manager.register(xid, data.clone());
+        data.dock(tray);
+
+        Ok(TrayIcon {
+            atoms,
+            connection,
+            data,
+            manager,
+        })
+    }
+}
+
+impl<W: 'static + Clone> Drop for TrayIcon<W> {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+impl<W: 'static + Clone> ITrayIcon for TrayIcon<W> {
+    type Client = Client<W>;
+
+    fn id(&self) -> &W {
+        &self.data.id
+    }
+
+    fn remove(&self) {
+        if let Some(xid) = self.xid() {
+            self.manager.unregister(xid);
+            unsafe {
+                xcb_sys::xcb_free_gc(self.connection.xcb_connection_ptr(), self.data.gc);
+                xcb_sys::xcb_destroy_window(self.connection.xcb_connection_ptr(), xid);
+            }
+        }
+    }
+
+    fn set_icon(&self, icon: &Icon) -> Result<()> {
+        *self.data.icon.borrow_mut() = icon.clone();
+        self.data.redraw()
+    }
+
+    fn set_tooltip(&self, tooltip: &str) -> Result<()> {
+        // XEmbed trays have no tooltip protocol, but many of them show the window's name.
+        let xid = match self.xid() {
+            None => return Err(err!(ResourceExpired("tray icon removed"))),
+            Some(xid) => xid,
+        };
+        self.connection.change_property(ChangePropertyMode::Replace, xid,
+                                        self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING,
+                                        tooltip.as_bytes());
+        Ok(())
+    }
+}
+
+/// Packs an 8-bit channel value into the bits selected by `mask`.
+fn pack_channel(value: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = mask >> mask.trailing_zeros();
+    ((u32::from(value) * max + 127) / 255) << mask.trailing_zeros()
+}
+
+/// Extracts the bits selected by `mask` as an 8-bit channel value.
+fn unpack_channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = mask >> mask.trailing_zeros();
+    (((pixel & mask) >> mask.trailing_zeros()) * 255 / max) as u8
+}
@@ -14,24 +14,53 @@ use std::rc::Rc;
 
 use vectorial::Vec2;
 
-use crate::driver::x11::client::{Atoms, Client, Connection, Screen};
+use crate::cursor::{Cursor, CursorShape};
+use crate::driver::x11::client::{Atoms, Client, Connection, Reply, Screen};
+use crate::driver::x11::cursor::create_cursor;
+use crate::driver::x11::device::{select_scroll_events, select_xi_events};
+use crate::driver::x11::ime::Ime;
 use crate::driver::x11::pixel_format::PixelFormat;
 use crate::error::Result;
-use crate::event::Event;
-use crate::window::{IWindow, IWindowBuilder};
+use crate::event::{Event, MainLoop};
+use crate::monitor::Monitor;
+use crate::window::{
+    AttentionKind,
+    ClosePolicy,
+    IWindow,
+    IWindowBuilder,
+    ResizeEdge,
+    Theme,
+    WindowHandle,
+    WindowType,
+};
 use crate::Coord;
 
 /// X11 window builder.
 pub struct WindowBuilder<W: 'static + Clone> {
+    app_id: Rc<RefCell<Option<String>>>,
+    aspect_ratio: Option<Vec2<Coord>>,
     atoms: Rc<Atoms>,
+    close_policy: ClosePolicy,
     connection: Rc<Connection>,
+    dpi: Rc<Cell<u32>>,
+    ime: Option<Rc<Ime>>,
     manager: Rc<WindowManager<W>>,
+    max_size: Option<Vec2<Coord>>,
+    min_size: Option<Vec2<Coord>>,
+    modal: bool,
+    owner: Option<Rc<WindowData<W>>>,
     _phantom: PhantomData<W>,
     pixel_format: Option<PixelFormat>,
+    pointer_locks: Rc<Cell<usize>>,
+    popup: bool,
     pos: Option<Vec2<Coord>>,
     screen_num: Option<u8>,
     screens: Rc<Vec<Screen>>,
     size: Option<Vec2<Coord>>,
+    title: String,
+    transparent: bool,
+    window_type: Option<WindowType>,
+    xinput2: bool,
 }
 
 impl<W: 'static + Clone> WindowBuilder<W> {
@@ -60,15 +89,30 @@ impl<W: 'static + Clone> WindowBuilder<W> {
     /// Constructs a window builder.
     pub(crate) fn new(client: &Client<W>) -> WindowBuilder<W> {
         WindowBuilder {
+            app_id: client.app_id().clone(),
+            aspect_ratio: None,
             atoms: client.atoms().clone(),
+            close_policy: ClosePolicy::Defer,
             connection: client.connection().clone(),
+            dpi: client.dpi().clone(),
+            ime: client.ime().clone(),
             manager: client.window_manager().clone(),
+            max_size: None,
+            min_size: None,
+            modal: false,
+            owner: None,
             _phantom: PhantomData,
             pixel_format: None,
+            pointer_locks: client.pointer_locks().clone(),
+            popup: false,
             pos: None,
             screen_num: None,
             screens: client.screens_ref().clone(),
             size: None,
+            title: String::new(),
+            transparent: false,
+            window_type: None,
+            xinput2: client.xinput2(),
         }
     }
 }
@@ -77,24 +121,153 @@ impl<W: 'static + Clone> IWindowBuilder for WindowBuilder<W> {
     type Client = Client<W>;
 
     fn build(&self, id: W) -> Result<Window<W>> {
+        let owner = match self.owner {
+            None => None,
+            Some(ref owner) => Some(owner.try_xid()?),
+        };
         let window = Window::new(self, id)?;
         window.init_wm_protocols()?;
+        window.update_normal_hints()?;
+        window.set_title(self.title.as_str())?;
+        if let Some(owner) = owner {
+            window.set_transient_for(owner, self.modal)?;
+        }
+        if let Some(window_type) = self.window_type {
+            window.set_window_type(window_type)?;
+        }
+        if let Some(ref app_id) = *self.app_id.borrow() {
+            window.set_app_id(app_id.as_str())?;
+        }
+        if let Some(ref ime) = self.ime {
+            // The window still receives key events without an input context.
+            if let Err(_err) = ime.create_context(window.try_xid()?) {
+                warn!("can't create X input context: {}", _err);
+            }
+        }
         Ok(window)
     }
+
+    fn with_aspect_ratio(&mut self, ratio: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.aspect_ratio = Some(ratio);
+        self
+    }
+
+    fn with_close_policy(&mut self, policy: ClosePolicy) -> &mut WindowBuilder<W> {
+        self.close_policy = policy;
+        self
+    }
+
+    fn with_max_size(&mut self, size: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.max_size = Some(size);
+        self
+    }
+
+    fn with_min_size(&mut self, size: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.min_size = Some(size);
+        self
+    }
+
+    fn with_owner(&mut self, owner: &Window<W>, modal: bool) -> &mut WindowBuilder<W> {
+        self.modal = modal;
+        self.owner = Some(owner.data.clone());
+        self
+    }
+
+    fn with_pixel_format(&mut self, pixel_format: &PixelFormat) -> &mut WindowBuilder<W> {
+        self.pixel_format = Some(pixel_format.clone());
+        self
+    }
+
+    fn with_popup(&mut self, popup: bool) -> &mut WindowBuilder<W> {
+        self.popup = popup;
+        self
+    }
+
+    fn with_pos(&mut self, pos: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.pos = Some(pos);
+        self
+    }
+
+    fn with_size(&mut self, size: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.size = Some(size);
+        self
+    }
+
+    fn with_title(&mut self, title: &str) -> &mut WindowBuilder<W> {
+        self.title = title.to_owned();
+        self
+    }
+
+    fn with_transparency(&mut self, transparent: bool) -> &mut WindowBuilder<W> {
+        self.transparent = transparent;
+        self
+    }
+
+    fn with_window_type(&mut self, window_type: WindowType) -> &mut WindowBuilder<W> {
+        self.window_type = Some(window_type);
+        self
+    }
 }
 
 /// Data shared between a [Window] and a [WindowManager].
 pub struct WindowData<W: 'static + Clone> {
+    aspect_ratio: Cell<Option<Vec2<Coord>>>,
+    close_policy: ClosePolicy,
+    colormap: Cell<Option<u32>>,
+    confine_cursor: Cell<bool>,
+    cursor: RefCell<Cursor>,
+    cursor_visible: Cell<bool>,
+    focused: Cell<bool>,
+    handle_target: u64,
     id: W,
+    max_size: Cell<Option<Vec2<Coord>>>,
+    min_size: Cell<Option<Vec2<Coord>>>,
+    pointer_grabbed: Cell<bool>,
+    pointer_locked: Cell<bool>,
+    raw_motion: Cell<Vec2<f64>>,
+    root: u32,
+    urgent: Cell<bool>,
+    user_pos: Option<Vec2<Coord>>,
     visible: Cell<bool>,
+    wm_state: RefCell<Vec<u32>>,
     xid: Cell<Option<u32>>,
 }
 
 impl<W: 'static + Clone> WindowData<W> {
+    /// Adds unaccelerated pointer motion to the window's accumulated motion and takes the whole
+    /// pixels from it. The fractional part is kept for later motion events.
+    pub fn accumulate_raw_motion(&self, delta: Vec2<f64>) -> Vec2<Coord> {
+        let total = self.raw_motion.get();
+        let total = Vec2::new(total.x + delta.x, total.y + delta.y);
+        let whole = Vec2::new(total.x.trunc(), total.y.trunc());
+        self.raw_motion.set(Vec2::new(total.x - whole.x, total.y - whole.y));
+        Vec2::new(whole.x as Coord, whole.y as Coord)
+    }
+
+    pub fn close_policy(&self) -> ClosePolicy {
+        self.close_policy
+    }
+
+    pub fn handle_target(&self) -> u64 {
+        self.handle_target
+    }
+
     pub fn id(&self) -> &W {
         &self.id
     }
 
+    pub fn is_pointer_locked(&self) -> bool {
+        self.pointer_locked.get()
+    }
+
+    pub fn is_urgent(&self) -> bool {
+        self.urgent.get()
+    }
+
+    pub fn set_focused(&self, focused: bool) {
+        self.focused.set(focused);
+    }
+
     pub fn try_xid(&self) -> Result<u32> {
         match self.xid.get() {
             None => Err(err!(ResourceExpired("window expired"))),
@@ -115,10 +288,29 @@ impl<W: 'static + Clone> WindowData<W> {
 }
 
 impl<W: 'static + Clone> WindowData<W> {
-    fn new(id: W, xid: u32) -> WindowData<W> {
+    fn new(builder: &WindowBuilder<W>, id: W, xid: u32, root: u32, colormap: Option<u32>)
+        -> WindowData<W>
+    {
         WindowData {
+            aspect_ratio: Cell::new(builder.aspect_ratio),
+            close_policy: builder.close_policy,
+            colormap: Cell::new(colormap),
+            confine_cursor: Cell::new(false),
+            cursor: RefCell::new(Cursor::default()),
+            cursor_visible: Cell::new(true),
+            focused: Cell::new(false),
+            handle_target: WindowHandle::next_target(),
             id,
+            max_size: Cell::new(builder.max_size),
+            min_size: Cell::new(builder.min_size),
+            pointer_grabbed: Cell::new(false),
+            pointer_locked: Cell::new(false),
+            raw_motion: Cell::new(Vec2::new(0.0, 0.0)),
+            root,
+            urgent: Cell::new(false),
+            user_pos: builder.pos,
             visible: Cell::new(false),
+            wm_state: RefCell::new(Vec::new()),
             xid: Cell::new(Some(xid)),
         }
     }
@@ -155,14 +347,26 @@ impl<W: 'static + Clone> WindowManager<W> {
         }
         data
     }
+
+    /// Returns all registered windows.
+    pub fn windows(&self) -> Vec<Rc<WindowData<W>>> {
+        self.map.borrow().values().cloned().collect()
+    }
 }
 
-/// X11 window type.
+/// X11 window type. Windows are destroyed when the handle returned by
+/// [build](IWindowBuilder::build) is dropped.
 pub struct Window<W: 'static + Clone> {
     atoms: Rc<Atoms>,
     connection: Rc<Connection>,
     data: Rc<WindowData<W>>,
+    dpi: Rc<Cell<u32>>,
+    ime: Option<Rc<Ime>>,
+    owned: bool,
+    pointer_locks: Rc<Cell<usize>>,
+    root: u32,
     xcb: *mut xcb_sys::xcb_connection_t,
+    xinput2: bool,
 }
 
 impl<W: 'static + Clone> Window<W> {
@@ -183,12 +387,146 @@ impl<W: 'static + Clone> Window<W> {
 }
 
 impl<W: 'static + Clone> Window<W> {
+    /// Sets the window's cursor attribute from its current cursor and cursor visibility.
+    /// Counts a pointer lock. XInput 2 raw motion events are selected on the root window while
+    /// any of the client's windows is locked. Raw events are only delivered to root windows, so
+    /// the client forwards them to the pointer-locked windows.
+    fn acquire_raw_motion(&self) -> Result<()> {
+        if !self.xinput2 {
+            return Err(err!(Unsupported("XInput 2 is required for pointer lock")));
+        }
+        let count = self.pointer_locks.get() + 1;
+        self.pointer_locks.set(count);
+        if count == 1 {
+            select_xi_events(self.xcb, self.root, xcb_sys::XCB_INPUT_DEVICE_ALL_MASTER as u16,
+                             xcb_sys::XCB_INPUT_XI_EVENT_MASK_RAW_MOTION as u32);
+        }
+        Ok(())
+    }
+
+    fn apply_cursor(&self) -> Result<()> {
+        let xid = self.try_xid()?;
+        let cursor = if self.data.cursor_visible.get() && !self.data.pointer_locked.get() {
+            create_cursor(&self.connection, self.root, &self.data.cursor.borrow())?
+        } else {
+            create_cursor(&self.connection, self.root, &Cursor::Shape(CursorShape::Hidden))?
+        };
+
+        unsafe {
+            // The server keeps the cursor alive for as long as the window uses it.
+            xcb_sys::xcb_change_window_attributes(self.xcb, xid, xcb_sys::XCB_CW_CURSOR,
+                                                  &cursor as *const u32 as *const _);
+            xcb_sys::xcb_free_cursor(self.xcb, cursor);
+        }
+
+        Ok(())
+    }
+
+    /// Asks the window manager to start an interactive move or resize with `_NET_WM_MOVERESIZE`.
+    fn begin_move_resize(&self, direction: u32) -> Result<()> {
+        const BUTTON_PRIMARY: u32 = 1;
+        const SOURCE_APPLICATION: u32 = 1;
+
+        self.try_xid()?;
+        let pointer;
+
+        unsafe {
+            let cookie = xcb_sys::xcb_query_pointer(self.xcb, self.root);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_query_pointer_reply(self.xcb, cookie, &mut err_ptr);
+            pointer = Reply::new("X_QueryPointer", reply_ptr, err_ptr)?;
+
+            // The window manager can't take over the drag while the button press's implicit
+            // pointer grab is still held.
+            xcb_sys::xcb_ungrab_pointer(self.xcb, xcb_sys::XCB_CURRENT_TIME);
+        }
+
+        self.send_wm_message(self.atoms._NET_WM_MOVERESIZE,
+                             [pointer.root_x as u32, pointer.root_y as u32, direction,
+                              BUTTON_PRIMARY, SOURCE_APPLICATION])
+    }
+
+    /// Adds or removes an EWMH `_NET_WM_STATE` atom.
+    fn change_wm_state(&self, add: bool, state: u32) -> Result<()> {
+        const NET_WM_STATE_REMOVE: u32 = 0;
+        const NET_WM_STATE_ADD: u32 = 1;
+
+        {
+            let mut wm_state = self.data.wm_state.borrow_mut();
+            wm_state.retain(|&atom| atom != state);
+            if add {
+                wm_state.push(state);
+            }
+        }
+
+        // Mapped windows must ask the window manager to change their state. Unmapped windows may
+        // set the property directly.
+        if self.data.visible.get() {
+            let action = if add { NET_WM_STATE_ADD } else { NET_WM_STATE_REMOVE };
+            self.send_wm_message(self.atoms._NET_WM_STATE, [action, state, 0, 1, 0])
+        } else {
+            let wm_state = self.data.wm_state.borrow();
+            self.set_property(self.atoms._NET_WM_STATE, xcb_sys::XCB_ATOM_ATOM,
+                              wm_state.as_slice())?;
+            Ok(())
+        }
+    }
+
+    fn configure(&self, value_mask: u32, values: &[u32]) -> Result<()> {
+        unsafe {
+            xcb_sys::xcb_configure_window(self.xcb, self.try_xid()?, value_mask as u16,
+                                          values.as_ptr() as *const _);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the `(left, right, top, bottom)` frame extents reported by the window manager, or
+    /// zeros if the window manager doesn't support `_NET_FRAME_EXTENTS`.
+    fn frame_extents(&self) -> Result<[Coord; 4]> {
+        let reply = self.connection.get_property(self.try_xid()?, self.atoms._NET_FRAME_EXTENTS,
+                                                 xcb_sys::XCB_ATOM_CARDINAL, 4)?;
+        let mut extents = [0; 4];
+        for (extent, &value) in extents.iter_mut().zip(reply.value32()) {
+            *extent = value as Coord;
+        }
+        Ok(extents)
+    }
+
     fn init_wm_protocols(&self) -> Result<()> {
         self.set_wm_protocols([
             self.atoms.WM_DELETE_WINDOW,
         ].as_ref())
     }
 
+    /// Releases a pointer lock counted by [acquire_raw_motion](Window::acquire_raw_motion).
+    /// Raw motion events are deselected once no window is locked.
+    fn release_raw_motion(&self) {
+        let count = self.pointer_locks.get().saturating_sub(1);
+        self.pointer_locks.set(count);
+        if count == 0 {
+            select_xi_events(self.xcb, self.root, xcb_sys::XCB_INPUT_DEVICE_ALL_MASTER as u16, 0);
+        }
+    }
+
+    /// Sends a client message about this window to the window manager.
+    fn send_wm_message(&self, ty: u32, data: [u32; 5]) -> Result<()> {
+        unsafe {
+            let mut event: xcb_sys::xcb_client_message_event_t = std::mem::zeroed();
+            event.response_type = xcb_sys::XCB_CLIENT_MESSAGE as u8;
+            event.format = 32;
+            event.window = self.try_xid()?;
+            event.type_ = ty;
+            event.data.data32 = data;
+            xcb_sys::xcb_send_event(self.xcb, 0, self.root,
+                                    (xcb_sys::XCB_EVENT_MASK_SUBSTRUCTURE_NOTIFY
+                                     | xcb_sys::XCB_EVENT_MASK_SUBSTRUCTURE_REDIRECT) as u32,
+                                    &event as *const _ as *const _);
+        }
+
+        Ok(())
+    }
+
     fn set_property<T: ?Sized + PropertyData>(&self, property: u32, ty: u32, data: &T)
         -> Result<xcb_sys::xcb_void_cookie_t>
     {
@@ -196,11 +534,76 @@ impl<W: 'static + Clone> Window<W> {
                                            ty, data))
     }
 
+    /// Marks the window as a transient for `owner`. Window managers keep transient windows above
+    /// their owner, and block input to the owner while a modal transient is mapped.
+    fn set_transient_for(&self, owner: u32, modal: bool) -> Result<()> {
+        self.set_property(xcb_sys::XCB_ATOM_WM_TRANSIENT_FOR, xcb_sys::XCB_ATOM_WINDOW,
+                          &[owner][..])?;
+        if modal {
+            self.change_wm_state(true, self.atoms._NET_WM_STATE_MODAL)?;
+        }
+        Ok(())
+    }
+
+    /// Sets `_NET_WM_WINDOW_TYPE`. Only the builder sets it, since window managers only read it
+    /// when the window is mapped.
+    fn set_window_type(&self, window_type: WindowType) -> Result<()> {
+        let atom = match window_type {
+            WindowType::Dialog => self.atoms._NET_WM_WINDOW_TYPE_DIALOG,
+            WindowType::Menu => self.atoms._NET_WM_WINDOW_TYPE_MENU,
+            WindowType::Normal => self.atoms._NET_WM_WINDOW_TYPE_NORMAL,
+            WindowType::Splash => self.atoms._NET_WM_WINDOW_TYPE_SPLASH,
+            WindowType::Tooltip => self.atoms._NET_WM_WINDOW_TYPE_TOOLTIP,
+            WindowType::Utility => self.atoms._NET_WM_WINDOW_TYPE_UTILITY,
+        };
+        self.set_property(self.atoms._NET_WM_WINDOW_TYPE, xcb_sys::XCB_ATOM_ATOM, &[atom][..])?;
+        Ok(())
+    }
+
     fn set_wm_protocols(&self, protocols: &[u32]) -> Result<()> {
         self.set_property(self.atoms.WM_PROTOCOLS, xcb_sys::XCB_ATOM_ATOM, protocols)?;
         Ok(())
     }
 
+    /// Writes the `WM_NORMAL_HINTS` property from the window's size constraints.
+    fn update_normal_hints(&self) -> Result<()> {
+        const US_POSITION: u32 = 1 << 0;
+        const P_MIN_SIZE: u32 = 1 << 4;
+        const P_MAX_SIZE: u32 = 1 << 5;
+        const P_ASPECT: u32 = 1 << 7;
+
+        // See the `WM_SIZE_HINTS` layout in ICCCM section 4.1.2.3.
+        let mut hints = [0u32; 18];
+        if let Some(pos) = self.data.user_pos {
+            // Without `USPosition`, most window managers ignore the initial position and place
+            // the window themselves. The position fields are obsolete but still filled in.
+            hints[0] |= US_POSITION;
+            hints[1] = clamp_pos(pos.x) as i32 as u32;
+            hints[2] = clamp_pos(pos.y) as i32 as u32;
+        }
+        if let Some(size) = self.data.min_size.get() {
+            hints[0] |= P_MIN_SIZE;
+            hints[5] = u32::from(clamp_size(size.x));
+            hints[6] = u32::from(clamp_size(size.y));
+        }
+        if let Some(size) = self.data.max_size.get() {
+            hints[0] |= P_MAX_SIZE;
+            hints[7] = u32::from(clamp_size(size.x));
+            hints[8] = u32::from(clamp_size(size.y));
+        }
+        if let Some(ratio) = self.data.aspect_ratio.get() {
+            hints[0] |= P_ASPECT;
+            hints[11] = u32::from(clamp_size(ratio.x));
+            hints[12] = u32::from(clamp_size(ratio.y));
+            hints[13] = hints[11];
+            hints[14] = hints[12];
+        }
+
+        self.set_property(xcb_sys::XCB_ATOM_WM_NORMAL_HINTS, xcb_sys::XCB_ATOM_WM_SIZE_HINTS,
+                          &hints[..])?;
+        Ok(())
+    }
+
     /// Creates and registers a new window with `xcb_create_window()` but does not do any other
     /// initialization.
     fn new(builder: &WindowBuilder<W>, id: W) -> Result<Window<W>> {
@@ -215,14 +618,22 @@ impl<W: 'static + Clone> Window<W> {
                 n
             },
         };
+        let screen = &builder.screens[screen_num as usize];
         let pixel_format = match builder.pixel_format {
-            None => builder.screens[screen_num as usize].default_pixel_format(),
+            None if builder.transparent => match screen.transparent_pixel_format() {
+                None => return Err(err!(Unsupported("no X11 visual with an alpha channel"))),
+                Some(pixel_format) => pixel_format,
+            },
+            None => screen.default_pixel_format(),
             Some(ref pixel_format) => {
                 if *pixel_format.connection() != builder.connection
                    || pixel_format.screen_num() != screen_num
                 {
                     return Err(err!(IncompatibleResource("incompatible pixel format")));
                 }
+                if builder.transparent && pixel_format.alpha_mask() == 0 {
+                    return Err(err!(IncompatibleResource("pixel format has no alpha channel")));
+                }
                 pixel_format.clone()
             },
         };
@@ -238,45 +649,242 @@ impl<W: 'static + Clone> Window<W> {
             Some(size) => Vec2::new(clamp_size(size.x), clamp_size(size.y)),
         };
         let visual_id = pixel_format.visual_id();
-        let values = vec! {
-            xcb_sys::XCB_EVENT_MASK_STRUCTURE_NOTIFY as u32,
-        };
-        let value_mask = xcb_sys::XCB_CW_EVENT_MASK;
+        let event_mask = (xcb_sys::XCB_EVENT_MASK_EXPOSURE
+                          | xcb_sys::XCB_EVENT_MASK_FOCUS_CHANGE
+                          | xcb_sys::XCB_EVENT_MASK_KEY_PRESS
+                          | xcb_sys::XCB_EVENT_MASK_KEY_RELEASE
+                          | xcb_sys::XCB_EVENT_MASK_STRUCTURE_NOTIFY) as u32;
+        let mut attributes = vec![(xcb_sys::XCB_CW_EVENT_MASK, event_mask)];
+        let mut colormap = None;
 
         unsafe {
+            // Windows with a visual other than the root visual need their own colormap, and a
+            // border pixel so the server doesn't try to copy the parent's border pixmap.
+            if visual_id != (*screen.xcb_screen_ptr()).root_visual {
+                let cmap = xcb_sys::xcb_generate_id(xcb);
+                xcb_sys::xcb_create_colormap(xcb, xcb_sys::XCB_COLORMAP_ALLOC_NONE as u8, cmap,
+                                             parent, visual_id);
+                colormap = Some(cmap);
+                attributes.extend_from_slice(&[(xcb_sys::XCB_CW_BACK_PIXEL, 0),
+                                               (xcb_sys::XCB_CW_BORDER_PIXEL, 0),
+                                               (xcb_sys::XCB_CW_COLORMAP, cmap)]);
+            }
+
+            // Override-redirect windows are ignored by the window manager, so they're placed
+            // exactly where requested and aren't decorated.
+            if builder.popup {
+                attributes.push((xcb_sys::XCB_CW_OVERRIDE_REDIRECT, 1));
+            }
+
+            // Attribute values are ordered by their mask bits.
+            attributes.sort_by_key(|&(mask, _)| mask);
+            let value_mask = attributes.iter().fold(0, |value_mask, &(mask, _)| value_mask | mask);
+            let values: Vec<u32> = attributes.iter().map(|&(_, value)| value).collect();
+
             xid = xcb_sys::xcb_generate_id(xcb);
             xcb_sys::xcb_create_window(xcb, depth, xid, parent, pos.x, pos.y, size.x, size.y, 0,
                                        xcb_sys::XCB_WINDOW_CLASS_INPUT_OUTPUT as u16, visual_id,
                                        value_mask, values.as_ptr() as *const _);
         }
+        select_scroll_events(xcb, xid);
 
-        let data = Rc::new(WindowData::new(id, xid));
+        let data = Rc::new(WindowData::new(builder, id, xid, parent, colormap));
         builder.manager.register(data.clone());
 
         Ok(Window {
             atoms: builder.atoms.clone(),
             connection,
             data,
+            dpi: builder.dpi.clone(),
+            ime: builder.ime.clone(),
+            owned: true,
+            pointer_locks: builder.pointer_locks.clone(),
+            root: parent,
             xcb,
+            xinput2: builder.xinput2,
         })
     }
 }
 
+impl<W: 'static + Clone> Window<W> {
+    /// Constructs a handle to an existing window which doesn't destroy it when dropped.
+    pub(crate) fn from_data(client: &Client<W>, data: Rc<WindowData<W>>) -> Window<W> {
+        Window {
+            atoms: client.atoms().clone(),
+            connection: client.connection().clone(),
+            dpi: client.dpi().clone(),
+            ime: client.ime().clone(),
+            owned: false,
+            pointer_locks: client.pointer_locks().clone(),
+            root: data.root,
+            xcb: client.connection().xcb_connection_ptr(),
+            xinput2: client.xinput2(),
+            data,
+        }
+    }
+
+    /// Unlocks the pointer of a window which is being destroyed, so that raw motion isn't kept
+    /// selected for it.
+    pub(crate) fn release_pointer_lock(&self) {
+        if self.data.pointer_locked.replace(false) {
+            self.release_raw_motion();
+        }
+    }
+
+    /// Sets `WM_CLASS` and `_GTK_APPLICATION_ID`. Both parts of `WM_CLASS` are set to the app ID.
+    pub(crate) fn set_app_id(&self, app_id: &str) -> Result<()> {
+        // `WM_CLASS` is a pair of null-terminated Latin-1 strings: the instance name and the class
+        // name.
+        let latin1: Vec<u8> = app_id.chars()
+                                    .map(|ch| u8::try_from(u32::from(ch)).unwrap_or(b'?'))
+                                    .chain(std::iter::once(0))
+                                    .collect();
+        let class = [latin1.as_slice(), latin1.as_slice()].concat();
+        self.set_property(xcb_sys::XCB_ATOM_WM_CLASS, xcb_sys::XCB_ATOM_STRING,
+                          class.as_slice())?;
+        self.set_property(self.atoms._GTK_APPLICATION_ID, self.atoms.UTF8_STRING,
+                          app_id.as_bytes())?;
+        Ok(())
+    }
+
+    /// Sets or clears the urgency flag of `WM_HINTS`, preserving its other fields.
+    pub(crate) fn set_urgency(&self, urgent: bool) -> Result<()> {
+        const URGENCY_HINT: u32 = 1 << 8;
+
+        if self.data.urgent.replace(urgent) == urgent {
+            return Ok(());
+        }
+
+        // `WM_HINTS` has nine fields, the first of which holds the flags.
+        let reply = self.connection.get_property(self.try_xid()?, xcb_sys::XCB_ATOM_WM_HINTS,
+                                                 xcb_sys::XCB_ATOM_WM_HINTS, 9)?;
+        let mut hints = [0u32; 9];
+        for (hint, &value) in hints.iter_mut().zip(reply.value32()) {
+            *hint = value;
+        }
+        if urgent {
+            hints[0] |= URGENCY_HINT;
+        } else {
+            hints[0] &= !URGENCY_HINT;
+        }
+        self.set_property(xcb_sys::XCB_ATOM_WM_HINTS, xcb_sys::XCB_ATOM_WM_HINTS, &hints[..])?;
+        Ok(())
+    }
+
+    /// Grabs the pointer while it should be confined to the window and the window is mapped and
+    /// focused, or releases the window's grab otherwise. This is called whenever one of those
+    /// conditions changes, since a grab on an unmapped window fails and a grab held while another
+    /// application is focused would trap the pointer.
+    pub(crate) fn update_pointer_grab(&self) -> Result<()> {
+        let xid = self.try_xid()?;
+        let confine = self.data.confine_cursor.get() || self.data.pointer_locked.get();
+        let grab = confine && self.data.visible.get() && self.data.focused.get();
+        if grab == self.data.pointer_grabbed.get() {
+            return Ok(());
+        }
+
+        unsafe {
+            if !grab {
+                xcb_sys::xcb_ungrab_pointer(self.xcb, xcb_sys::XCB_CURRENT_TIME);
+                self.data.pointer_grabbed.set(false);
+                return Ok(());
+            }
+
+            let cookie = xcb_sys::xcb_grab_pointer(self.xcb, 1, xid, 0,
+                                                   xcb_sys::XCB_GRAB_MODE_ASYNC as u8,
+                                                   xcb_sys::XCB_GRAB_MODE_ASYNC as u8,
+                                                   xid, xcb_sys::XCB_NONE,
+                                                   xcb_sys::XCB_CURRENT_TIME);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_grab_pointer_reply(self.xcb, cookie, &mut err_ptr);
+            let reply = Reply::new("X_GrabPointer", reply_ptr, err_ptr)?;
+            if u32::from(reply.status) != xcb_sys::XCB_GRAB_STATUS_SUCCESS {
+                return Err(err!(RequestFailed{"X_GrabPointer failed with status {}",
+                                              reply.status}));
+            }
+        }
+
+        self.data.pointer_grabbed.set(true);
+        Ok(())
+    }
+}
+
 impl<W: 'static + Clone> Drop for Window<W> {
     fn drop(&mut self) {
-        self.destroy();
+        if self.owned {
+            self.destroy();
+        }
     }
 }
 
 impl<W: 'static + Clone> IWindow for Window<W> {
     type Client = Client<W>;
 
+    fn begin_move_drag(&self) -> Result<()> {
+        const NET_WM_MOVERESIZE_MOVE: u32 = 8;
+        self.begin_move_resize(NET_WM_MOVERESIZE_MOVE)
+    }
+
+    fn begin_resize_drag(&self, edge: ResizeEdge) -> Result<()> {
+        // See `_NET_WM_MOVERESIZE` in the EWMH specification.
+        self.begin_move_resize(match edge {
+            ResizeEdge::TopLeft => 0,
+            ResizeEdge::Top => 1,
+            ResizeEdge::TopRight => 2,
+            ResizeEdge::Right => 3,
+            ResizeEdge::BottomRight => 4,
+            ResizeEdge::Bottom => 5,
+            ResizeEdge::BottomLeft => 6,
+            ResizeEdge::Left => 7,
+        })
+    }
+
+    fn close(&self) -> Result<()> {
+        // Without an event mask, the event is delivered to the window's creator, which is this
+        // client.
+        unsafe {
+            let mut event: xcb_sys::xcb_client_message_event_t = std::mem::zeroed();
+            event.response_type = xcb_sys::XCB_CLIENT_MESSAGE as u8;
+            event.format = 32;
+            event.window = self.try_xid()?;
+            event.type_ = self.atoms.WM_PROTOCOLS;
+            event.data.data32 = [self.atoms.WM_DELETE_WINDOW, xcb_sys::XCB_CURRENT_TIME, 0, 0, 0];
+            xcb_sys::xcb_send_event(self.xcb, 0, event.window, 0, &event as *const _ as *const _);
+        }
+
+        Ok(())
+    }
+
+    fn confine_cursor(&self, confine: bool) -> Result<()> {
+        self.try_xid()?;
+        self.data.confine_cursor.set(confine);
+        self.update_pointer_grab()
+    }
+
     fn destroy(&self) {
         if let Some(xid) = self.data.xid.take() {
+            if let Some(ref ime) = self.ime {
+                ime.destroy_context(xid);
+            }
+            self.release_pointer_lock();
             unsafe {
                 xcb_sys::xcb_destroy_window(self.xcb, xid);
             }
         }
+        if let Some(colormap) = self.data.colormap.take() {
+            unsafe {
+                xcb_sys::xcb_free_colormap(self.xcb, colormap);
+            }
+        }
+    }
+
+    fn dpi(&self) -> u32 {
+        self.dpi.get()
+    }
+
+    fn handle(&self, main_loop: &MainLoop) -> Result<WindowHandle> {
+        self.try_xid()?;
+        Ok(main_loop.window_handle(self.data.handle_target))
     }
 
     fn id(&self) -> &W {
@@ -287,6 +895,192 @@ impl<W: 'static + Clone> IWindow for Window<W> {
         self.xid().is_some() && self.data.visible.get()
     }
 
+    fn pos(&self) -> Result<Vec2<Coord>> {
+        let xid = self.try_xid()?;
+        let extents = self.frame_extents()?;
+
+        unsafe {
+            let cookie = xcb_sys::xcb_translate_coordinates(self.xcb, xid, self.root, 0, 0);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_translate_coordinates_reply(self.xcb, cookie,
+                                                                     &mut err_ptr);
+            let reply = Reply::new("X_TranslateCoords", reply_ptr, err_ptr)?;
+            Ok(Vec2::new(Coord::from(reply.dst_x) - extents[0],
+                         Coord::from(reply.dst_y) - extents[2]))
+        }
+    }
+
+    fn request_user_attention(&self, kind: Option<AttentionKind>) -> Result<()> {
+        // The window manager clears the EWMH state when the window is activated, but the urgency
+        // hint is left for the client to clear when the window gains focus. Window managers
+        // without EWMH support only know about the urgency hint.
+        self.try_xid()?;
+        self.change_wm_state(kind.is_some(), self.atoms._NET_WM_STATE_DEMANDS_ATTENTION)?;
+        self.set_urgency(kind == Some(AttentionKind::Critical))
+    }
+
+    fn request_redraw(&self) -> Result<()> {
+        unsafe {
+            // Clearing a zero-sized area with `exposures` set generates an `Expose` event for the
+            // whole window. Windows are created without a background, so nothing is erased.
+            xcb_sys::xcb_clear_area(self.xcb, 1, self.try_xid()?, 0, 0, 0, 0);
+        }
+
+        Ok(())
+    }
+
+    fn set_aspect_ratio(&self, ratio: Option<Vec2<Coord>>) -> Result<()> {
+        self.data.aspect_ratio.set(ratio);
+        self.update_normal_hints()
+    }
+
+    fn set_cursor(&self, cursor: &Cursor) -> Result<()> {
+        *self.data.cursor.borrow_mut() = cursor.clone();
+        self.apply_cursor()
+    }
+
+    fn set_cursor_pos(&self, pos: Vec2<Coord>) -> Result<()> {
+        unsafe {
+            xcb_sys::xcb_warp_pointer(self.xcb, xcb_sys::XCB_NONE, self.try_xid()?, 0, 0, 0, 0,
+                                      clamp_pos(pos.x), clamp_pos(pos.y));
+        }
+
+        Ok(())
+    }
+
+    fn set_cursor_visible(&self, visible: bool) -> Result<()> {
+        self.data.cursor_visible.set(visible);
+        self.apply_cursor()
+    }
+
+    fn set_fullscreen(&self, monitor: Option<&Monitor>) -> Result<()> {
+        // Window managers make windows fullscreen on whichever monitor contains them.
+        if let Some(monitor) = monitor {
+            self.set_pos(monitor.pos())?;
+            self.set_size(monitor.size())?;
+        }
+        self.change_wm_state(monitor.is_some(), self.atoms._NET_WM_STATE_FULLSCREEN)
+    }
+
+    fn set_ime_position(&self, pos: Vec2<Coord>) -> Result<()> {
+        let xid = self.try_xid()?;
+        if let Some(ref ime) = self.ime {
+            ime.set_spot(xid, Vec2::new(clamp_pos(pos.x), clamp_pos(pos.y)));
+        }
+        Ok(())
+    }
+
+    fn set_max_size(&self, size: Option<Vec2<Coord>>) -> Result<()> {
+        self.data.max_size.set(size);
+        self.update_normal_hints()
+    }
+
+    fn set_min_size(&self, size: Option<Vec2<Coord>>) -> Result<()> {
+        self.data.min_size.set(size);
+        self.update_normal_hints()
+    }
+
+    fn set_opacity(&self, opacity: f32) -> Result<()> {
+        let xid = self.try_xid()?;
+        let opacity = opacity_to_cardinal(opacity)?;
+
+        // Compositing managers treat a missing `_NET_WM_WINDOW_OPACITY` as fully opaque.
+        if opacity == u32::MAX {
+            unsafe {
+                xcb_sys::xcb_delete_property(self.xcb, xid, self.atoms._NET_WM_WINDOW_OPACITY);
+            }
+            return Ok(());
+        }
+
+        self.set_property(self.atoms._NET_WM_WINDOW_OPACITY, xcb_sys::XCB_ATOM_CARDINAL,
+                          &[opacity][..])?;
+        Ok(())
+    }
+
+    fn set_pointer_locked(&self, locked: bool) -> Result<()> {
+        self.try_xid()?;
+        if self.data.pointer_locked.get() == locked {
+            return Ok(());
+        }
+        if locked {
+            self.data.raw_motion.set(Vec2::new(0.0, 0.0));
+            self.acquire_raw_motion()?;
+        } else {
+            self.release_raw_motion();
+        }
+        self.data.pointer_locked.set(locked);
+        self.apply_cursor()?;
+        self.update_pointer_grab()
+    }
+
+    fn set_pos(&self, pos: Vec2<Coord>) -> Result<()> {
+        // With the default `NorthWest` window gravity, the window manager positions the frame
+        // rather than the client area.
+        self.configure(xcb_sys::XCB_CONFIG_WINDOW_X | xcb_sys::XCB_CONFIG_WINDOW_Y,
+                       &[clamp_pos(pos.x) as i32 as u32, clamp_pos(pos.y) as i32 as u32])
+    }
+
+    fn set_progress(&self, progress: Option<f32>) -> Result<()> {
+        let xid = self.try_xid()?;
+
+        // `_NET_WM_XAPP_PROGRESS` is a percentage which is shown by XApp-based window lists such
+        // as those of Cinnamon and MATE. Other window managers ignore it.
+        let progress = match progress {
+            Some(progress) if progress.is_nan() => {
+                return Err(err!(InvalidArgument("progress is NaN")));
+            },
+            Some(progress) => (progress.clamp(0.0, 1.0) * 100.0).round() as u32,
+            None => {
+                unsafe {
+                    xcb_sys::xcb_delete_property(self.xcb, xid, self.atoms._NET_WM_XAPP_PROGRESS);
+                }
+                return Ok(());
+            },
+        };
+
+        self.set_property(self.atoms._NET_WM_XAPP_PROGRESS, xcb_sys::XCB_ATOM_CARDINAL,
+                          &[progress][..])?;
+        Ok(())
+    }
+
+    fn set_size(&self, size: Vec2<Coord>) -> Result<()> {
+        self.configure(xcb_sys::XCB_CONFIG_WINDOW_WIDTH | xcb_sys::XCB_CONFIG_WINDOW_HEIGHT,
+                       &[u32::from(clamp_size(size.x)), u32::from(clamp_size(size.y))])
+    }
+
+    fn set_theme(&self, theme: Option<Theme>) -> Result<()> {
+        let xid = self.try_xid()?;
+
+        // Mutter and other GTK-aware window managers draw dark decorations for windows which ask
+        // for the dark variant of the GTK theme.
+        let variant = match theme {
+            None => {
+                unsafe {
+                    xcb_sys::xcb_delete_property(self.xcb, xid, self.atoms._GTK_THEME_VARIANT);
+                }
+                return Ok(());
+            },
+            Some(Theme::Dark) => "dark",
+            Some(Theme::Light) => "light",
+        };
+
+        self.set_property(self.atoms._GTK_THEME_VARIANT, self.atoms.UTF8_STRING,
+                          variant.as_bytes())?;
+        Ok(())
+    }
+
+    fn set_title(&self, title: &str) -> Result<()> {
+        // `WM_NAME` is a Latin-1 `STRING`, so characters outside of that range are replaced. Window
+        // managers which support EWMH will use the UTF-8 `_NET_WM_NAME` instead.
+        let latin1: Vec<u8> = title.chars()
+                                   .map(|ch| u8::try_from(u32::from(ch)).unwrap_or(b'?'))
+                                   .collect();
+        self.set_property(xcb_sys::XCB_ATOM_WM_NAME, xcb_sys::XCB_ATOM_STRING,
+                          latin1.as_slice())?;
+        self.set_property(self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING, title.as_bytes())?;
+        Ok(())
+    }
+
     fn set_visible(&self, visible: bool) -> Result<()> {
         unsafe {
             if visible {
@@ -298,6 +1092,18 @@ impl<W: 'static + Clone> IWindow for Window<W> {
 
         Ok(())
     }
+
+    fn size(&self) -> Result<Vec2<Coord>> {
+        let xid = self.try_xid()?;
+
+        unsafe {
+            let cookie = xcb_sys::xcb_get_geometry(self.xcb, xid);
+            let mut err_ptr = std::ptr::null_mut();
+            let reply_ptr = xcb_sys::xcb_get_geometry_reply(self.xcb, cookie, &mut err_ptr);
+            let reply = Reply::new("X_GetGeometry", reply_ptr, err_ptr)?;
+            Ok(Vec2::new(Coord::from(reply.width), Coord::from(reply.height)))
+        }
+    }
 }
 
 /// Modes for property change requests.
@@ -317,6 +1123,18 @@ pub trait PropertyData {
     fn len(&self) -> u32;
 }
 
+impl PropertyData for [u8] {
+    fn as_ptr(&self) -> *const c_void {
+        self.as_ptr() as *const c_void
+    }
+
+    fn format() -> u8 { 8 }
+
+    fn len(&self) -> u32 {
+        self.len() as u32
+    }
+}
+
 impl PropertyData for [u32] {
     fn as_ptr(&self) -> *const c_void {
         self.as_ptr() as *const c_void
@@ -350,3 +1168,11 @@ fn clamp_size(n: Coord) -> u16 {
         n as u16
     }
 }
+
+/// Converts an opacity from 0.0 to 1.0 to a `_NET_WM_WINDOW_OPACITY` value.
+fn opacity_to_cardinal(opacity: f32) -> Result<u32> {
+    if opacity.is_nan() {
+        return Err(err!(InvalidArgument("opacity is NaN")));
+    }
+    Ok((f64::from(opacity.clamp(0.0, 1.0)) * f64::from(u32::MAX)).round() as u32)
+}
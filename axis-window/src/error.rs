@@ -25,6 +25,7 @@ pub enum ErrorKind {
     RequestFailed,
     ResourceExpired,
     RuntimeError,
+    Unsupported,
 }
 
 impl ErrorKind {
@@ -41,6 +42,7 @@ impl ErrorKind {
             ErrorKind::RequestFailed => "request failed",
             ErrorKind::ResourceExpired => "resource expired",
             ErrorKind::RuntimeError => "runtime error",
+            ErrorKind::Unsupported => "unsupported operation",
         }
     }
 }
@@ -6,14 +6,90 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
-use std::cell::Cell;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use vectorial::Vec2;
+
+use crate::error::{ErrorKind, Result};
+use crate::key::Key;
+use crate::monitor::Monitor;
+use crate::window::{Theme, WindowCommand, WindowHandle};
+use crate::Coord;
+
+/// Returned from event callbacks to control the main loop.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ControlFlow {
+    /// Keeps running the main loop.
+    Continue,
+    /// Causes the main loop to break, as if [MainLoop::quit] was called.
+    Exit,
+    /// Wakes a waiting main loop at the specified time and triggers an update, even if no other
+    /// events arrive. Only the most recently requested time is kept.
+    WaitUntil(Instant),
+}
+
+impl From<()> for ControlFlow {
+    fn from(_: ()) -> ControlFlow {
+        ControlFlow::Continue
+    }
+}
 
 /// Window system event type.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Event<W: 'static + Clone> {
     CloseRequest { window_id: W },
     Destroy { window_id: W },
+    /// An input device was added. See [devices](crate::IClient::devices).
+    DeviceAdded { device_id: u64 },
+    /// An input device was removed.
+    DeviceRemoved { device_id: u64 },
+    /// The window system reported an error asynchronously, usually for a request which has no
+    /// reply. Such errors don't stop the main loop.
+    Error { kind: ErrorKind, detail: String },
+    /// Text was committed by an input method. On X11, input method events require the `x11-sys`
+    /// feature and a UTF-8 locale; see [crate::window::IWindow::set_ime_position].
+    ImeCommit { window_id: W, text: String },
+    /// An input method's composition text changed. `cursor` is a byte offset into `text`. An empty
+    /// `text` indicates that composition has ended. Has the same requirements as `ImeCommit`.
+    ImePreedit { window_id: W, text: String, cursor: Option<usize> },
+    /// A key was pressed. `scancode` is the window system's code for the physical key: an X11
+    /// keycode, or a Win32 scan code with `0xe0` in the high byte for extended keys. `key` is the
+    /// logical key in the active layout. `repeat` is true if this is an auto-repeated press of a
+    /// key which is already held.
+    KeyDown { window_id: W, scancode: u32, key: Key, repeat: bool },
+    KeyUp { window_id: W, scancode: u32, key: Key },
+    /// The active keyboard layout changed, so scancodes may map to different keys.
+    KeymapChanged,
+    /// Unaccelerated relative mouse motion, reported while the window's pointer is locked.
+    MouseMotionRaw { window_id: W, delta: Vec2<Coord> },
+    /// The application was asked to quit by the window system, such as by a Win32 `WM_QUIT`
+    /// message. Only reported by [poll_events](crate::IClient::poll_events) and
+    /// [wait_events](crate::IClient::wait_events); [run](crate::IClient::run) simply returns.
+    Quit { exit_code: i32 },
+    RedrawRequested { window_id: W },
+    ScaleFactorChanged { window_id: W, dpi: u32 },
+    /// The mouse wheel or another scrolling device was scrolled over the window. `delta` is
+    /// measured in 120ths of a wheel notch, so that high resolution devices such as touchpads can
+    /// report partial notches. Positive values scroll right and up.
+    Scroll { window_id: W, delta: Vec2<Coord> },
+    /// The system's preferred theme changed. See [theme](crate::IClient::theme).
+    ThemeChanged { theme: Theme },
+    /// A timer created by [MainLoop::set_timeout] or [MainLoop::set_interval] elapsed.
+    Timer { timer_id: TimerId },
+    /// A tray icon was clicked with the primary mouse button.
+    TrayActivate { tray_id: W },
+    /// A tray icon's context menu should be shown, usually because it was right-clicked. `pos` is
+    /// the pointer position in screen coordinates, where a
+    /// [popup](crate::IWindowBuilder::with_popup) window can be placed.
+    TrayMenuRequest { tray_id: W, pos: Vec2<Coord> },
     Update { update_mode: UpdateMode },
+    /// Application-defined value posted through a [MainLoopProxy].
+    User { data: UserData },
     VisibilityChange { window_id: W, visible: bool },
 }
 
@@ -23,6 +99,14 @@ impl<W: 'static + Clone> Event<W> {
         match *self {
             Event::CloseRequest { ref window_id } => Some(window_id),
             Event::Destroy { ref window_id } => Some(window_id),
+            Event::ImeCommit { ref window_id, .. } => Some(window_id),
+            Event::ImePreedit { ref window_id, .. } => Some(window_id),
+            Event::KeyDown { ref window_id, .. } => Some(window_id),
+            Event::KeyUp { ref window_id, .. } => Some(window_id),
+            Event::MouseMotionRaw { ref window_id, .. } => Some(window_id),
+            Event::RedrawRequested { ref window_id } => Some(window_id),
+            Event::ScaleFactorChanged { ref window_id, .. } => Some(window_id),
+            Event::Scroll { ref window_id, .. } => Some(window_id),
             Event::VisibilityChange { ref window_id, .. } => Some(window_id),
             _ => None,
         }
@@ -31,11 +115,24 @@ impl<W: 'static + Clone> Event<W> {
 
 /// Main loop state type.
 pub struct MainLoop {
+    next_timer_id: Cell<u64>,
+    proxy: Arc<ProxyState>,
     quit: Cell<bool>,
+    timers: RefCell<Vec<Timer>>,
     update_mode: Cell<UpdateMode>,
+    wait_until: Cell<Option<Instant>>,
 }
 
 impl MainLoop {
+    /// Cancels a timer. Returns false if the timer was a timeout which already fired or was
+    /// already cancelled.
+    pub fn cancel_timer(&self, timer_id: TimerId) -> bool {
+        let mut timers = self.timers.borrow_mut();
+        let len = timers.len();
+        timers.retain(|timer| timer.id != timer_id);
+        timers.len() != len
+    }
+
     /// Returns true if the main loop is slated to break.
     pub fn is_quit_requested(&self) -> bool {
         self.quit.get()
@@ -44,16 +141,40 @@ impl MainLoop {
     /// Constructs a new main loop state.
     pub fn new(update_mode: UpdateMode) -> MainLoop {
         MainLoop {
+            next_timer_id: Cell::new(0),
+            proxy: Arc::new(ProxyState {
+                commands: Mutex::new(VecDeque::new()),
+                events: Mutex::new(VecDeque::new()),
+                waker: Mutex::new(None),
+            }),
             quit: Cell::new(false),
+            timers: RefCell::new(Vec::new()),
             update_mode: Cell::new(update_mode),
+            wait_until: Cell::new(None),
         }
     }
 
+    /// Returns a handle which can post events to the main loop from other threads.
+    pub fn proxy(&self) -> MainLoopProxy {
+        MainLoopProxy { state: self.proxy.clone() }
+    }
+
     /// Causes the main loop to break.
     pub fn quit(&self) {
         self.quit.set(true);
     }
 
+    /// Creates a timer which fires a [Timer](Event::Timer) event every `interval` until it's
+    /// cancelled.
+    pub fn set_interval(&self, interval: Duration) -> TimerId {
+        self.add_timer(interval, Some(interval))
+    }
+
+    /// Creates a timer which fires a single [Timer](Event::Timer) event after `delay`.
+    pub fn set_timeout(&self, delay: Duration) -> TimerId {
+        self.add_timer(delay, None)
+    }
+
     /// Changes the update mode.
     pub fn set_update_mode(&self, update_mode: UpdateMode) {
         self.update_mode.set(update_mode);
@@ -63,6 +184,239 @@ impl MainLoop {
     pub fn update_mode(&self) -> UpdateMode { self.update_mode.get() }
 }
 
+impl MainLoop {
+    /// Applies a control flow value returned from an event callback.
+    pub(crate) fn apply_control_flow(&self, control_flow: ControlFlow) {
+        match control_flow {
+            ControlFlow::Continue => (),
+            ControlFlow::Exit => self.quit(),
+            ControlFlow::WaitUntil(instant) => self.wait_until.set(Some(instant)),
+        }
+    }
+
+    /// Returns the time remaining until the next timer fires or the requested wake-up time, or
+    /// `None` if there is nothing to wait for.
+    pub(crate) fn next_timeout(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.timers.borrow().iter().map(|timer| timer.deadline)
+            .chain(self.wait_until.get())
+            .map(|deadline| deadline.saturating_duration_since(now))
+            .min()
+    }
+
+    /// Sets the function used to wake the driver while it's waiting for events. Drivers must
+    /// clear the waker before the resources it uses are released.
+    pub(crate) fn set_waker(&self, waker: Option<Box<dyn Fn() + Send>>) -> Result<()> {
+        *self.proxy.waker.lock()? = waker;
+        Ok(())
+    }
+
+    /// Removes expired timeouts, reschedules expired intervals and returns the IDs of all expired
+    /// timers in the order of their deadlines.
+    pub(crate) fn take_expired_timers(&self) -> Vec<TimerId> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+
+        self.timers.borrow_mut().retain_mut(|timer| {
+            if timer.deadline > now {
+                return true;
+            }
+            expired.push((timer.deadline, timer.id));
+            match timer.interval {
+                None => false,
+                Some(interval) => {
+                    // Skip missed ticks rather than firing them in a burst.
+                    timer.deadline += interval;
+                    if timer.deadline <= now {
+                        timer.deadline = now + interval;
+                    }
+                    true
+                },
+            }
+        });
+
+        expired.sort();
+        expired.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Returns true and clears the requested wake-up time if it has passed.
+    pub(crate) fn take_wait_expired(&self) -> bool {
+        match self.wait_until.get() {
+            Some(instant) if instant <= Instant::now() => {
+                self.wait_until.set(None);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Takes all values posted by proxies since the last call.
+    pub(crate) fn take_user_events(&self) -> Result<VecDeque<UserData>> {
+        Ok(std::mem::take(&mut *self.proxy.events.lock()?))
+    }
+
+    /// Takes all commands sent through window handles since the last call, along with the target
+    /// IDs of their handles.
+    pub(crate) fn take_window_commands(&self) -> Result<VecDeque<(u64, WindowCommand)>> {
+        Ok(std::mem::take(&mut *self.proxy.commands.lock()?))
+    }
+
+    /// Constructs a handle for the window with the specified target ID. See
+    /// [WindowHandle::next_target].
+    pub(crate) fn window_handle(&self, target: u64) -> WindowHandle {
+        WindowHandle::new(self.proxy(), target)
+    }
+}
+
+impl MainLoop {
+    fn add_timer(&self, delay: Duration, interval: Option<Duration>) -> TimerId {
+        let id = TimerId(self.next_timer_id.get());
+        self.next_timer_id.set(id.0 + 1);
+        self.timers.borrow_mut().push(Timer {
+            deadline: Instant::now() + delay,
+            id,
+            interval,
+        });
+        id
+    }
+}
+
+/// Paces [Sync](UpdateMode::Sync) updates to a display's refresh rate when the driver has no
+/// better source of v-blank timing.
+pub(crate) struct FrameClock {
+    next_frame: Instant,
+    period: Duration,
+}
+
+impl FrameClock {
+    /// Schedules the next frame, skipping missed frames rather than catching up.
+    pub fn advance(&mut self) {
+        let now = Instant::now();
+        self.next_frame += self.period;
+        if self.next_frame <= now {
+            self.next_frame = now + self.period;
+        }
+    }
+
+    /// Constructs a frame clock using the refresh rate of the primary monitor, or 60 Hz if the
+    /// refresh rate is unknown.
+    pub fn for_monitors<I: IntoIterator<Item = Monitor>>(monitors: I) -> FrameClock {
+        const DEFAULT_REFRESH_RATE: u32 = 60_000;
+
+        // Some drivers report a refresh rate of zero when it's unknown.
+        let mut refresh_rate = None;
+        for monitor in monitors {
+            if monitor.is_primary() || refresh_rate.is_none() {
+                refresh_rate = monitor.refresh_rate().filter(|&rate| rate != 0).or(refresh_rate);
+            }
+        }
+
+        let refresh_rate = match refresh_rate {
+            None => {
+                warn!("monitor refresh rate is unknown; pacing updates at 60 Hz");
+                DEFAULT_REFRESH_RATE
+            },
+            Some(rate) => rate,
+        };
+
+        FrameClock {
+            next_frame: Instant::now(),
+            period: Duration::from_secs(1000) / refresh_rate,
+        }
+    }
+
+    /// Returns the time remaining until the next frame, or `None` if a frame is due.
+    pub fn time_until_frame(&self) -> Option<Duration> {
+        let now = Instant::now();
+        if now >= self.next_frame {
+            None
+        } else {
+            Some(self.next_frame - now)
+        }
+    }
+}
+
+/// Handle for posting [User](Event::User) events to a [MainLoop] from any thread.
+#[derive(Clone)]
+pub struct MainLoopProxy {
+    state: Arc<ProxyState>,
+}
+
+impl MainLoopProxy {
+    /// Posts a [User](Event::User) event carrying `data`, waking the main loop if it's waiting for
+    /// events.
+    pub fn send<T: 'static + Send + Sync>(&self, data: T) -> Result<()> {
+        self.state.events.lock()?.push_back(UserData(Arc::new(data)));
+        self.wake()
+    }
+}
+
+impl MainLoopProxy {
+    /// Queues a command for a window, waking the main loop if it's waiting for events.
+    pub(crate) fn send_window_command(&self, target: u64, command: WindowCommand) -> Result<()> {
+        self.state.commands.lock()?.push_back((target, command));
+        self.wake()
+    }
+}
+
+impl MainLoopProxy {
+    fn wake(&self) -> Result<()> {
+        if let Some(ref waker) = *self.state.waker.lock()? {
+            waker();
+        }
+        Ok(())
+    }
+}
+
+/// State shared between a [MainLoop] and its proxies.
+struct ProxyState {
+    commands: Mutex<VecDeque<(u64, WindowCommand)>>,
+    events: Mutex<VecDeque<UserData>>,
+    waker: Mutex<Option<Box<dyn Fn() + Send>>>,
+}
+
+/// Timer state.
+struct Timer {
+    deadline: Instant,
+    id: TimerId,
+    interval: Option<Duration>,
+}
+
+/// Identifies a timer created by a [MainLoop].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct TimerId(u64);
+
+/// Payload of a [User](Event::User) event. Clones share the same value, and payloads only compare
+/// equal to their clones.
+#[derive(Clone)]
+pub struct UserData(Arc<dyn Any + Send + Sync>);
+
+impl UserData {
+    /// Returns the value if it has type `T`.
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+
+    /// Returns true if the value has type `T`.
+    pub fn is<T: 'static>(&self) -> bool {
+        self.0.is::<T>()
+    }
+}
+
+impl Debug for UserData {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("UserData(..)")
+    }
+}
+
+impl Eq for UserData {}
+
+impl PartialEq for UserData {
+    fn eq(&self, other: &UserData) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
 /// Determines when update events are triggered.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum UpdateMode {
@@ -70,7 +424,160 @@ pub enum UpdateMode {
     Passive,
     /// Updates are continuously triggered when the event queue is empty.
     Active,
-    /// If supported, an update is triggered once per v-blank. If not supported, this behaves the
-    /// same as `Active`.
+    /// Updates are triggered once per display refresh. Drivers without access to v-blank timing
+    /// pace updates to the refresh rate of the primary monitor.
     Sync,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deadline(main_loop: &MainLoop, timer_id: TimerId) -> Option<Instant> {
+        main_loop.timers.borrow().iter().find(|timer| timer.id == timer_id)
+            .map(|timer| timer.deadline)
+    }
+
+    fn monitor(primary: bool, refresh_rate: Option<u32>) -> Monitor {
+        Monitor::new(String::new(), Vec2::new(0, 0), Vec2::new(1920, 1080))
+            .with_primary(primary)
+            .with_refresh_rate(refresh_rate)
+    }
+
+    /// Moves a timer's deadline so that tests don't depend on the passage of time.
+    fn set_deadline(main_loop: &MainLoop, timer_id: TimerId, deadline: Instant) {
+        for timer in main_loop.timers.borrow_mut().iter_mut() {
+            if timer.id == timer_id {
+                timer.deadline = deadline;
+            }
+        }
+    }
+
+    #[test]
+    fn cancel_fired_timeout() {
+        let main_loop = MainLoop::new(UpdateMode::Passive);
+        let timeout = main_loop.set_timeout(Duration::ZERO);
+
+        assert_eq!(main_loop.take_expired_timers(), vec![timeout]);
+        assert!(!main_loop.cancel_timer(timeout));
+        assert!(main_loop.take_expired_timers().is_empty());
+    }
+
+    #[test]
+    fn cancel_timer() {
+        let main_loop = MainLoop::new(UpdateMode::Passive);
+        let timeout = main_loop.set_timeout(Duration::from_secs(60));
+        let interval = main_loop.set_interval(Duration::from_secs(60));
+
+        assert!(main_loop.cancel_timer(timeout));
+        assert!(!main_loop.cancel_timer(timeout));
+
+        set_deadline(&main_loop, interval, Instant::now() - Duration::from_secs(1));
+        assert_eq!(main_loop.take_expired_timers(), vec![interval]);
+        assert!(main_loop.cancel_timer(interval));
+        assert_eq!(main_loop.next_timeout(), None);
+    }
+
+    #[test]
+    fn expired_timers_are_ordered_by_deadline() {
+        let main_loop = MainLoop::new(UpdateMode::Passive);
+        let now = Instant::now();
+        let a = main_loop.set_timeout(Duration::ZERO);
+        let b = main_loop.set_interval(Duration::from_secs(60));
+        let c = main_loop.set_timeout(Duration::ZERO);
+        let pending = main_loop.set_timeout(Duration::from_secs(60));
+        set_deadline(&main_loop, a, now - Duration::from_millis(10));
+        set_deadline(&main_loop, b, now - Duration::from_millis(30));
+        set_deadline(&main_loop, c, now - Duration::from_millis(20));
+
+        assert_eq!(main_loop.take_expired_timers(), vec![b, c, a]);
+        assert!(deadline(&main_loop, pending).is_some());
+        assert!(main_loop.take_expired_timers().is_empty());
+    }
+
+    #[test]
+    fn frame_clock_defaults_to_60_hz() {
+        let clock = FrameClock::for_monitors(None);
+        assert_eq!(clock.period, Duration::from_secs(1) / 60);
+
+        let clock = FrameClock::for_monitors([monitor(true, Some(0))]);
+        assert_eq!(clock.period, Duration::from_secs(1) / 60);
+    }
+
+    #[test]
+    fn frame_clock_prefers_primary_monitor() {
+        let monitors = [
+            monitor(false, Some(75_000)),
+            monitor(true, Some(144_000)),
+            monitor(false, Some(60_000)),
+        ];
+        let clock = FrameClock::for_monitors(monitors);
+        assert_eq!(clock.period, Duration::from_secs(1) / 144);
+
+        // Monitors with unknown refresh rates are skipped.
+        let clock = FrameClock::for_monitors([monitor(false, Some(75_000)), monitor(true, None)]);
+        assert_eq!(clock.period, Duration::from_secs(1) / 75);
+    }
+
+    #[test]
+    fn frame_clock_skips_missed_frames() {
+        let mut clock = FrameClock::for_monitors(None);
+        assert_eq!(clock.time_until_frame(), None);
+
+        clock.advance();
+        let until_frame = clock.time_until_frame().unwrap();
+        assert!(until_frame <= clock.period);
+
+        // A late frame is scheduled one period from now rather than immediately.
+        clock.next_frame = Instant::now() - clock.period * 10;
+        clock.advance();
+        assert!(clock.next_frame > Instant::now());
+        assert!(clock.time_until_frame().unwrap() <= clock.period);
+    }
+
+    #[test]
+    fn interval_is_rescheduled() {
+        let main_loop = MainLoop::new(UpdateMode::Passive);
+        let period = Duration::from_secs(60);
+        let interval = main_loop.set_interval(period);
+        let first = Instant::now() - Duration::from_secs(1);
+        set_deadline(&main_loop, interval, first);
+
+        assert_eq!(main_loop.take_expired_timers(), vec![interval]);
+        assert_eq!(deadline(&main_loop, interval), Some(first + period));
+        assert!(main_loop.take_expired_timers().is_empty());
+    }
+
+    #[test]
+    fn interval_skips_missed_ticks() {
+        let main_loop = MainLoop::new(UpdateMode::Passive);
+        let period = Duration::from_millis(10);
+        let interval = main_loop.set_interval(period);
+        set_deadline(&main_loop, interval, Instant::now() - Duration::from_secs(1));
+
+        let before = Instant::now();
+        assert_eq!(main_loop.take_expired_timers(), vec![interval]);
+        let deadline = deadline(&main_loop, interval).unwrap();
+        assert!(deadline > before);
+        assert!(deadline <= Instant::now() + period);
+    }
+
+    #[test]
+    fn next_timeout() {
+        let main_loop = MainLoop::new(UpdateMode::Passive);
+        assert_eq!(main_loop.next_timeout(), None);
+
+        let timeout = main_loop.set_timeout(Duration::from_secs(60));
+        let next = main_loop.next_timeout().unwrap();
+        assert!(next > Duration::from_secs(59) && next <= Duration::from_secs(60));
+
+        // A requested wake-up time counts if it's sooner than every timer.
+        main_loop.apply_control_flow(ControlFlow::WaitUntil(
+            Instant::now() + Duration::from_secs(10)));
+        assert!(main_loop.next_timeout().unwrap() <= Duration::from_secs(10));
+
+        // Deadlines which already passed are clamped to zero.
+        set_deadline(&main_loop, timeout, Instant::now() - Duration::from_secs(1));
+        assert_eq!(main_loop.next_timeout(), Some(Duration::ZERO));
+    }
+}
@@ -11,3 +11,12 @@ pub mod posix;
 
 #[cfg(all(feature = "winapi", target_os = "windows"))]
 pub mod win32;
+
+#[cfg(all(feature = "x11-driver", feature = "x11-sys", any(
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "netbsd",
+    target_os = "openbsd",
+)))]
+pub mod xlib;
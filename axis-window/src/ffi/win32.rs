@@ -6,9 +6,15 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
+use std::ffi::CString;
 use std::fmt::{Display, Formatter};
+use std::os::raw::c_void;
+use std::sync::Once;
 
-use winapi::shared::minwindef::HMODULE;
+use winapi::shared::minwindef::{BOOL, FARPROC, HMODULE};
+use winapi::shared::windef::{DPI_AWARENESS_CONTEXT, HWND, RECT};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::imm::HIMC;
 
 /// Win32 error type.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -71,6 +77,69 @@ impl Display for Error {
 
 impl std::error::Error for Error {}
 
+pub const GCS_COMPSTR: u32 = 0x0008;
+pub const GCS_CURSORPOS: u32 = 0x0080;
+pub const GCS_RESULTSTR: u32 = 0x0800;
+
+#[link(name = "imm32")]
+extern "system" {
+    pub fn ImmGetCompositionStringW(himc: HIMC, index: u32, buf: *mut c_void, len: u32) -> i32;
+}
+
+#[link(name = "shell32")]
+extern "system" {
+    pub fn SetCurrentProcessExplicitAppUserModelID(app_id: *const u16) -> HRESULT;
+}
+
+/// Converts a client rectangle to a window rectangle for a window with the specified styles and
+/// DPI. Falls back to the system DPI on versions of Windows which don't support
+/// `AdjustWindowRectExForDpi`.
+pub unsafe fn adjust_window_rect_for_dpi(rect: &mut RECT, style: u32, ex_style: u32, dpi: u32)
+    -> bool
+{
+    match *ADJUST_WINDOW_RECT_EX_FOR_DPI {
+        None => winapi::um::winuser::AdjustWindowRectEx(rect, style, 0, ex_style) != 0,
+        Some(adjust_window_rect_ex_for_dpi) => {
+            adjust_window_rect_ex_for_dpi(rect, style, 0, ex_style, dpi) != 0
+        },
+    }
+}
+
+/// Opts the process into per-monitor DPI awareness, so that Windows doesn't stretch its windows
+/// as bitmaps on high DPI monitors. Per-monitor v2 awareness (Windows 10 1703) is preferred,
+/// followed by per-monitor awareness (Windows 8.1) and system awareness. This has no effect if
+/// the awareness was already set, for example by the application manifest.
+pub fn enable_dpi_awareness() {
+    static ONCE: Once = Once::new();
+
+    ONCE.call_once(|| unsafe {
+        if let Some(set_process_dpi_awareness_context) = *SET_PROCESS_DPI_AWARENESS_CONTEXT {
+            if set_process_dpi_awareness_context(
+                winapi::shared::windef::DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) != 0
+            {
+                return;
+            }
+        }
+
+        // `SetProcessDpiAwareness` fails with `E_ACCESSDENIED` if the awareness was already set,
+        // in which case it shouldn't be downgraded to system awareness.
+        let shcore: Vec<u16> = "shcore.dll".encode_utf16()
+                                           .chain(std::iter::repeat(0).take(1))
+                                           .collect();
+        if !winapi::um::libloaderapi::LoadLibraryW(shcore.as_ptr()).is_null() {
+            let proc_ptr = get_proc_address("shcore.dll", "SetProcessDpiAwareness");
+            if !proc_ptr.is_null() {
+                let set_process_dpi_awareness: unsafe extern "system" fn(u32) -> i32 =
+                    std::mem::transmute(proc_ptr);
+                set_process_dpi_awareness(PROCESS_PER_MONITOR_DPI_AWARE);
+                return;
+            }
+        }
+
+        winapi::um::winuser::SetProcessDPIAware();
+    });
+}
+
 /// Gets the current executable's handle.
 pub fn get_exe_handle() -> crate::Result<HMODULE> {
     let handle;
@@ -84,3 +153,111 @@ pub fn get_exe_handle() -> crate::Result<HMODULE> {
     }
     Ok(handle)
 }
+
+/// Gets the DPI of a window. Falls back to the DPI of the window's device context on versions of
+/// Windows which don't support `GetDpiForWindow`.
+pub fn get_dpi_for_window(hwnd: HWND) -> u32 {
+    unsafe {
+        if let Some(get_dpi_for_window) = *GET_DPI_FOR_WINDOW {
+            match get_dpi_for_window(hwnd) {
+                0 => (),
+                dpi => return dpi,
+            }
+        }
+
+        let hdc = winapi::um::winuser::GetDC(hwnd);
+        if hdc.is_null() {
+            return 96;
+        }
+        let dpi = winapi::um::wingdi::GetDeviceCaps(hdc, winapi::um::wingdi::LOGPIXELSX);
+        winapi::um::winuser::ReleaseDC(hwnd, hdc);
+        match dpi {
+            dpi if dpi > 0 => dpi as u32,
+            _ => 96,
+        }
+    }
+}
+
+/// Gets the system DPI, which is the DPI of the primary monitor when the process started. Falls
+/// back to the DPI of the screen's device context on versions of Windows which don't support
+/// `GetDpiForSystem`.
+pub fn get_dpi_for_system() -> u32 {
+    unsafe {
+        if let Some(get_dpi_for_system) = *GET_DPI_FOR_SYSTEM {
+            return get_dpi_for_system();
+        }
+    }
+
+    get_dpi_for_window(std::ptr::null_mut())
+}
+
+/// Gets a UTF-16 composition string from an input context.
+pub unsafe fn imm_get_composition_string(himc: HIMC, index: u32) -> Option<Vec<u16>> {
+    let len = ImmGetCompositionStringW(himc, index, std::ptr::null_mut(), 0);
+    if len < 0 {
+        return None;
+    }
+
+    let mut buf = vec![0u16; len as usize / 2];
+    let len = ImmGetCompositionStringW(himc, index, buf.as_mut_ptr() as *mut c_void, len as u32);
+    if len < 0 {
+        return None;
+    }
+    buf.truncate(len as usize / 2);
+    Some(buf)
+}
+
+/// Looks up a function exported by a module which is already loaded into the process. Returns null
+/// if either the module or function can't be found.
+pub fn get_proc_address(module: &str, name: &str) -> FARPROC {
+    let module: Vec<u16> = module.encode_utf16().chain(std::iter::repeat(0).take(1)).collect();
+    let name = match CString::new(name) {
+        Ok(name) => name,
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    unsafe {
+        let handle = winapi::um::libloaderapi::GetModuleHandleW(module.as_ptr());
+        if handle.is_null() {
+            return std::ptr::null_mut();
+        }
+        winapi::um::libloaderapi::GetProcAddress(handle, name.as_ptr())
+    }
+}
+
+/// `PROCESS_DPI_AWARENESS` value for per-monitor awareness.
+const PROCESS_PER_MONITOR_DPI_AWARE: u32 = 2;
+
+lazy_static! {
+    static ref ADJUST_WINDOW_RECT_EX_FOR_DPI: Option<
+        unsafe extern "system" fn(*mut RECT, u32, BOOL, u32, u32) -> BOOL> =
+    {
+        match get_proc_address("user32.dll", "AdjustWindowRectExForDpi") {
+            proc_ptr if proc_ptr.is_null() => None,
+            proc_ptr => Some(unsafe { std::mem::transmute(proc_ptr) }),
+        }
+    };
+
+    static ref GET_DPI_FOR_SYSTEM: Option<unsafe extern "system" fn() -> u32> = {
+        match get_proc_address("user32.dll", "GetDpiForSystem") {
+            proc_ptr if proc_ptr.is_null() => None,
+            proc_ptr => Some(unsafe { std::mem::transmute(proc_ptr) }),
+        }
+    };
+
+    static ref GET_DPI_FOR_WINDOW: Option<unsafe extern "system" fn(HWND) -> u32> = {
+        match get_proc_address("user32.dll", "GetDpiForWindow") {
+            proc_ptr if proc_ptr.is_null() => None,
+            proc_ptr => Some(unsafe { std::mem::transmute(proc_ptr) }),
+        }
+    };
+
+    static ref SET_PROCESS_DPI_AWARENESS_CONTEXT: Option<
+        unsafe extern "system" fn(DPI_AWARENESS_CONTEXT) -> BOOL> =
+    {
+        match get_proc_address("user32.dll", "SetProcessDpiAwarenessContext") {
+            proc_ptr if proc_ptr.is_null() => None,
+            proc_ptr => Some(unsafe { std::mem::transmute(proc_ptr) }),
+        }
+    };
+}
@@ -0,0 +1,120 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+#![allow(non_camel_case_types, non_upper_case_globals)]
+
+use std::os::raw::{c_char, c_int, c_ulong, c_ushort, c_void};
+
+use x11_sys::{Display, XEvent, XKeyEvent};
+
+pub type XIC = *mut c_void;
+pub type XIM = *mut c_void;
+pub type XIMStyle = c_ulong;
+pub type XPointer = *mut c_char;
+
+/// Callback type used for both `XIMProc` and `XICProc`. Xlib ignores the return value of
+/// callbacks other than `PreeditStartCallback`.
+pub type XIMProc = unsafe extern "C" fn(XIC, XPointer, XPointer) -> c_int;
+
+#[repr(C)]
+pub struct XIMCallback {
+    pub client_data: XPointer,
+    pub callback: Option<XIMProc>,
+}
+
+#[repr(C)]
+pub struct XIMPreeditCaretCallbackStruct {
+    pub position: c_int,
+    pub direction: c_int,
+    pub style: c_int,
+}
+
+#[repr(C)]
+pub struct XIMPreeditDrawCallbackStruct {
+    pub caret: c_int,
+    pub chg_first: c_int,
+    pub chg_length: c_int,
+    pub text: *mut XIMText,
+}
+
+#[repr(C)]
+pub struct XIMStyles {
+    pub count_styles: c_ushort,
+    pub supported_styles: *mut XIMStyle,
+}
+
+/// `string` is a union of `char *multi_byte` and `wchar_t *wide_char`, selected by
+/// `encoding_is_wchar`.
+#[repr(C)]
+pub struct XIMText {
+    pub length: c_ushort,
+    pub feedback: *mut c_ulong,
+    pub encoding_is_wchar: c_int,
+    pub string: *mut c_void,
+}
+
+#[repr(C)]
+pub struct XPoint {
+    pub x: i16,
+    pub y: i16,
+}
+
+pub const KeyPress: c_int = 2;
+pub const KeyRelease: c_int = 3;
+pub const QueuedAfterFlush: c_int = 2;
+
+pub const XBufferOverflow: c_int = -1;
+pub const XLookupBoth: c_int = 4;
+pub const XLookupChars: c_int = 2;
+
+pub const XIMAbsolutePosition: c_int = 10;
+pub const XIMBackwardChar: c_int = 1;
+pub const XIMForwardChar: c_int = 0;
+pub const XIMLineEnd: c_int = 9;
+pub const XIMLineStart: c_int = 8;
+
+pub const XIMPreeditCallbacks: XIMStyle = 0x0002;
+pub const XIMPreeditNothing: XIMStyle = 0x0008;
+pub const XIMStatusNothing: XIMStyle = 0x0400;
+
+pub const XNClientWindow: &[u8] = b"clientWindow\0";
+pub const XNDestroyCallback: &[u8] = b"destroyCallback\0";
+pub const XNFocusWindow: &[u8] = b"focusWindow\0";
+pub const XNInputStyle: &[u8] = b"inputStyle\0";
+pub const XNPreeditAttributes: &[u8] = b"preeditAttributes\0";
+pub const XNPreeditCaretCallback: &[u8] = b"preeditCaretCallback\0";
+pub const XNPreeditDoneCallback: &[u8] = b"preeditDoneCallback\0";
+pub const XNPreeditDrawCallback: &[u8] = b"preeditDrawCallback\0";
+pub const XNPreeditStartCallback: &[u8] = b"preeditStartCallback\0";
+pub const XNQueryInputStyle: &[u8] = b"queryInputStyle\0";
+pub const XNSpotLocation: &[u8] = b"spotLocation\0";
+
+#[link(name = "X11")]
+extern "C" {
+    pub fn XCloseIM(im: XIM) -> c_int;
+    pub fn XCreateIC(im: XIM, ...) -> XIC;
+    pub fn XDestroyIC(ic: XIC);
+    pub fn XFilterEvent(event: *mut XEvent, window: c_ulong) -> c_int;
+    pub fn XGetIMValues(im: XIM, ...) -> *mut c_char;
+    pub fn XOpenIM(display: *mut Display, db: *mut c_void, res_name: *mut c_char,
+                   res_class: *mut c_char) -> XIM;
+    pub fn XSetICFocus(ic: XIC);
+    pub fn XSetICValues(ic: XIC, ...) -> *mut c_char;
+    pub fn XSetIMValues(im: XIM, ...) -> *mut c_char;
+    pub fn XSetLocaleModifiers(modifiers: *const c_char) -> *mut c_char;
+    pub fn XSupportsLocale() -> c_int;
+    pub fn XUnsetICFocus(ic: XIC);
+    pub fn XVaCreateNestedList(unused: c_int, ...) -> *mut c_void;
+    pub fn Xutf8LookupString(ic: XIC, event: *mut XKeyEvent, buffer: *mut c_char, bytes: c_int,
+                             keysym: *mut c_ulong, status: *mut c_int) -> c_int;
+}
+
+/// Returns a pointer to a nul-terminated XIM value name such as [XNInputStyle].
+pub fn xn(name: &[u8]) -> *const c_char {
+    name.as_ptr() as *const c_char
+}
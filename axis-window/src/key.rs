@@ -0,0 +1,37 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+/// Logical key as determined by the active keyboard layout.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Key {
+    Alt,
+    Backspace,
+    CapsLock,
+    /// Key which produces a character without modifiers, such as letters, digits, punctuation
+    /// and the space bar. Letters are reported in lowercase.
+    Character(char),
+    Control,
+    Delete,
+    Down,
+    End,
+    Enter,
+    Escape,
+    /// Function key `F1` through `F24`.
+    F(u8),
+    Home,
+    Insert,
+    Left,
+    PageDown,
+    PageUp,
+    Right,
+    Shift,
+    Super,
+    Tab,
+    Unknown,
+    Up,
+}
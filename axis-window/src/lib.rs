@@ -52,18 +52,40 @@ mod macros;
 pub mod driver;
 
 mod client;
+mod cursor;
+mod device;
 mod error;
 mod event;
+mod key;
+mod monitor;
 mod pixel_format;
+mod tray;
 mod window;
 
 #[allow(dead_code)]
 mod ffi;
 
 pub use client::{Client, IClient};
+pub use cursor::{Cursor, CursorImage, CursorShape};
+pub use device::{Device, DeviceKind, Devices};
 pub use error::{Error, ErrorKind, Result};
-pub use event::{Event, MainLoop, UpdateMode};
-pub use window::{IWindow, IWindowBuilder, Window, WindowBuilder};
+pub use event::{ControlFlow, Event, MainLoop, MainLoopProxy, TimerId, UpdateMode, UserData};
+pub use key::Key;
+pub use monitor::{Monitor, Monitors};
+pub use pixel_format::{IPixelFormat, PixelFormat, PixelFormatRequirements};
+pub use tray::{Icon, ITrayIcon, TrayIcon};
+pub use window::{
+    AttentionKind,
+    ClosePolicy,
+    IWindow,
+    IWindowBuilder,
+    ResizeEdge,
+    Theme,
+    Window,
+    WindowBuilder,
+    WindowHandle,
+    WindowType,
+};
 
 /// Window coordinate type.
 pub type Coord = i32;
@@ -66,3 +66,9 @@ macro_rules! err {
 macro_rules! error {
     ($($tt:tt),*) => {};
 }
+
+/// No-op.
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+    ($($tt:tt),*) => {};
+}
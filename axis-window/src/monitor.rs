@@ -0,0 +1,117 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use vectorial::Vec2;
+
+use crate::Coord;
+
+/// Describes a display monitor.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Monitor {
+    name: String,
+    pos: Vec2<Coord>,
+    primary: bool,
+    refresh_rate: Option<u32>,
+    size: Vec2<Coord>,
+    work_pos: Vec2<Coord>,
+    work_size: Vec2<Coord>,
+}
+
+impl Monitor {
+    /// Returns true if a point in screen coordinates lies within the monitor.
+    pub fn contains(&self, point: Vec2<Coord>) -> bool {
+        point.x >= self.pos.x && point.y >= self.pos.y
+            && point.x - self.pos.x < self.size.x && point.y - self.pos.y < self.size.y
+    }
+
+    /// Returns true if this is the primary monitor.
+    pub fn is_primary(&self) -> bool {
+        self.primary
+    }
+
+    /// Returns the name of the monitor as reported by the window system.
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Returns the position of the monitor in screen coordinates.
+    pub fn pos(&self) -> Vec2<Coord> {
+        self.pos
+    }
+
+    /// Returns the refresh rate of the monitor in millihertz, if known.
+    pub fn refresh_rate(&self) -> Option<u32> {
+        self.refresh_rate
+    }
+
+    /// Returns the size of the monitor.
+    pub fn size(&self) -> Vec2<Coord> {
+        self.size
+    }
+
+    /// Returns the position of the monitor's work area, which excludes panels and taskbars.
+    pub fn work_pos(&self) -> Vec2<Coord> {
+        self.work_pos
+    }
+
+    /// Returns the size of the monitor's work area, which excludes panels and taskbars.
+    pub fn work_size(&self) -> Vec2<Coord> {
+        self.work_size
+    }
+}
+
+impl Monitor {
+    pub(crate) fn new(name: String, pos: Vec2<Coord>, size: Vec2<Coord>) -> Monitor {
+        Monitor {
+            name,
+            pos,
+            primary: false,
+            refresh_rate: None,
+            size,
+            work_pos: pos,
+            work_size: size,
+        }
+    }
+
+    pub(crate) fn with_primary(self, primary: bool) -> Monitor {
+        Monitor { primary, ..self }
+    }
+
+    pub(crate) fn with_refresh_rate(self, refresh_rate: Option<u32>) -> Monitor {
+        Monitor { refresh_rate, ..self }
+    }
+
+    pub(crate) fn with_work_area(self, work_pos: Vec2<Coord>, work_size: Vec2<Coord>)
+        -> Monitor
+    {
+        Monitor { work_pos, work_size, ..self }
+    }
+}
+
+/// Iterator over the monitors reported by a window system client.
+pub struct Monitors {
+    inner: std::vec::IntoIter<Monitor>,
+}
+
+impl Monitors {
+    pub(crate) fn new(monitors: Vec<Monitor>) -> Monitors {
+        Monitors { inner: monitors.into_iter() }
+    }
+}
+
+impl Iterator for Monitors {
+    type Item = Monitor;
+
+    fn next(&mut self) -> Option<Monitor> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
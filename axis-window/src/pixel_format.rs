@@ -10,20 +10,58 @@ use std::any::Any;
 use std::rc::Rc;
 
 /// Pixel format interface.
-pub trait IPixelFormat: Clone + Eq {}
+pub trait IPixelFormat: Clone + Eq {
+    /// Returns the number of depth buffer bits, or zero if the format has no depth buffer.
+    fn depth_bits(&self) -> u8;
+
+    /// Returns true if the format has separate front and back buffers.
+    fn is_double_buffered(&self) -> bool;
+
+    /// Returns the number of multisample samples per pixel, or zero if the format isn't
+    /// multisampled.
+    fn samples(&self) -> u8;
+
+    /// Returns the number of stencil buffer bits, or zero if the format has no stencil buffer.
+    fn stencil_bits(&self) -> u8;
+}
 
 /// Internal interface for [PixelFormat].
 trait IPixelFormatObject: 'static {
+    fn as_any(&self) -> &dyn Any;
+    fn depth_bits(&self) -> u8;
     fn eq(&self, rhs: &dyn Any) -> bool;
+    fn is_double_buffered(&self) -> bool;
+    fn samples(&self) -> u8;
+    fn stencil_bits(&self) -> u8;
 }
 
 impl<T: 'static + IPixelFormat> IPixelFormatObject for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn depth_bits(&self) -> u8 {
+        <T as IPixelFormat>::depth_bits(self)
+    }
+
     fn eq(&self, rhs: &dyn Any) -> bool {
         match rhs.downcast_ref::<T>() {
             None => false,
             Some(rhs) => *self == *rhs,
         }
     }
+
+    fn is_double_buffered(&self) -> bool {
+        <T as IPixelFormat>::is_double_buffered(self)
+    }
+
+    fn samples(&self) -> u8 {
+        <T as IPixelFormat>::samples(self)
+    }
+
+    fn stencil_bits(&self) -> u8 {
+        <T as IPixelFormat>::stencil_bits(self)
+    }
 }
 
 /// Boxed pixel format type.
@@ -33,6 +71,10 @@ pub struct PixelFormat {
 }
 
 impl PixelFormat {
+    pub(crate) fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.inner.as_any().downcast_ref()
+    }
+
     pub(crate) fn new<T: 'static + IPixelFormat>(inner: T) -> PixelFormat {
         PixelFormat { inner: Rc::new(inner) }
     }
@@ -40,10 +82,65 @@ impl PixelFormat {
 
 impl Eq for PixelFormat {}
 
-impl IPixelFormat for PixelFormat {}
+impl IPixelFormat for PixelFormat {
+    fn depth_bits(&self) -> u8 {
+        self.inner.depth_bits()
+    }
+
+    fn is_double_buffered(&self) -> bool {
+        self.inner.is_double_buffered()
+    }
+
+    fn samples(&self) -> u8 {
+        self.inner.samples()
+    }
+
+    fn stencil_bits(&self) -> u8 {
+        self.inner.stencil_bits()
+    }
+}
 
 impl PartialEq for PixelFormat {
     fn eq(&self, rhs: &PixelFormat) -> bool {
-        self.inner.eq(rhs)
+        self.inner.eq(rhs.inner.as_any())
+    }
+}
+
+/// Criteria for choosing a pixel format suitable for OpenGL rendering.
+///
+/// Bit and sample counts are minimums. Among the formats which satisfy them, the one with the
+/// fewest excess bits and samples is preferred.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PixelFormatRequirements {
+    /// Minimum number of depth buffer bits.
+    pub depth_bits: u8,
+    /// Whether the format must be double buffered.
+    pub double_buffered: bool,
+    /// Minimum number of multisample samples per pixel. Zero and one both request a format
+    /// without multisampling.
+    pub samples: u8,
+    /// Minimum number of stencil buffer bits.
+    pub stencil_bits: u8,
+}
+
+impl PixelFormatRequirements {
+    /// Returns true if `self` is satisfied by a format with the given attributes.
+    pub(crate) fn is_satisfied_by(&self, depth_bits: u8, double_buffered: bool, samples: u8,
+                                  stencil_bits: u8) -> bool
+    {
+        depth_bits >= self.depth_bits && (double_buffered || !self.double_buffered)
+        && (samples >= self.samples || self.samples <= 1) && stencil_bits >= self.stencil_bits
+    }
+
+    /// Scores a format which satisfies `self`. Lower scores are better.
+    pub(crate) fn score(&self, depth_bits: u8, double_buffered: bool, samples: u8,
+                        stencil_bits: u8) -> u32
+    {
+        // Unrequested multisampling is the most expensive excess, followed by an unrequested back
+        // buffer.
+        let excess_samples = u32::from(samples.saturating_sub(std::cmp::max(self.samples, 1)));
+        let excess_buffering = u32::from(double_buffered && !self.double_buffered);
+        excess_samples << 16 | excess_buffering << 12
+        | u32::from(depth_bits - self.depth_bits) + u32::from(stencil_bits - self.stencil_bits)
     }
 }
@@ -0,0 +1,120 @@
+/*
+ * Copyright (c) 2023 Martin Mills <daggerbot@gmail.com>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use vectorial::Vec2;
+
+use crate::client::{Client, IClient};
+use crate::error::Result;
+use crate::Coord;
+
+/// Image shown by a tray icon.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Icon {
+    pixels: Vec<[u8; 4]>,
+    size: Vec2<Coord>,
+}
+
+impl Icon {
+    /// Constructs an icon from non-premultiplied RGBA pixels in row-major order.
+    pub fn new(size: Vec2<Coord>, pixels: Vec<[u8; 4]>) -> Result<Icon> {
+        if size.x <= 0 || size.y <= 0 || size.x > u16::MAX as Coord || size.y > u16::MAX as Coord {
+            return Err(err!(InvalidArgument("icon size out of range")));
+        }
+        if pixels.len() != size.x as usize * size.y as usize {
+            return Err(err!(InvalidArgument("icon pixel count doesn't match size")));
+        }
+
+        Ok(Icon { pixels, size })
+    }
+
+    /// Returns the RGBA pixels of the icon in row-major order.
+    pub fn pixels(&self) -> &[[u8; 4]] {
+        self.pixels.as_slice()
+    }
+
+    /// Returns the size of the icon.
+    pub fn size(&self) -> Vec2<Coord> {
+        self.size
+    }
+}
+
+/// Tray icon interface.
+pub trait ITrayIcon {
+    type Client: IClient;
+
+    /// Returns the ID which identifies the icon in tray events.
+    fn id(&self) -> &<Self::Client as IClient>::WindowId;
+
+    /// Removes the icon from the tray. This also happens when the icon is dropped.
+    fn remove(&self);
+
+    /// Changes the icon's image.
+    fn set_icon(&self, icon: &Icon) -> Result<()>;
+
+    /// Sets the text shown when the pointer hovers over the icon.
+    fn set_tooltip(&self, tooltip: &str) -> Result<()>;
+}
+
+/// Internal interface for [TrayIcon].
+trait ITrayIconObject<W: 'static + Clone>: 'static {
+    fn id(&self) -> &W;
+    fn remove(&self);
+    fn set_icon(&self, icon: &Icon) -> Result<()>;
+    fn set_tooltip(&self, tooltip: &str) -> Result<()>;
+}
+
+impl<T: 'static + ITrayIcon> ITrayIconObject<<T::Client as IClient>::WindowId> for T {
+    fn id(&self) -> &<T::Client as IClient>::WindowId {
+        <T as ITrayIcon>::id(self)
+    }
+
+    fn remove(&self) {
+        <T as ITrayIcon>::remove(self)
+    }
+
+    fn set_icon(&self, icon: &Icon) -> Result<()> {
+        <T as ITrayIcon>::set_icon(self, icon)
+    }
+
+    fn set_tooltip(&self, tooltip: &str) -> Result<()> {
+        <T as ITrayIcon>::set_tooltip(self, tooltip)
+    }
+}
+
+/// Boxed tray icon type.
+pub struct TrayIcon<W: 'static + Clone> {
+    inner: Box<dyn ITrayIconObject<W>>,
+}
+
+impl<W: 'static + Clone> TrayIcon<W> {
+    pub(crate) fn new<T: 'static + ITrayIcon>(inner: T) -> TrayIcon<W>
+    where T::Client: IClient<WindowId = W>
+    {
+        TrayIcon { inner: Box::new(inner) }
+    }
+}
+
+impl<W: 'static + Clone> ITrayIcon for TrayIcon<W> {
+    type Client = Client<W>;
+
+    fn id(&self) -> &W {
+        self.inner.id()
+    }
+
+    fn remove(&self) {
+        self.inner.remove()
+    }
+
+    fn set_icon(&self, icon: &Icon) -> Result<()> {
+        self.inner.set_icon(icon)
+    }
+
+    fn set_tooltip(&self, tooltip: &str) -> Result<()> {
+        self.inner.set_tooltip(tooltip)
+    }
+}
@@ -6,8 +6,18 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use vectorial::Vec2;
+
 use crate::client::{Client, IClient};
-use crate::error::Result;
+use crate::cursor::Cursor;
+use crate::error::{Error, Result};
+use crate::event::{MainLoop, MainLoopProxy};
+use crate::monitor::Monitor;
+use crate::pixel_format::PixelFormat;
+use crate::Coord;
 
 /// Window builder interface.
 pub trait IWindowBuilder {
@@ -16,11 +26,68 @@ pub trait IWindowBuilder {
     /// Builds a window.
     fn build(&self, id: <Self::Client as IClient>::WindowId)
         -> Result<<Self::Client as IClient>::Window>;
+
+    /// Constrains the client area to the aspect ratio `ratio.x : ratio.y`.
+    fn with_aspect_ratio(&mut self, ratio: Vec2<Coord>) -> &mut Self;
+
+    /// Sets what happens when the user asks to close the window. Defaults to
+    /// [Defer](ClosePolicy::Defer).
+    fn with_close_policy(&mut self, policy: ClosePolicy) -> &mut Self;
+
+    /// Sets the maximum size of the client area.
+    fn with_max_size(&mut self, size: Vec2<Coord>) -> &mut Self;
+
+    /// Sets the minimum size of the client area.
+    fn with_min_size(&mut self, size: Vec2<Coord>) -> &mut Self;
+
+    /// Makes the window owned by `owner`. Owned windows stay above their owner and are treated as
+    /// its dialogs. If `modal` is true, the owner doesn't accept input while the window exists.
+    fn with_owner(&mut self, owner: &<Self::Client as IClient>::Window, modal: bool)
+        -> &mut Self;
+
+    /// Sets the pixel format of the window, such as one returned by
+    /// [choose_pixel_format](IClient::choose_pixel_format). The client's default pixel format is
+    /// used otherwise.
+    fn with_pixel_format(&mut self, pixel_format: &<Self::Client as IClient>::PixelFormat)
+        -> &mut Self;
+
+    /// Makes the window a popup, such as a menu, tooltip or dropdown. Popups bypass the window
+    /// manager, have no decorations, and are positioned in screen coordinates.
+    fn with_popup(&mut self, popup: bool) -> &mut Self;
+
+    /// Sets the initial position of the window frame in screen coordinates.
+    fn with_pos(&mut self, pos: Vec2<Coord>) -> &mut Self;
+
+    /// Sets the initial size of the client area.
+    fn with_size(&mut self, size: Vec2<Coord>) -> &mut Self;
+
+    /// Sets the initial window title.
+    fn with_title(&mut self, title: &str) -> &mut Self;
+
+    /// Requests a window with per-pixel alpha. Building fails if the window system or the chosen
+    /// pixel format can't provide one.
+    fn with_transparency(&mut self, transparent: bool) -> &mut Self;
+
+    /// Sets the type of the window, which tells the window manager how to decorate and place it.
+    /// Otherwise, the window manager decides, usually treating owned windows as dialogs.
+    fn with_window_type(&mut self, window_type: WindowType) -> &mut Self;
 }
 
 /// Internal interface for [WindowBuilder].
 trait IWindowBuilderObject<W: 'static + Clone>: 'static {
     fn build(&self, id: W) -> Result<Window<W>>;
+    fn with_aspect_ratio(&mut self, ratio: Vec2<Coord>);
+    fn with_close_policy(&mut self, policy: ClosePolicy);
+    fn with_max_size(&mut self, size: Vec2<Coord>);
+    fn with_min_size(&mut self, size: Vec2<Coord>);
+    fn with_owner(&mut self, owner: &Window<W>, modal: bool) -> Result<()>;
+    fn with_pixel_format(&mut self, pixel_format: &PixelFormat) -> Result<()>;
+    fn with_popup(&mut self, popup: bool);
+    fn with_pos(&mut self, pos: Vec2<Coord>);
+    fn with_size(&mut self, size: Vec2<Coord>);
+    fn with_title(&mut self, title: &str);
+    fn with_transparency(&mut self, transparent: bool);
+    fn with_window_type(&mut self, window_type: WindowType);
 }
 
 impl<T: 'static + IWindowBuilder> IWindowBuilderObject<<T::Client as IClient>::WindowId> for T {
@@ -29,18 +96,86 @@ impl<T: 'static + IWindowBuilder> IWindowBuilderObject<<T::Client as IClient>::W
     {
         Ok(Window::new(<Self as IWindowBuilder>::build(self, id)?))
     }
+
+    fn with_aspect_ratio(&mut self, ratio: Vec2<Coord>) {
+        <Self as IWindowBuilder>::with_aspect_ratio(self, ratio);
+    }
+
+    fn with_close_policy(&mut self, policy: ClosePolicy) {
+        <Self as IWindowBuilder>::with_close_policy(self, policy);
+    }
+
+    fn with_max_size(&mut self, size: Vec2<Coord>) {
+        <Self as IWindowBuilder>::with_max_size(self, size);
+    }
+
+    fn with_min_size(&mut self, size: Vec2<Coord>) {
+        <Self as IWindowBuilder>::with_min_size(self, size);
+    }
+
+    fn with_owner(&mut self, owner: &Window<<T::Client as IClient>::WindowId>, modal: bool)
+        -> Result<()>
+    {
+        // Boxed windows always wrap the driver's window type, so this can only fail if windows
+        // from different drivers are mixed.
+        let owner = match owner.downcast_ref::<<T::Client as IClient>::Window>() {
+            None => return Err(err!(IncompatibleResource("owner window from another client"))),
+            Some(owner) => owner,
+        };
+        <Self as IWindowBuilder>::with_owner(self, owner, modal);
+        Ok(())
+    }
+
+    fn with_pixel_format(&mut self, pixel_format: &PixelFormat) -> Result<()> {
+        let pixel_format = match pixel_format.downcast_ref() {
+            None => return Err(err!(IncompatibleResource("pixel format from another client"))),
+            Some(pixel_format) => pixel_format,
+        };
+        <Self as IWindowBuilder>::with_pixel_format(self, pixel_format);
+        Ok(())
+    }
+
+    fn with_popup(&mut self, popup: bool) {
+        <Self as IWindowBuilder>::with_popup(self, popup);
+    }
+
+    fn with_pos(&mut self, pos: Vec2<Coord>) {
+        <Self as IWindowBuilder>::with_pos(self, pos);
+    }
+
+    fn with_size(&mut self, size: Vec2<Coord>) {
+        <Self as IWindowBuilder>::with_size(self, size);
+    }
+
+    fn with_title(&mut self, title: &str) {
+        <Self as IWindowBuilder>::with_title(self, title);
+    }
+
+    fn with_transparency(&mut self, transparent: bool) {
+        <Self as IWindowBuilder>::with_transparency(self, transparent);
+    }
+
+    fn with_window_type(&mut self, window_type: WindowType) {
+        <Self as IWindowBuilder>::with_window_type(self, window_type);
+    }
 }
 
 /// Boxed window builder type.
 pub struct WindowBuilder<W: 'static + Clone> {
     inner: Box<dyn IWindowBuilderObject<W>>,
+    owner_error: Option<Error>,
+    pixel_format_error: Option<Error>,
 }
 
 impl<W: 'static + Clone> WindowBuilder<W> {
     pub(crate) fn new<T: 'static + IWindowBuilder>(inner: T) -> WindowBuilder<W>
     where <T as IWindowBuilder>::Client: IClient<WindowId = W>
     {
-        WindowBuilder { inner: Box::new(inner) }
+        WindowBuilder {
+            inner: Box::new(inner),
+            owner_error: None,
+            pixel_format_error: None,
+        }
     }
 }
 
@@ -48,40 +183,265 @@ impl<W: 'static + Clone> IWindowBuilder for WindowBuilder<W> {
     type Client = Client<W>;
 
     fn build(&self, id: W) -> Result<Window<W>> {
+        // `build` may be called more than once, so stored errors are copied rather than taken.
+        if let Some(err) = self.owner_error.as_ref().or(self.pixel_format_error.as_ref()) {
+            let detail = err.detail().unwrap_or_default().to_owned();
+            return Err(Error::from(err.kind()).with_detail_string(detail));
+        }
         self.inner.build(id)
     }
+
+    fn with_aspect_ratio(&mut self, ratio: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.inner.with_aspect_ratio(ratio);
+        self
+    }
+
+    fn with_close_policy(&mut self, policy: ClosePolicy) -> &mut WindowBuilder<W> {
+        self.inner.with_close_policy(policy);
+        self
+    }
+
+    fn with_max_size(&mut self, size: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.inner.with_max_size(size);
+        self
+    }
+
+    fn with_min_size(&mut self, size: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.inner.with_min_size(size);
+        self
+    }
+
+    fn with_owner(&mut self, owner: &Window<W>, modal: bool) -> &mut WindowBuilder<W> {
+        self.owner_error = self.inner.with_owner(owner, modal).err();
+        self
+    }
+
+    fn with_pixel_format(&mut self, pixel_format: &PixelFormat) -> &mut WindowBuilder<W> {
+        self.pixel_format_error = self.inner.with_pixel_format(pixel_format).err();
+        self
+    }
+
+    fn with_popup(&mut self, popup: bool) -> &mut WindowBuilder<W> {
+        self.inner.with_popup(popup);
+        self
+    }
+
+    fn with_pos(&mut self, pos: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.inner.with_pos(pos);
+        self
+    }
+
+    fn with_size(&mut self, size: Vec2<Coord>) -> &mut WindowBuilder<W> {
+        self.inner.with_size(size);
+        self
+    }
+
+    fn with_title(&mut self, title: &str) -> &mut WindowBuilder<W> {
+        self.inner.with_title(title);
+        self
+    }
+
+    fn with_transparency(&mut self, transparent: bool) -> &mut WindowBuilder<W> {
+        self.inner.with_transparency(transparent);
+        self
+    }
+
+    fn with_window_type(&mut self, window_type: WindowType) -> &mut WindowBuilder<W> {
+        self.inner.with_window_type(window_type);
+        self
+    }
 }
 
 /// Window interface.
 pub trait IWindow {
     type Client: IClient;
 
+    /// Starts moving the window with the mouse, as if its title bar had been dragged. This lets
+    /// windows with custom decorations be moved by the window system. It should be called while
+    /// the primary mouse button is held.
+    fn begin_move_drag(&self) -> Result<()>;
+
+    /// Starts resizing the window with the mouse from `edge`, as if its frame had been dragged. It
+    /// should be called while the primary mouse button is held.
+    fn begin_resize_drag(&self, edge: ResizeEdge) -> Result<()>;
+
+    /// Asks the window to close as if the user had clicked its close button. The resulting
+    /// [CloseRequest](crate::Event::CloseRequest) event is reported and the window's [ClosePolicy]
+    /// is applied after this returns.
+    fn close(&self) -> Result<()>;
+
+    /// Confines the mouse cursor to the window's client area while the window has focus.
+    fn confine_cursor(&self, confine: bool) -> Result<()>;
+
     /// Destroys the window.
     fn destroy(&self);
 
+    /// Returns the number of pixels per logical inch used when rendering the window.
+    fn dpi(&self) -> u32;
+
+    /// Returns a handle which can control the window from other threads. Operations on the handle
+    /// are applied by `main_loop` while it's running. Fails if the window has been destroyed.
+    fn handle(&self, main_loop: &MainLoop) -> Result<WindowHandle>;
+
     /// Returns the window ID which is used when reporting events.
     fn id(&self) -> &<Self::Client as IClient>::WindowId;
 
     /// Returns true if the window is visible.
     fn is_visible(&self) -> bool;
 
+    /// Returns the position of the window frame in screen coordinates.
+    fn pos(&self) -> Result<Vec2<Coord>>;
+
+    /// Asks the window system to draw the user's attention to the window. Equivalent to
+    /// `request_user_attention(Some(AttentionKind::Informational))`.
+    fn request_attention(&self) -> Result<()> {
+        self.request_user_attention(Some(AttentionKind::Informational))
+    }
+
+    /// Asks the window system to draw the user's attention to the window, typically by highlighting
+    /// its taskbar entry until the window is activated. Passing `None` cancels a previous request.
+    fn request_user_attention(&self, kind: Option<AttentionKind>) -> Result<()>;
+
+    /// Asks the window system to send a [RedrawRequested](crate::Event::RedrawRequested) event
+    /// for the window.
+    fn request_redraw(&self) -> Result<()>;
+
+    /// Returns the ratio of the window's DPI to the standard 96 DPI.
+    fn scale_factor(&self) -> f32 {
+        self.dpi() as f32 / 96.0
+    }
+
+    /// Constrains the client area to the aspect ratio `ratio.x : ratio.y`, or removes the
+    /// constraint if `ratio` is `None`.
+    fn set_aspect_ratio(&self, ratio: Option<Vec2<Coord>>) -> Result<()>;
+
+    /// Sets the appearance of the mouse cursor while it is over the window.
+    fn set_cursor(&self, cursor: &Cursor) -> Result<()>;
+
+    /// Moves the mouse cursor to `pos`, relative to the top-left corner of the client area.
+    fn set_cursor_pos(&self, pos: Vec2<Coord>) -> Result<()>;
+
+    /// Shows or hides the mouse cursor while it is over the window.
+    fn set_cursor_visible(&self, visible: bool) -> Result<()>;
+
+    /// Makes the window fullscreen on the specified monitor, or restores it if `monitor` is
+    /// `None`.
+    fn set_fullscreen(&self, monitor: Option<&Monitor>) -> Result<()>;
+
+    /// Moves the input method's composition window to a position in client coordinates, which
+    /// should normally be the location of the text cursor.
+    ///
+    /// On X11, input methods are only available when the `x11-sys` feature is enabled and the
+    /// application has set a UTF-8 locale with `setlocale(LC_ALL, "")` or similar. Otherwise this
+    /// does nothing.
+    fn set_ime_position(&self, pos: Vec2<Coord>) -> Result<()>;
+
+    /// Sets or removes the maximum size of the client area.
+    fn set_max_size(&self, size: Option<Vec2<Coord>>) -> Result<()>;
+
+    /// Sets or removes the minimum size of the client area.
+    fn set_min_size(&self, size: Option<Vec2<Coord>>) -> Result<()>;
+
+    /// Sets the opacity of the whole window, from 0.0 (fully transparent) to 1.0 (opaque). Values
+    /// outside of that range are clamped.
+    fn set_opacity(&self, opacity: f32) -> Result<()>;
+
+    /// Locks or unlocks the pointer. While locked, the cursor is hidden and confined to the
+    /// window, and mouse motion is reported as [MouseMotionRaw](crate::Event::MouseMotionRaw)
+    /// events.
+    fn set_pointer_locked(&self, locked: bool) -> Result<()>;
+
+    /// Moves the window frame to a position in screen coordinates.
+    fn set_pos(&self, pos: Vec2<Coord>) -> Result<()>;
+
+    /// Shows a progress indicator from 0.0 to 1.0 on the window's taskbar entry, or hides it if
+    /// `progress` is `None`. Values outside of that range are clamped.
+    fn set_progress(&self, progress: Option<f32>) -> Result<()>;
+
+    /// Resizes the client area.
+    fn set_size(&self, size: Vec2<Coord>) -> Result<()>;
+
+    /// Sets the theme of the window's decorations, such as its title bar, or makes them follow
+    /// the system's preferred theme if `theme` is `None`.
+    fn set_theme(&self, theme: Option<Theme>) -> Result<()>;
+
+    /// Changes the window title.
+    fn set_title(&self, title: &str) -> Result<()>;
+
     /// Shows or hides the window.
     fn set_visible(&self, visible: bool) -> Result<()>;
+
+    /// Returns the size of the client area.
+    fn size(&self) -> Result<Vec2<Coord>>;
 }
 
 /// Internal interface for [Window].
 trait IWindowObject<W: 'static + Clone>: 'static {
+    fn as_any(&self) -> &dyn Any;
+    fn begin_move_drag(&self) -> Result<()>;
+    fn begin_resize_drag(&self, edge: ResizeEdge) -> Result<()>;
+    fn close(&self) -> Result<()>;
+    fn confine_cursor(&self, confine: bool) -> Result<()>;
     fn destroy(&self);
+    fn dpi(&self) -> u32;
+    fn handle(&self, main_loop: &MainLoop) -> Result<WindowHandle>;
     fn id(&self) -> &W;
     fn is_visible(&self) -> bool;
+    fn pos(&self) -> Result<Vec2<Coord>>;
+    fn request_user_attention(&self, kind: Option<AttentionKind>) -> Result<()>;
+    fn request_redraw(&self) -> Result<()>;
+    fn set_aspect_ratio(&self, ratio: Option<Vec2<Coord>>) -> Result<()>;
+    fn set_cursor(&self, cursor: &Cursor) -> Result<()>;
+    fn set_cursor_pos(&self, pos: Vec2<Coord>) -> Result<()>;
+    fn set_cursor_visible(&self, visible: bool) -> Result<()>;
+    fn set_fullscreen(&self, monitor: Option<&Monitor>) -> Result<()>;
+    fn set_ime_position(&self, pos: Vec2<Coord>) -> Result<()>;
+    fn set_max_size(&self, size: Option<Vec2<Coord>>) -> Result<()>;
+    fn set_min_size(&self, size: Option<Vec2<Coord>>) -> Result<()>;
+    fn set_opacity(&self, opacity: f32) -> Result<()>;
+    fn set_pointer_locked(&self, locked: bool) -> Result<()>;
+    fn set_pos(&self, pos: Vec2<Coord>) -> Result<()>;
+    fn set_progress(&self, progress: Option<f32>) -> Result<()>;
+    fn set_size(&self, size: Vec2<Coord>) -> Result<()>;
+    fn set_theme(&self, theme: Option<Theme>) -> Result<()>;
+    fn set_title(&self, title: &str) -> Result<()>;
     fn set_visible(&self, visible: bool) -> Result<()>;
+    fn size(&self) -> Result<Vec2<Coord>>;
 }
 
 impl<T: 'static + IWindow> IWindowObject<<T::Client as IClient>::WindowId> for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn begin_move_drag(&self) -> Result<()> {
+        <T as IWindow>::begin_move_drag(self)
+    }
+
+    fn begin_resize_drag(&self, edge: ResizeEdge) -> Result<()> {
+        <T as IWindow>::begin_resize_drag(self, edge)
+    }
+
+    fn close(&self) -> Result<()> {
+        <T as IWindow>::close(self)
+    }
+
+    fn confine_cursor(&self, confine: bool) -> Result<()> {
+        <T as IWindow>::confine_cursor(self, confine)
+    }
+
     fn destroy(&self) {
         <T as IWindow>::destroy(self)
     }
 
+    fn dpi(&self) -> u32 {
+        <T as IWindow>::dpi(self)
+    }
+
+    fn handle(&self, main_loop: &MainLoop) -> Result<WindowHandle> {
+        <T as IWindow>::handle(self, main_loop)
+    }
+
     fn id(&self) -> &<T::Client as IClient>::WindowId {
         <T as IWindow>::id(self)
     }
@@ -90,9 +450,85 @@ impl<T: 'static + IWindow> IWindowObject<<T::Client as IClient>::WindowId> for T
         <T as IWindow>::is_visible(self)
     }
 
+    fn pos(&self) -> Result<Vec2<Coord>> {
+        <T as IWindow>::pos(self)
+    }
+
+    fn request_user_attention(&self, kind: Option<AttentionKind>) -> Result<()> {
+        <T as IWindow>::request_user_attention(self, kind)
+    }
+
+    fn request_redraw(&self) -> Result<()> {
+        <T as IWindow>::request_redraw(self)
+    }
+
+    fn set_aspect_ratio(&self, ratio: Option<Vec2<Coord>>) -> Result<()> {
+        <T as IWindow>::set_aspect_ratio(self, ratio)
+    }
+
+    fn set_cursor(&self, cursor: &Cursor) -> Result<()> {
+        <T as IWindow>::set_cursor(self, cursor)
+    }
+
+    fn set_cursor_pos(&self, pos: Vec2<Coord>) -> Result<()> {
+        <T as IWindow>::set_cursor_pos(self, pos)
+    }
+
+    fn set_cursor_visible(&self, visible: bool) -> Result<()> {
+        <T as IWindow>::set_cursor_visible(self, visible)
+    }
+
+    fn set_fullscreen(&self, monitor: Option<&Monitor>) -> Result<()> {
+        <T as IWindow>::set_fullscreen(self, monitor)
+    }
+
+    fn set_ime_position(&self, pos: Vec2<Coord>) -> Result<()> {
+        <T as IWindow>::set_ime_position(self, pos)
+    }
+
+    fn set_max_size(&self, size: Option<Vec2<Coord>>) -> Result<()> {
+        <T as IWindow>::set_max_size(self, size)
+    }
+
+    fn set_min_size(&self, size: Option<Vec2<Coord>>) -> Result<()> {
+        <T as IWindow>::set_min_size(self, size)
+    }
+
+    fn set_opacity(&self, opacity: f32) -> Result<()> {
+        <T as IWindow>::set_opacity(self, opacity)
+    }
+
+    fn set_pointer_locked(&self, locked: bool) -> Result<()> {
+        <T as IWindow>::set_pointer_locked(self, locked)
+    }
+
+    fn set_pos(&self, pos: Vec2<Coord>) -> Result<()> {
+        <T as IWindow>::set_pos(self, pos)
+    }
+
+    fn set_progress(&self, progress: Option<f32>) -> Result<()> {
+        <T as IWindow>::set_progress(self, progress)
+    }
+
+    fn set_size(&self, size: Vec2<Coord>) -> Result<()> {
+        <T as IWindow>::set_size(self, size)
+    }
+
+    fn set_theme(&self, theme: Option<Theme>) -> Result<()> {
+        <T as IWindow>::set_theme(self, theme)
+    }
+
+    fn set_title(&self, title: &str) -> Result<()> {
+        <T as IWindow>::set_title(self, title)
+    }
+
     fn set_visible(&self, visible: bool) -> Result<()> {
         <T as IWindow>::set_visible(self, visible)
     }
+
+    fn size(&self) -> Result<Vec2<Coord>> {
+        <T as IWindow>::size(self)
+    }
 }
 
 /// Boxed window type.
@@ -101,7 +537,12 @@ pub struct Window<W: 'static + Clone> {
 }
 
 impl<W: 'static + Clone> Window<W> {
-    fn new<T: 'static + IWindow>(inner: T) -> Window<W>
+    /// Returns the driver window if it has type `T`.
+    pub(crate) fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.inner.as_any().downcast_ref()
+    }
+
+    pub(crate) fn new<T: 'static + IWindow>(inner: T) -> Window<W>
     where T::Client: IClient<WindowId = W>
     {
         Window { inner: Box::new(inner) }
@@ -111,10 +552,34 @@ impl<W: 'static + Clone> Window<W> {
 impl<W: 'static + Clone> IWindow for Window<W> {
     type Client = Client<W>;
 
+    fn begin_move_drag(&self) -> Result<()> {
+        self.inner.begin_move_drag()
+    }
+
+    fn begin_resize_drag(&self, edge: ResizeEdge) -> Result<()> {
+        self.inner.begin_resize_drag(edge)
+    }
+
+    fn close(&self) -> Result<()> {
+        self.inner.close()
+    }
+
+    fn confine_cursor(&self, confine: bool) -> Result<()> {
+        self.inner.confine_cursor(confine)
+    }
+
     fn destroy(&self) {
         self.inner.destroy()
     }
 
+    fn dpi(&self) -> u32 {
+        self.inner.dpi()
+    }
+
+    fn handle(&self, main_loop: &MainLoop) -> Result<WindowHandle> {
+        self.inner.handle(main_loop)
+    }
+
     fn id(&self) -> &W {
         self.inner.id()
     }
@@ -123,7 +588,205 @@ impl<W: 'static + Clone> IWindow for Window<W> {
         self.inner.is_visible()
     }
 
+    fn pos(&self) -> Result<Vec2<Coord>> {
+        self.inner.pos()
+    }
+
+    fn request_user_attention(&self, kind: Option<AttentionKind>) -> Result<()> {
+        self.inner.request_user_attention(kind)
+    }
+
+    fn request_redraw(&self) -> Result<()> {
+        self.inner.request_redraw()
+    }
+
+    fn set_aspect_ratio(&self, ratio: Option<Vec2<Coord>>) -> Result<()> {
+        self.inner.set_aspect_ratio(ratio)
+    }
+
+    fn set_cursor(&self, cursor: &Cursor) -> Result<()> {
+        self.inner.set_cursor(cursor)
+    }
+
+    fn set_cursor_pos(&self, pos: Vec2<Coord>) -> Result<()> {
+        self.inner.set_cursor_pos(pos)
+    }
+
+    fn set_cursor_visible(&self, visible: bool) -> Result<()> {
+        self.inner.set_cursor_visible(visible)
+    }
+
+    fn set_fullscreen(&self, monitor: Option<&Monitor>) -> Result<()> {
+        self.inner.set_fullscreen(monitor)
+    }
+
+    fn set_ime_position(&self, pos: Vec2<Coord>) -> Result<()> {
+        self.inner.set_ime_position(pos)
+    }
+
+    fn set_max_size(&self, size: Option<Vec2<Coord>>) -> Result<()> {
+        self.inner.set_max_size(size)
+    }
+
+    fn set_min_size(&self, size: Option<Vec2<Coord>>) -> Result<()> {
+        self.inner.set_min_size(size)
+    }
+
+    fn set_opacity(&self, opacity: f32) -> Result<()> {
+        self.inner.set_opacity(opacity)
+    }
+
+    fn set_pointer_locked(&self, locked: bool) -> Result<()> {
+        self.inner.set_pointer_locked(locked)
+    }
+
+    fn set_pos(&self, pos: Vec2<Coord>) -> Result<()> {
+        self.inner.set_pos(pos)
+    }
+
+    fn set_progress(&self, progress: Option<f32>) -> Result<()> {
+        self.inner.set_progress(progress)
+    }
+
+    fn set_size(&self, size: Vec2<Coord>) -> Result<()> {
+        self.inner.set_size(size)
+    }
+
+    fn set_theme(&self, theme: Option<Theme>) -> Result<()> {
+        self.inner.set_theme(theme)
+    }
+
+    fn set_title(&self, title: &str) -> Result<()> {
+        self.inner.set_title(title)
+    }
+
     fn set_visible(&self, visible: bool) -> Result<()> {
         self.inner.set_visible(visible)
     }
+
+    fn size(&self) -> Result<Vec2<Coord>> {
+        self.inner.size()
+    }
+}
+
+/// Urgency of a request for the user's attention.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AttentionKind {
+    /// Requests immediate attention, such as for an incoming call. The window is highlighted more
+    /// prominently where the window system supports it.
+    Critical,
+    /// Notifies the user of something which doesn't need immediate attention, such as a completed
+    /// task.
+    Informational,
 }
+
+/// Determines what happens when the user asks to close a window.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ClosePolicy {
+    /// Only reports a [CloseRequest](crate::Event::CloseRequest) event. The application decides
+    /// whether to destroy the window.
+    Defer,
+    /// Reports a [CloseRequest](crate::Event::CloseRequest) event, then destroys the window.
+    Destroy,
+}
+
+/// Edge or corner of a window to resize from.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ResizeEdge {
+    Bottom,
+    BottomLeft,
+    BottomRight,
+    Left,
+    Right,
+    Top,
+    TopLeft,
+    TopRight,
+}
+
+/// Light or dark appearance of windows and their decorations.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+/// Operation sent to a window through a [WindowHandle].
+pub(crate) enum WindowCommand {
+    RequestRedraw,
+    SetTitle(String),
+    SetVisible(bool),
+}
+
+impl WindowCommand {
+    /// Applies the command to a window.
+    pub(crate) fn apply<T: IWindow>(self, window: &T) -> Result<()> {
+        match self {
+            WindowCommand::RequestRedraw => window.request_redraw(),
+            WindowCommand::SetTitle(title) => window.set_title(title.as_str()),
+            WindowCommand::SetVisible(visible) => window.set_visible(visible),
+        }
+    }
+}
+
+/// Handle for controlling a window from any thread. Operations are queued and applied by the
+/// main loop which created the handle, so they're ignored if the window is destroyed first. See
+/// [handle](IWindow::handle).
+#[derive(Clone)]
+pub struct WindowHandle {
+    proxy: MainLoopProxy,
+    target: u64,
+}
+
+impl WindowHandle {
+    /// Requests a redraw of the window. See [request_redraw](IWindow::request_redraw).
+    pub fn request_redraw(&self) -> Result<()> {
+        self.send(WindowCommand::RequestRedraw)
+    }
+
+    /// Changes the window title. See [set_title](IWindow::set_title).
+    pub fn set_title(&self, title: &str) -> Result<()> {
+        self.send(WindowCommand::SetTitle(title.to_string()))
+    }
+
+    /// Shows or hides the window. See [set_visible](IWindow::set_visible).
+    pub fn set_visible(&self, visible: bool) -> Result<()> {
+        self.send(WindowCommand::SetVisible(visible))
+    }
+}
+
+impl WindowHandle {
+    pub(crate) fn new(proxy: MainLoopProxy, target: u64) -> WindowHandle {
+        WindowHandle { proxy, target }
+    }
+
+    /// Allocates a target ID for a new window's handles. Drivers store it with the window and
+    /// look windows up by it when applying commands.
+    pub(crate) fn next_target() -> u64 {
+        NEXT_HANDLE_TARGET.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl WindowHandle {
+    fn send(&self, command: WindowCommand) -> Result<()> {
+        self.proxy.send_window_command(self.target, command)
+    }
+}
+
+/// Purpose of a window, used by the window manager to choose decorations and placement.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum WindowType {
+    /// Dialog box, usually owned by another window.
+    Dialog,
+    /// Torn-off menu, which stays open like a palette.
+    Menu,
+    Normal,
+    /// Splash screen shown while an application starts.
+    Splash,
+    Tooltip,
+    /// Small persistent window such as a palette or toolbox.
+    Utility,
+}
+
+/// Next window handle target ID. IDs are never reused, so a handle whose window was destroyed
+/// can't control a window created later, even if the window system reuses the native ID.
+static NEXT_HANDLE_TARGET: AtomicU64 = AtomicU64::new(1);